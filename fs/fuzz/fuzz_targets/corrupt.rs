@@ -0,0 +1,21 @@
+//! Corrupt some bytes of a valid image, and read everything in it.
+//!
+//! Random images rarely get past the super block, so this target starts from a
+//! populated file system and only patches it. The input is a list of 3-byte
+//! patches: the position (LE u16, which covers the whole image) and the new value.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proka_fs_fuzz::{exercise, populated_image};
+use std::sync::OnceLock;
+
+static IMAGE: OnceLock<Vec<u8>> = OnceLock::new();
+
+fuzz_target!(|data: &[u8]| {
+    let mut image = IMAGE.get_or_init(populated_image).clone();
+    for patch in data.chunks_exact(3) {
+        let pos = u16::from_le_bytes([patch[0], patch[1]]) as usize;
+        image[pos] = patch[2];
+    }
+    exercise(image);
+});
@@ -0,0 +1,9 @@
+//! Mount an arbitrary image, and read everything in it.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proka_fs_fuzz::exercise;
+
+fuzz_target!(|data: &[u8]| {
+    exercise(data.to_vec());
+});
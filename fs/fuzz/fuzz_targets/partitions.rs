@@ -0,0 +1,15 @@
+//! Read the partition table of an arbitrary disk.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proka_fs::partition::read_partitions;
+use proka_fs::ram::RamBlockDevice;
+
+fuzz_target!(|data: &[u8]| {
+    let mut bd = RamBlockDevice::new(data.to_vec());
+    if let Ok(partitions) = read_partitions(&mut bd) {
+        for partition in partitions {
+            let _ = partition.open(&mut bd);
+        }
+    }
+});
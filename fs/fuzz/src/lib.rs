@@ -0,0 +1,90 @@
+//! The helpers shared by the fuzz targets.
+//!
+//! Every target feeds untrusted bytes to the parsing layer, which must return
+//! errors instead of panicking or reading invalid values.
+
+use proka_fs::FileSystem;
+use proka_fs::definition::FileType;
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+
+/// The size of the populated image patched by the `corrupt` target.
+pub const IMAGE_SIZE: usize = 64 * 1024;
+
+/// The max number of files visited in an image, whose tree may have cycles.
+const MAX_FILES: usize = 256;
+
+/// The max number of bytes read from each file.
+const MAX_READ: usize = 64 * 1024;
+
+/// Create a small formatted image holding a few files, directories and xattrs.
+pub fn populated_image() -> Vec<u8> {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        // A fixed UUID, as there is no random source without `std`.
+        &FormatOptions {
+            uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+            ..FormatOptions::default()
+        },
+    )
+    .unwrap();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    fs.mkfile(dir, "file").unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    // Large enough to need the indirect block.
+    fs.write_at(file, 0, &[0x5A; 14 * 1024]).unwrap();
+    fs.setxattr(file, "user.comment", &[0xA5; 200]).unwrap();
+    fs.mkfifo(0, "fifo").unwrap();
+    fs.unmount().unwrap();
+    image
+}
+
+/// Mount an image, then check it and read everything reachable from the root.
+///
+/// Every call may fail, but none may panic.
+pub fn exercise(image: Vec<u8>) {
+    let Ok(mut fs) = FileSystem::mount(RamBlockDevice::new(image)) else {
+        return;
+    };
+    let _ = fs.statfs();
+    let _ = fs.check();
+
+    let mut pending = vec![(String::new(), 0u32)];
+    let mut visited = 0;
+    let mut buf = vec![0u8; MAX_READ];
+    while let Some((path, inode_id)) = pending.pop() {
+        visited += 1;
+        if visited > MAX_FILES {
+            break;
+        }
+        let Ok(metadata) = fs.stat(inode_id) else {
+            continue;
+        };
+        let _ = fs.listxattr(inode_id);
+        let _ = fs.getxattr(inode_id, "user.comment");
+        if metadata.file_type != FileType::Directory {
+            let _ = fs.read_at(inode_id, 0, &mut buf);
+            continue;
+        }
+
+        let Ok(entries) = fs.ls(inode_id) else {
+            continue;
+        };
+        for entry in entries {
+            let Ok(name) = core::str::from_utf8(entry.name_bytes()) else {
+                continue;
+            };
+            if name == "." || name == ".." {
+                continue;
+            }
+            let path = format!("{path}/{name}");
+            let _ = fs.lookup(&path);
+            pending.push((path, entry.inode));
+        }
+    }
+}
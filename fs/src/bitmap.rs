@@ -1,6 +1,6 @@
 //! The bitmap which describes is the block bitmap and inode bitmap used.
 
-use crate::{GenericFsData, Vec};
+use crate::Vec;
 
 pub trait Bitmap {
     /// Set up a bit's status
@@ -99,9 +99,7 @@ impl Bitmap for Vec<u8> {
     }
 
     fn clear(&mut self) {
-        for i in 0..self.len() {
-            self[i] = 0;
-        }
+        self.fill(0);
     }
 }
 
@@ -134,34 +132,28 @@ impl Bitmap for BlockBitmap<'_> {
     }
 }
 
-// This struct will written into the disk as well, so it's a generic fs type.
-// That's why it implements the GenericFsData trait.
-impl GenericFsData for BlockBitmap<'_> {
-    fn as_bytes(&self) -> &[u8] {
-        self.0
+// This struct is written into the disk as well, but it borrows its bytes
+// instead of being read out of them, so it can't be a `GenericFsData`.
+impl<'a> BlockBitmap<'a> {
+    pub fn new(bytes: &'a mut [u8]) -> Self {
+        Self(bytes)
     }
 
-    fn as_mut_bytes(&mut self) -> &mut [u8] {
+    /// Get the bitmap as a byte slice.
+    ///
+    /// # Returns
+    ///
+    /// * `&[u8]` - The bitmap as a byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
         self.0
     }
 
-    fn from_bytes(bytes: &[u8]) -> Option<&Self>
-    where
-        Self: Sized,
-    {
-        Some(unsafe { &*(bytes.as_ptr() as *const Self) })
-    }
-
-    fn from_mut_bytes(bytes: &mut [u8]) -> Option<&mut Self>
-    where
-        Self: Sized,
-    {
-        Some(unsafe { &mut *(bytes.as_mut_ptr() as *mut Self) })
+    /// Get the bitmap as a mutable byte slice.
+    ///
+    /// # Returns
+    ///
+    /// * `&mut [u8]` - The bitmap as a mutable byte slice.
+    pub fn as_mut_bytes(&mut self) -> &mut [u8] {
+        self.0
     }
 }
-
-impl<'a> BlockBitmap<'a> {
-    pub fn new(bytes: &'a mut [u8]) -> Self {
-        Self(bytes)
-    }
-}
\ No newline at end of file
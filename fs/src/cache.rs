@@ -0,0 +1,309 @@
+//! The block cache, which keeps recently used blocks in memory.
+//!
+//! [`CachedBlockDevice`] wraps any [`BlockDevice`] and works in `no_std`. It's a
+//! write-back cache: writes only update the cached copy, so several partial writes
+//! to the same block reach the device as a single block write. Dirty blocks are
+//! written back when they are evicted (least recently used first) or when the cache
+//! is flushed, which [`FileSystem::sync`](crate::FileSystem::sync) does.
+//!
+//! The dirty blocks are written back in the order they were changed, so the
+//! device only ever holds what it would have held at some earlier moment without
+//! the cache, and the write ordering the file system relies on to survive a crash
+//! (see [`crate::checksum`]) still holds. A block changed again after other blocks
+//! is written back first, with the blocks changed before it, and so are all the
+//! dirty blocks before a discard.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use proka_fs::{FileSystem, init_block_device};
+//! use proka_fs::cache::CachedBlockDevice;
+//!
+//! let bd = init_block_device("disk.img").unwrap();
+//!
+//! // Keep up to 64 blocks in memory.
+//! let mut fs = FileSystem::mount(CachedBlockDevice::new(bd, 64)).unwrap();
+//! fs.mkfile(0, "hello.txt").unwrap();
+//! fs.sync().unwrap();
+//! ```
+
+use crate::options::DEFAULT_CACHE_BLOCKS;
+use crate::{BlockDevice, FileSystem, MountOptions};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// A block kept in the cache.
+struct CacheEntry {
+    /// The content of the block.
+    data: Vec<u8>,
+
+    /// When this entry was used for the last time, its key in `CachedBlockDevice::lru`.
+    last_used: u64,
+
+    /// When this entry was changed first since it was written back, its key in
+    /// `CachedBlockDevice::dirty`, or `None` if it's the same as on the block device.
+    dirty_since: Option<u64>,
+}
+
+/// A write-back LRU block cache wrapping a block device.
+pub struct CachedBlockDevice<B: BlockDevice> {
+    /// The wrapped block device.
+    inner: B,
+
+    /// The max number of blocks in the cache.
+    capacity: usize,
+
+    /// The block size of the wrapped block device.
+    block_size: usize,
+
+    /// The cached blocks, by block number.
+    entries: BTreeMap<u32, CacheEntry>,
+
+    /// The cached blocks, from the least recently used.
+    lru: BTreeMap<u64, u32>,
+
+    /// The dirty blocks, in the order they were changed.
+    dirty: BTreeMap<u64, u32>,
+
+    /// The counter giving the order of the uses and the changes.
+    tick: u64,
+}
+
+impl<B: BlockDevice> CachedBlockDevice<B> {
+    /// Create a block cache.
+    ///
+    /// # Parameters
+    ///
+    /// * `inner` - The block device to wrap.
+    /// * `capacity` - The max number of blocks kept in memory (at least 1).
+    pub fn new(inner: B, capacity: usize) -> Self {
+        let block_size = inner.block_size() as usize;
+        Self {
+            inner,
+            capacity: capacity.max(1),
+            block_size,
+            entries: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            dirty: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Get the wrapped block device.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Get the wrapped block device mutably.
+    ///
+    /// # Note
+    ///
+    /// Writing to it directly bypasses the cache, you should flush the cache before.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Get the number of blocks in the cache.
+    pub fn cached_blocks(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Get the number of blocks in the cache which aren't written back yet.
+    pub fn dirty_blocks(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// Write the dirty blocks back to the block device, in the order they were
+    /// changed, up to the one changed at `until`.
+    fn write_back(&mut self, until: u64) -> Result<(), &'static str> {
+        while let Some((&since, &block_num)) = self.dirty.first_key_value() {
+            if since > until {
+                break;
+            }
+            let entry = self
+                .entries
+                .get_mut(&block_num)
+                .ok_or("Cache entry not found")?;
+            self.inner.write_block(block_num, 0, &entry.data)?;
+            entry.dirty_since = None;
+            self.dirty.remove(&since);
+        }
+        Ok(())
+    }
+
+    /// Make sure a block is in the cache, loading it if it isn't cached.
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The block number.
+    /// * `load` - Whether to read the block from the device on a miss. It's not
+    ///   needed when the whole block is going to be overwritten.
+    fn cache_block(&mut self, block_num: u32, load: bool) -> Result<(), &'static str> {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(&block_num) {
+            self.lru.remove(&entry.last_used);
+            self.lru.insert(self.tick, block_num);
+            entry.last_used = self.tick;
+            return Ok(());
+        }
+
+        if self.entries.len() >= self.capacity {
+            // Evict the least recently used entry, once it's written back.
+            if let Some((_, victim)) = self.lru.pop_first() {
+                if let Some(since) = self.entries[&victim].dirty_since {
+                    self.write_back(since)?;
+                }
+                self.entries.remove(&victim);
+            }
+        }
+
+        let mut data = alloc::vec![0u8; self.block_size];
+        if load {
+            self.inner.read_block(block_num, 0, &mut data)?;
+        }
+        self.lru.insert(self.tick, block_num);
+        self.entries.insert(
+            block_num,
+            CacheEntry {
+                data,
+                last_used: self.tick,
+                dirty_since: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Mark a cached block as changed, before it's changed.
+    ///
+    /// A block changed again after other blocks is written back first, so the
+    /// blocks still reach the device in the order they were changed.
+    fn mark_dirty(&mut self, block_num: u32) -> Result<(), &'static str> {
+        let since = self.entries[&block_num].dirty_since;
+        if let Some(since) = since {
+            if self.dirty.last_key_value().map(|(&last, _)| last) == Some(since) {
+                // The last changed block, the changes can be merged.
+                return Ok(());
+            }
+            self.write_back(since)?;
+        }
+        self.tick += 1;
+        self.dirty.insert(self.tick, block_num);
+        self.entries
+            .get_mut(&block_num)
+            .ok_or("Cache entry not found")?
+            .dirty_since = Some(self.tick);
+        Ok(())
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for CachedBlockDevice<B> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        // The buffer may cross several blocks.
+        let block_size = self.block_size;
+        let mut pos = offset as usize;
+        let mut done = 0;
+        while done < buf.len() {
+            let block = block_num + (pos / block_size) as u32;
+            let in_block = pos % block_size;
+            let len = (block_size - in_block).min(buf.len() - done);
+
+            self.cache_block(block, true)?;
+            let data = &self.entries[&block].data;
+            buf[done..done + len].copy_from_slice(&data[in_block..in_block + len]);
+            pos += len;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let block_size = self.block_size;
+        let mut pos = offset as usize;
+        let mut done = 0;
+        while done < buf.len() {
+            let block = block_num + (pos / block_size) as u32;
+            let in_block = pos % block_size;
+            let len = (block_size - in_block).min(buf.len() - done);
+
+            self.cache_block(block, len < block_size)?;
+            self.mark_dirty(block)?;
+            let entry = self
+                .entries
+                .get_mut(&block)
+                .ok_or("Cache entry not found")?;
+            entry.data[in_block..in_block + len].copy_from_slice(&buf[done..done + len]);
+            pos += len;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.write_back(u64::MAX)?;
+        self.inner.flush()
+    }
+
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        // The discarded content is undefined, so its dirty data can be dropped.
+        let discarded: Vec<u32> = self
+            .entries
+            .range(blocks.clone())
+            .map(|(&block_num, _)| block_num)
+            .collect();
+        for block_num in discarded {
+            if let Some(entry) = self.entries.remove(&block_num) {
+                self.lru.remove(&entry.last_used);
+                if let Some(since) = entry.dirty_since {
+                    self.dirty.remove(&since);
+                }
+            }
+        }
+        // The changes which freed the blocks reach the device before the discard,
+        // so they are never used by a file after a crash.
+        self.write_back(u64::MAX)?;
+        self.inner.discard(blocks)
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        self.inner.block_count()
+    }
+
+    fn block_size(&self) -> u32 {
+        self.inner.block_size()
+    }
+}
+
+impl<B: BlockDevice> Drop for CachedBlockDevice<B> {
+    fn drop(&mut self) {
+        // Nobody can handle the error here, call `flush` before to catch it.
+        let _ = self.flush();
+    }
+}
+
+impl<B: BlockDevice> FileSystem<CachedBlockDevice<B>> {
+    /// Mount the file system behind a block cache of [`MountOptions::cache_blocks`] blocks,
+    /// or [`DEFAULT_CACHE_BLOCKS`] if it isn't given.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device driver.
+    /// * `options` - The mount options.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The mounted file system.
+    /// * `Err(&'static str)` - See [`FileSystem::mount_with`].
+    pub fn mount_cached(bd: B, options: &MountOptions) -> Result<Self, &'static str> {
+        let cache_blocks = options.cache_blocks.unwrap_or(DEFAULT_CACHE_BLOCKS);
+        let options = MountOptions {
+            cache_blocks: None,
+            ..*options
+        };
+        Self::mount_with(CachedBlockDevice::new(bd, cache_blocks), &options)
+    }
+}
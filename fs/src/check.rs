@@ -0,0 +1,449 @@
+//! The consistency checker of the file system.
+//!
+//! The problems found are split in two kinds:
+//!
+//! - Errors, which mean the file system is corrupted: a block owned twice, a
+//!   used block marked free, a dir entry pointing to a free inode...
+//! - Warnings, which are harmless leftovers of an interrupted operation: a
+//!   block or an inode which is used but referenced by nobody, or a wrong count.
+//!
+//! The operations of [`FileSystem`] are ordered so that a crash at any point only
+//! leaves warnings behind.
+
+use crate::definition::{DirEntry, FileType, Inode, SuperBlock};
+use crate::{BlockDevice, FileSystem, GenericFsData};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+/// A problem found by the checker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The super block is invalid, nothing else is checked.
+    BadSuperBlock,
+
+    /// A metadata block doesn't match its checksum, so what it holds isn't used.
+    Corrupted { block: u32 },
+
+    /// The root inode is free or not a directory, the tree isn't checked.
+    BadRootInode,
+
+    /// An inode holds invalid values, it's handled as a free one.
+    BadInode { inode: u32 },
+
+    /// A file points to a block outside the data area.
+    BadBlockPointer { inode: u32, block: u32 },
+
+    /// A block is owned by several files, `inode` is the second owner.
+    SharedBlock { inode: u32, block: u32 },
+
+    /// A block owned by a file is marked free in the bitmap.
+    FreeBlockInUse { inode: u32, block: u32 },
+
+    /// A directory has a broken size or broken '.' and '..' entries.
+    BadDirectory { inode: u32 },
+
+    /// A dir entry points to a free inode, or to a directory which is already
+    /// in the tree.
+    BadDirEntry { dir: u32, inode: u32 },
+
+    /// A block is marked used, but nobody owns it.
+    LeakedBlock { block: u32 },
+
+    /// A block reserved by the file system (a backup of the super block or the
+    /// checksum table) is marked free in the bitmap.
+    UnreservedBlock { block: u32 },
+
+    /// A backup of the super block is invalid, or describes another file system
+    /// than the primary one.
+    StaleBackup { block: u32 },
+
+    /// An inode is used, but no dir entry points to it.
+    OrphanInode { inode: u32 },
+
+    /// The link count of an inode differs from the number of dir entries pointing to it.
+    WrongLinkCount {
+        inode: u32,
+        stored: u16,
+        actual: u16,
+    },
+
+    /// The block count of an inode differs from the number of blocks it owns.
+    WrongBlockCount {
+        inode: u32,
+        stored: u32,
+        actual: u32,
+    },
+
+    /// The free block counter of the super block differs from the bitmap.
+    WrongFreeBlocks { stored: u32, actual: u32 },
+
+    /// The free inode counter of the super block differs from the inode table.
+    WrongFreeInodes { stored: u32, actual: u32 },
+}
+
+impl Problem {
+    /// Check if the problem means the file system is corrupted.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            Self::LeakedBlock { .. }
+                | Self::StaleBackup { .. }
+                | Self::OrphanInode { .. }
+                | Self::WrongLinkCount { .. }
+                | Self::WrongBlockCount { .. }
+                | Self::WrongFreeBlocks { .. }
+                | Self::WrongFreeInodes { .. }
+        )
+    }
+}
+
+impl core::fmt::Display for Problem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::BadSuperBlock => write!(f, "invalid super block"),
+            Self::Corrupted { block } => write!(f, "block {block} doesn't match its checksum"),
+            Self::BadRootInode => write!(f, "the root inode is not a directory"),
+            Self::BadInode { inode } => write!(f, "inode {inode} is invalid"),
+            Self::BadBlockPointer { inode, block } => {
+                write!(
+                    f,
+                    "inode {inode} points to block {block} outside the data area"
+                )
+            }
+            Self::SharedBlock { inode, block } => {
+                write!(
+                    f,
+                    "block {block} of inode {inode} is owned by another inode"
+                )
+            }
+            Self::FreeBlockInUse { inode, block } => {
+                write!(f, "block {block} of inode {inode} is marked free")
+            }
+            Self::BadDirectory { inode } => write!(f, "directory {inode} is broken"),
+            Self::BadDirEntry { dir, inode } => {
+                write!(f, "directory {dir} has an invalid entry for inode {inode}")
+            }
+            Self::LeakedBlock { block } => write!(f, "block {block} is used by nobody"),
+            Self::UnreservedBlock { block } => {
+                write!(f, "reserved block {block} is marked free")
+            }
+            Self::StaleBackup { block } => {
+                write!(f, "backup super block {block} is out of date")
+            }
+            Self::OrphanInode { inode } => write!(f, "inode {inode} is in no directory"),
+            Self::WrongLinkCount {
+                inode,
+                stored,
+                actual,
+            } => write!(f, "inode {inode} has {stored} links instead of {actual}"),
+            Self::WrongBlockCount {
+                inode,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "inode {inode} counts {stored} blocks instead of {actual}"
+            ),
+            Self::WrongFreeBlocks { stored, actual } => {
+                write!(f, "{stored} free blocks are counted instead of {actual}")
+            }
+            Self::WrongFreeInodes { stored, actual } => {
+                write!(f, "{stored} free inodes are counted instead of {actual}")
+            }
+        }
+    }
+}
+
+/// The result of a check.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    /// The problems found.
+    pub problems: Vec<Problem>,
+}
+
+impl CheckReport {
+    /// Check if no error was found, warnings are allowed.
+    pub fn is_consistent(&self) -> bool {
+        !self.problems.iter().any(Problem::is_error)
+    }
+
+    /// Check if no problem at all was found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Get the errors found.
+    pub fn errors(&self) -> impl Iterator<Item = &Problem> {
+        self.problems.iter().filter(|problem| problem.is_error())
+    }
+}
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Check the consistency of the file system, without modifying it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(CheckReport)` - The problems found.
+    /// * `Err(&'static str)` - If the block device can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use proka_fs::FileSystem;
+    /// use proka_fs::mkfs::{FormatOptions, format};
+    /// use proka_fs::ram::RamBlockDevice;
+    ///
+    /// let mut bd = RamBlockDevice::new(vec![0u8; 1024 * 1024]);
+    /// # let options = FormatOptions {
+    /// #     uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+    /// #     ..FormatOptions::default()
+    /// # };
+    /// format(&mut bd, 1024 * 1024, &options).unwrap();
+    ///
+    /// let mut fs = FileSystem::mount(bd).unwrap();
+    /// fs.mkdir(0, "boot").unwrap();
+    /// assert!(fs.check().unwrap().is_clean());
+    /// ```
+    pub fn check(&mut self) -> Result<CheckReport, &'static str> {
+        let mut report = CheckReport::default();
+        let super_block = self.super_block;
+        let block_size = super_block.block_size as usize;
+
+        /* Stage 1: Check the super block */
+        let device_blocks = self.block_device.block_count();
+        if super_block.validate().is_err()
+            || block_size != self.block_device.block_size() as usize
+            || device_blocks.is_some_and(|count| count < super_block.total_block)
+        {
+            report.problems.push(Problem::BadSuperBlock);
+            return Ok(report);
+        }
+
+        /* Stage 2: Load the block bitmap */
+        let mut bitmap = alloc::vec![
+            0u8;
+            (super_block.total_block - super_block.bitmap_start_block) as usize * block_size
+        ];
+        self.block_device
+            .read_blocks(super_block.bitmap_start_block, &mut bitmap)?;
+        for (i, data) in bitmap.chunks_exact(block_size).enumerate() {
+            let block = super_block.bitmap_start_block + i as u32;
+            if !self.block_checksum_matches(block, data)? {
+                report.problems.push(Problem::Corrupted { block });
+            }
+        }
+
+        /* Stage 3: Find the owner of every block */
+        let mut inodes = BTreeMap::new();
+        let mut owners = alloc::vec![None; super_block.data_block_count() as usize];
+        // The backups of the super block and the checksum table are owned by no inode.
+        let reserved_blocks: Vec<u32> = super_block
+            .backup_blocks()
+            .into_iter()
+            .chain(super_block.checksum_table_blocks())
+            .collect();
+        for &block in &reserved_blocks {
+            owners[(block - super_block.data_start_block) as usize] = Some(u32::MAX);
+        }
+        let inode_size = Inode::SIZE;
+        let mut buf = alloc::vec![0u8; block_size];
+        for block in 1..super_block.data_start_block {
+            self.block_device.read_block(block, 0, &mut buf)?;
+            for (i, chunk) in buf.chunks_exact(inode_size).enumerate() {
+                let inode_id = ((block - 1) as usize * (block_size / inode_size) + i) as u32;
+                let Some(inode) = Inode::from_bytes(chunk) else {
+                    report.problems.push(Problem::BadInode { inode: inode_id });
+                    continue;
+                };
+                if !inode.is_used {
+                    continue;
+                }
+                if inode.inode_id != inode_id {
+                    report.problems.push(Problem::BadInode { inode: inode_id });
+                    continue;
+                }
+                if self.has_metadata_csum() && !Inode::checksum_matches(chunk) {
+                    if !report.problems.contains(&Problem::Corrupted { block }) {
+                        report.problems.push(Problem::Corrupted { block });
+                    }
+                    continue;
+                }
+
+                let blocks = self.file_blocks(&inode)?;
+                if blocks.len() as u32 != inode.block_count {
+                    report.problems.push(Problem::WrongBlockCount {
+                        inode: inode_id,
+                        stored: inode.block_count,
+                        actual: blocks.len() as u32,
+                    });
+                }
+                let xattr_block = (inode.xattr_block != 0).then_some(inode.xattr_block);
+                for block in blocks.into_iter().chain(xattr_block) {
+                    if !self.is_data_block(block) {
+                        report.problems.push(Problem::BadBlockPointer {
+                            inode: inode_id,
+                            block,
+                        });
+                        continue;
+                    }
+                    let owner = &mut owners[(block - super_block.data_start_block) as usize];
+                    if owner.is_some() {
+                        report.problems.push(Problem::SharedBlock {
+                            inode: inode_id,
+                            block,
+                        });
+                        continue;
+                    }
+                    *owner = Some(inode_id);
+                    if bitmap[block as usize] == 0 {
+                        report.problems.push(Problem::FreeBlockInUse {
+                            inode: inode_id,
+                            block,
+                        });
+                    }
+                }
+                inodes.insert(inode_id, inode);
+            }
+        }
+
+        for (i, owner) in owners.iter().enumerate() {
+            let block = super_block.data_start_block + i as u32;
+            if owner.is_none() && bitmap[block as usize] != 0 {
+                report.problems.push(Problem::LeakedBlock { block });
+            }
+        }
+        for &block in &reserved_blocks {
+            if bitmap[block as usize] == 0 {
+                report.problems.push(Problem::UnreservedBlock { block });
+            }
+        }
+        for block in super_block.backup_blocks() {
+            self.block_device.read_block(block, 0, &mut buf)?;
+            if !SuperBlock::from_bytes(&buf)
+                .is_some_and(|backup| backup.same_file_system(&super_block))
+            {
+                report.problems.push(Problem::StaleBackup { block });
+            }
+        }
+
+        /* Stage 4: Walk the directory tree, counting the links */
+        if inodes
+            .get(&0)
+            .is_none_or(|root| root.file_type != FileType::Directory)
+        {
+            report.problems.push(Problem::BadRootInode);
+            return Ok(report);
+        }
+        let entry_size = DirEntry::SIZE;
+        let mut links: BTreeMap<u32, u16> = BTreeMap::new();
+        let mut visited = BTreeSet::from([0]);
+        let mut pending = alloc::vec![(0u32, 0u32)];
+        while let Some((dir, parent)) = pending.pop() {
+            let dir_inode = inodes[&dir];
+            // Don't read a corrupted directory, it would fail or make the file system read-only.
+            let mut corrupted = false;
+            for index in 0..dir_inode.file_length.div_ceil(block_size as u64) {
+                let block = self.find_block(&dir_inode, index as u32)?;
+                if block == 0 {
+                    continue;
+                }
+                // Already reported as a bad or shared block.
+                if !self.is_file_block(block) {
+                    corrupted = true;
+                    continue;
+                }
+                self.block_device.read_block(block, 0, &mut buf)?;
+                if !self.block_checksum_matches(block, &buf)? {
+                    report.problems.push(Problem::Corrupted { block });
+                    corrupted = true;
+                }
+            }
+            if corrupted {
+                continue;
+            }
+            let Ok((_, entries)) = self.list_dir(dir) else {
+                report.problems.push(Problem::BadDirectory { inode: dir });
+                continue;
+            };
+            let has_entry = |name: &[u8], target: u32| {
+                entries
+                    .iter()
+                    .any(|entry| entry.name_bytes() == name && entry.inode == target)
+            };
+            if dir_inode.file_length % entry_size as u64 != 0
+                || !has_entry(b".", dir)
+                || !has_entry(b"..", parent)
+            {
+                report.problems.push(Problem::BadDirectory { inode: dir });
+            }
+
+            for entry in &entries {
+                let Some(target) = inodes.get(&entry.inode) else {
+                    report.problems.push(Problem::BadDirEntry {
+                        dir,
+                        inode: entry.inode,
+                    });
+                    continue;
+                };
+                let count = links.entry(entry.inode).or_default();
+                *count = count.saturating_add(1);
+
+                let name = entry.name_bytes();
+                if target.file_type == FileType::Directory && name != b"." && name != b".." {
+                    if visited.insert(entry.inode) {
+                        pending.push((entry.inode, dir));
+                    } else {
+                        report.problems.push(Problem::BadDirEntry {
+                            dir,
+                            inode: entry.inode,
+                        });
+                    }
+                }
+            }
+        }
+
+        /* Stage 5: Compare the links with the inodes */
+        // The orphans are in no directory until they're released.
+        let mut orphans = BTreeSet::new();
+        let mut orphan = super_block.orphan_head;
+        while let Some(inode) = inodes.get(&orphan) {
+            if orphan == 0 || !orphans.insert(orphan) {
+                break;
+            }
+            orphan = inode.next_orphan;
+        }
+        for (&inode_id, inode) in &inodes {
+            match links.get(&inode_id) {
+                None if inode.links_count == 0 && orphans.contains(&inode_id) => {}
+                None => report
+                    .problems
+                    .push(Problem::OrphanInode { inode: inode_id }),
+                Some(&actual) if actual != inode.links_count => {
+                    report.problems.push(Problem::WrongLinkCount {
+                        inode: inode_id,
+                        stored: inode.links_count,
+                        actual,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        /* Stage 6: Compare the free counters, which a clean mount trusts */
+        let (free_blocks, free_inodes) = self.count_free()?;
+        if free_blocks != super_block.free_blocks {
+            report.problems.push(Problem::WrongFreeBlocks {
+                stored: super_block.free_blocks,
+                actual: free_blocks,
+            });
+        }
+        if free_inodes != super_block.free_inodes {
+            report.problems.push(Problem::WrongFreeInodes {
+                stored: super_block.free_inodes,
+                actual: free_inodes,
+            });
+        }
+
+        Ok(report)
+    }
+}
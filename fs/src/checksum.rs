@@ -0,0 +1,227 @@
+//! The checksums of the metadata, on file systems with the `metadata_csum` feature.
+//!
+//! The super block and the inodes store their own CRC-32C. The other metadata
+//! blocks are full, so theirs are in the checksum table (see
+//! [`SuperBlock::checksum_table_blocks`]), which holds an entry of
+//! [`CHECKSUM_ENTRY_SIZE`] bytes for every block of the partition:
+//!
+//! | Offset | Field |
+//! |---|---|
+//! | 0 | The checksum of the block |
+//! | 4 | The previous checksum of the block |
+//!
+//! The blocks with an entry are the bitmap blocks, the directory blocks, the
+//! pointer blocks (the indirect blocks, and the double indirect ones with the
+//! blocks they list) and the extended attribute blocks.
+//!
+//! A block is valid if it matches either of them. A change of a block writes its
+//! entry first, then the block in a write smaller than a sector, which can't be
+//! torn. So whenever a crash happens, the block still matches its entry. A block
+//! rewritten as a whole, such as an extended attribute block, is written a sector
+//! at a time for this reason.
+//!
+//! With the `data_csum` feature, the data blocks of the regular files have their
+//! checksums in the table too, but the writes of whole blocks can be torn: such a
+//! block is then reported corrupted, as its content is indeed neither the old nor
+//! the new one. See [`FileSystem::scrub`] to check them all.
+//!
+//! [`SuperBlock::checksum_table_blocks`]: crate::definition::SuperBlock::checksum_table_blocks
+
+use crate::crc32c::{crc32c, crc32c_append};
+use crate::definition::feature;
+use crate::definition::{CHECKSUM_ENTRY_SIZE, FileType, Inode, read_u32, write_u32};
+use crate::{BlockDevice, FileSystem, error};
+
+/// The size of a sector, the unit a write can't be torn inside.
+const SECTOR_SIZE: usize = 512;
+
+/// What to do when the checksum of some metadata doesn't match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumPolicy {
+    /// Fail the operation reading the metadata, see [`FileSystem::corrupted_block`]
+    /// for the block.
+    #[default]
+    Enforce,
+
+    /// Go on with the metadata as it is, but make the file system read-only, so
+    /// nothing is built on top of it. It's meant for checking and recovering files.
+    ReadOnly,
+}
+
+/// Compute the checksum of a block, as stored in the checksum table.
+///
+/// The block number is part of it, so a block written at the wrong place doesn't match.
+///
+/// # Parameters
+///
+/// * `block_num` - The block number.
+/// * `data` - The content of the whole block.
+pub fn block_checksum(block_num: u32, data: &[u8]) -> u32 {
+    crc32c_append(crc32c(&block_num.to_le_bytes()), data)
+}
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Check if the file system has metadata checksums.
+    pub(crate) fn has_metadata_csum(&self) -> bool {
+        self.super_block.features().contains(feature::METADATA_CSUM)
+    }
+
+    /// Check if the data blocks of a file have checksums.
+    ///
+    /// The blocks of the directories are metadata, the others need `data_csum`.
+    pub(crate) fn has_block_checksums(&self, inode: &Inode) -> bool {
+        self.has_metadata_csum()
+            && (inode.file_type == FileType::Directory
+                || self.super_block.features().contains(feature::DATA_CSUM))
+    }
+
+    /// Check a block against the checksum table.
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The block number.
+    /// * `data` - The content of the whole block.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - Whether the block matches, always true without metadata checksums.
+    /// * `Err(&'static str)` - If the checksum table can't be read.
+    pub(crate) fn block_checksum_matches(
+        &mut self,
+        block_num: u32,
+        data: &[u8],
+    ) -> Result<bool, &'static str> {
+        if !self.has_metadata_csum() {
+            return Ok(true);
+        }
+        let (table_block, offset) = self.checksum_entry(block_num)?;
+        let mut entry = [0u8; CHECKSUM_ENTRY_SIZE];
+        self.block_device
+            .read_block(table_block, offset, &mut entry)?;
+        let checksum = block_checksum(block_num, data);
+        Ok(checksum == read_u32(&entry, 0) || checksum == read_u32(&entry, 4))
+    }
+
+    /// Read a whole block with a checksum, checking it.
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The block number.
+    /// * `buf` - The buffer, one block long.
+    pub(crate) fn read_checksummed_block(
+        &mut self,
+        block_num: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        self.block_device.read_block(block_num, 0, buf)?;
+        if !self.block_checksum_matches(block_num, buf)? {
+            self.checksum_error(block_num)?;
+        }
+        Ok(())
+    }
+
+    /// Change a part of a block with a checksum, and its checksum.
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The block number.
+    /// * `offset` - The byte offset of the change in the block.
+    /// * `data` - The new bytes, in a single sector for metadata so the write can't be torn.
+    pub(crate) fn write_checksummed(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        data: &[u8],
+    ) -> Result<(), &'static str> {
+        if self.has_metadata_csum() {
+            let mut buf = alloc::vec![0u8; self.super_block.block_size as usize];
+            self.block_device.read_block(block_num, 0, &mut buf)?;
+            let previous = block_checksum(block_num, &buf);
+            buf[offset as usize..offset as usize + data.len()].copy_from_slice(data);
+
+            let mut entry = [0u8; CHECKSUM_ENTRY_SIZE];
+            write_u32(&mut entry, 0, block_checksum(block_num, &buf));
+            write_u32(&mut entry, 4, previous);
+            let (table_block, table_offset) = self.checksum_entry(block_num)?;
+            self.block_device
+                .write_block(table_block, table_offset, &entry)?;
+        }
+        self.block_device.write_block(block_num, offset, data)
+    }
+
+    /// Rewrite a whole block with a checksum, and its checksum.
+    ///
+    /// The sectors which change are written one at a time, each after its own
+    /// change of the checksum, see [`FileSystem::write_checksummed`].
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The block number.
+    /// * `data` - The new content of the whole block.
+    pub(crate) fn write_checksummed_block(
+        &mut self,
+        block_num: u32,
+        data: &[u8],
+    ) -> Result<(), &'static str> {
+        if !self.has_metadata_csum() {
+            return self.block_device.write_block(block_num, 0, data);
+        }
+        let mut old = alloc::vec![0u8; data.len()];
+        self.block_device.read_block(block_num, 0, &mut old)?;
+        let sectors = data.chunks(SECTOR_SIZE).zip(old.chunks(SECTOR_SIZE));
+        let mut changed = false;
+        for (i, (new, old)) in sectors.enumerate() {
+            if new != old {
+                self.write_checksummed(block_num, (i * SECTOR_SIZE) as u32, new)?;
+                changed = true;
+            }
+        }
+        // A block which already holds the data may still lack its checksum, if
+        // it was never written with one, like a freshly allocated block.
+        if !changed && !self.block_checksum_matches(block_num, data)? {
+            let len = data.len().min(SECTOR_SIZE);
+            self.write_checksummed(block_num, 0, &data[..len])?;
+        }
+        Ok(())
+    }
+
+    /// Handle some metadata which doesn't match its checksum, following
+    /// [`FileSystem::checksum_policy`] and [`FileSystem::errors`].
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The corrupted block, kept in [`FileSystem::corrupted_block`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the metadata can still be used.
+    /// * `Err(&'static str)` - If the operation must fail.
+    pub(crate) fn checksum_error(&mut self, block_num: u32) -> Result<(), &'static str> {
+        self.corrupted_block = Some(block_num);
+        match self.checksum_policy {
+            ChecksumPolicy::Enforce => self.corruption_error(error::CHECKSUM_MISMATCH),
+            ChecksumPolicy::ReadOnly => {
+                self.read_only = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Locate the entry of a block in the checksum table.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((u32, u32))` - The block of the table holding the entry, and its offset.
+    /// * `Err(&'static str)` - If the block is outside the partition.
+    fn checksum_entry(&self, block_num: u32) -> Result<(u32, u32), &'static str> {
+        if block_num >= self.super_block.total_block {
+            return Err("Block out of range");
+        }
+        let block_size = self.super_block.block_size as u64;
+        let pos = block_num as u64 * CHECKSUM_ENTRY_SIZE as u64;
+        Ok((
+            self.super_block.checksum_table + (pos / block_size) as u32,
+            (pos % block_size) as u32,
+        ))
+    }
+}
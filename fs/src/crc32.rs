@@ -0,0 +1,64 @@
+//! The CRC-32 (IEEE 802.3) checksum, used by the GPT partition tables.
+//!
+//! It's computed a byte at a time with a table built at compile time, like
+//! [`crc32c`](crate::crc32c).
+
+/// The reversed polynomial of CRC-32.
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// The CRC of every byte value.
+const TABLE: [u32; 256] = make_table(POLYNOMIAL);
+
+/// Build the table of a reflected CRC-32, with the CRC of every byte value.
+///
+/// # Parameters
+///
+/// * `poly` - The reversed polynomial.
+///
+/// # Returns
+///
+/// * `[u32; 256]` - The table, indexed by byte value.
+pub(crate) const fn make_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Compute the CRC-32 of some bytes.
+///
+/// # Parameters
+///
+/// * `bytes` - The bytes.
+///
+/// # Returns
+///
+/// * `u32` - The checksum.
+///
+/// # Example
+///
+/// ```rust
+/// use proka_fs::crc32::crc32;
+///
+/// assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+/// ```
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc = TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
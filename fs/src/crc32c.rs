@@ -0,0 +1,55 @@
+//! The CRC-32C (Castagnoli) checksum, used by the checksums of the metadata.
+//!
+//! It's computed a byte at a time with a table built at compile time, so it
+//! works without `std` and any hardware support.
+
+use crate::crc32::make_table;
+
+/// The reversed polynomial of CRC-32C.
+const POLYNOMIAL: u32 = 0x82F6_3B78;
+
+/// The CRC of every byte value.
+const TABLE: [u32; 256] = make_table(POLYNOMIAL);
+
+/// Compute the CRC-32C of some bytes.
+///
+/// # Parameters
+///
+/// * `bytes` - The bytes.
+///
+/// # Returns
+///
+/// * `u32` - The checksum.
+///
+/// # Example
+///
+/// ```rust
+/// use proka_fs::crc32c::crc32c;
+///
+/// assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+/// ```
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    !update(!0, bytes)
+}
+
+/// Continue a CRC-32C with more bytes.
+///
+/// # Parameters
+///
+/// * `crc` - The checksum of the previous bytes, as returned by [`crc32c`].
+/// * `bytes` - The next bytes.
+///
+/// # Returns
+///
+/// * `u32` - The checksum of all the bytes.
+pub fn crc32c_append(crc: u32, bytes: &[u8]) -> u32 {
+    !update(!crc, bytes)
+}
+
+/// Feed bytes to the raw (not inverted) CRC register.
+fn update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc = TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
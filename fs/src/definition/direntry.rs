@@ -1,5 +1,12 @@
+use crate::GenericFsData;
+use crate::definition::{read_u32, write_u32};
+
+/// The max length of a name, the last byte of [`DirEntry::name`] is always 0.
+pub const MAX_NAME_LEN: usize = 251;
+
 /// The entry point of directory.
-#[repr(C)]
+///
+/// It's stored as the inode number (a little-endian `u32`), then the name.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DirEntry {
     /// The inode number of the directory.
@@ -16,43 +23,38 @@ impl DirEntry {
             name: [0; 252],
         }
     }
-}
 
-impl crate::GenericFsData for DirEntry {
-    fn as_bytes(&self) -> &[u8] {
-        unsafe {
-            core::slice::from_raw_parts(
-                self as *const Self as *const u8,
-                core::mem::size_of::<Self>(),
-            )
-        }
+    /// Get the name of the entry, without the trailing zeros.
+    pub fn name_bytes(&self) -> &[u8] {
+        let len = self
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(self.name.len());
+        &self.name[..len]
     }
+}
 
-    fn as_mut_bytes(&mut self) -> &mut [u8] {
-        unsafe {
-            core::slice::from_raw_parts_mut(
-                self as *mut Self as *mut u8,
-                core::mem::size_of::<Self>(),
-            )
-        }
-    }
+impl GenericFsData for DirEntry {
+    const SIZE: usize = 256;
 
-    fn from_bytes(bytes: &[u8]) -> Option<&Self>
-    where
-        Self: Sized,
-    {
-        if bytes.len() < core::mem::size_of::<Self>() {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
             return None;
         }
-        let dir_entry = unsafe { &*(bytes[0] as *const u8 as *const Self) };
-        Some(dir_entry)
+        let dir_entry = Self {
+            inode: read_u32(bytes, 0),
+            name: bytes[4..256].try_into().unwrap(),
+        };
+
+        // The name must end with a 0.
+        (dir_entry.name[MAX_NAME_LEN] == 0).then_some(dir_entry)
     }
 
-    fn from_mut_bytes(buf: &mut [u8]) -> Option<&mut Self> {
-        if buf.len() < core::mem::size_of::<Self>() {
-            return None;
-        }
-        let dir_entry = unsafe { &mut *(buf[0] as *mut u8 as *mut Self) };
-        Some(dir_entry)
+    fn write_bytes(&self, buf: &mut [u8]) {
+        write_u32(buf, 0, self.inode);
+        buf[4..256].copy_from_slice(&self.name);
     }
 }
+
+const _: () = assert!(crate::BLOCK_SIZE.is_multiple_of(DirEntry::SIZE));
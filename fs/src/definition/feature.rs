@@ -0,0 +1,201 @@
+//! The optional features of the on-disk format.
+//!
+//! Each feature is a bit in one of the three masks of the super block, which tell
+//! what a driver not knowing the feature may do with the file system:
+//!
+//! - Compatible: it can still read and write it;
+//! - Read-only compatible: it can read it, but writing would break it;
+//! - Incompatible: it can't even read it.
+
+/// The kind of a feature, which is the mask it's stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureKind {
+    /// A driver not knowing the feature can mount the file system.
+    Compat,
+
+    /// A driver not knowing the feature can only mount the file system read-only.
+    RoCompat,
+
+    /// A driver not knowing the feature must not mount the file system.
+    Incompat,
+}
+
+/// An optional feature of the file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Feature {
+    /// The name of the feature, as given to `mkpkfs`.
+    pub name: &'static str,
+
+    /// The mask the feature is stored in.
+    pub kind: FeatureKind,
+
+    /// The bit of the feature in its mask.
+    pub mask: u32,
+}
+
+/// The extended attributes, stored in the inodes and in extra blocks.
+pub const XATTR: Feature = Feature {
+    name: "xattr",
+    kind: FeatureKind::Compat,
+    mask: 1 << 0,
+};
+
+/// The backups of the super block, see [`crate::definition::SuperBlock::backup_blocks`].
+pub const BACKUP_SUPER: Feature = Feature {
+    name: "backup_super",
+    kind: FeatureKind::Compat,
+    mask: 1 << 1,
+};
+
+/// The checksums of the metadata, see [`crate::checksum`].
+///
+/// Older drivers would change the metadata without updating the checksums.
+pub const METADATA_CSUM: Feature = Feature {
+    name: "metadata_csum",
+    kind: FeatureKind::RoCompat,
+    mask: 1 << 0,
+};
+
+/// The checksums of the data blocks of the regular files, in the checksum table of
+/// [`METADATA_CSUM`], which it needs.
+pub const DATA_CSUM: Feature = Feature {
+    name: "data_csum",
+    kind: FeatureKind::RoCompat,
+    mask: 1 << 1,
+};
+
+/// The blocks allocated by [`FileSystem::fallocate`] but never written, which have
+/// [`UNWRITTEN_FLAG`] in their pointers.
+///
+/// Without it, the blocks are zeroed instead. It's incompatible, as older drivers
+/// would take the flagged pointers for corrupted ones, so it's only enabled when
+/// asked for, such as with `mkpkfs -O unwritten`. It needs less than
+/// [`UNWRITTEN_FLAG`] blocks.
+///
+/// [`FileSystem::fallocate`]: crate::FileSystem::fallocate
+/// [`UNWRITTEN_FLAG`]: crate::definition::UNWRITTEN_FLAG
+pub const UNWRITTEN: Feature = Feature {
+    name: "unwritten",
+    kind: FeatureKind::Incompat,
+    mask: 1 << 0,
+};
+
+/// All the features known by this driver.
+pub const ALL: &[Feature] = &[XATTR, BACKUP_SUPER, METADATA_CSUM, DATA_CSUM, UNWRITTEN];
+
+/// A set of features, as stored in the super block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Features {
+    /// The compatible features.
+    pub compat: u32,
+
+    /// The read-only compatible features.
+    pub ro_compat: u32,
+
+    /// The incompatible features.
+    pub incompat: u32,
+}
+
+impl Features {
+    /// No feature at all.
+    pub const NONE: Self = Self {
+        compat: 0,
+        ro_compat: 0,
+        incompat: 0,
+    };
+
+    /// The features of the images made before the features existed (revision 0).
+    pub const LEGACY: Self = Self::NONE.with(XATTR);
+
+    /// The features known by this driver.
+    pub const SUPPORTED: Self = {
+        let mut features = Self::NONE;
+        let mut i = 0;
+        while i < ALL.len() {
+            features = features.with(ALL[i]);
+            i += 1;
+        }
+        features
+    };
+
+    /// Get the set with a feature added.
+    pub const fn with(mut self, feature: Feature) -> Self {
+        *self.mask_mut(feature.kind) |= feature.mask;
+        self
+    }
+
+    /// Get the set with a feature removed.
+    pub const fn without(mut self, feature: Feature) -> Self {
+        *self.mask_mut(feature.kind) &= !feature.mask;
+        self
+    }
+
+    /// Check if a feature is in the set.
+    pub const fn contains(&self, feature: Feature) -> bool {
+        let mask = match feature.kind {
+            FeatureKind::Compat => self.compat,
+            FeatureKind::RoCompat => self.ro_compat,
+            FeatureKind::Incompat => self.incompat,
+        };
+        mask & feature.mask != 0
+    }
+
+    /// Get the features of the set which this driver doesn't know.
+    pub const fn unknown(&self) -> Self {
+        Self {
+            compat: self.compat & !Self::SUPPORTED.compat,
+            ro_compat: self.ro_compat & !Self::SUPPORTED.ro_compat,
+            incompat: self.incompat & !Self::SUPPORTED.incompat,
+        }
+    }
+
+    /// Change the set by a comma-separated list of feature names, each one
+    /// prefixed with `^` to remove it, such as `xattr` or `^xattr`.
+    ///
+    /// # Parameters
+    ///
+    /// * `list` - The list of features.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The changed set.
+    /// * `Err(&'static str)` - If a feature is unknown.
+    pub fn parse(self, list: &str) -> Result<Self, &'static str> {
+        let mut features = self;
+        for item in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (remove, name) = match item.strip_prefix('^') {
+                Some(name) => (true, name),
+                None => (false, item),
+            };
+            let feature = *ALL
+                .iter()
+                .find(|feature| feature.name == name)
+                .ok_or("Unknown feature")?;
+            features = if remove {
+                features.without(feature)
+            } else {
+                features.with(feature)
+            };
+        }
+        Ok(features)
+    }
+
+    /// Get the mask storing a kind of feature.
+    const fn mask_mut(&mut self, kind: FeatureKind) -> &mut u32 {
+        match kind {
+            FeatureKind::Compat => &mut self.compat,
+            FeatureKind::RoCompat => &mut self.ro_compat,
+            FeatureKind::Incompat => &mut self.incompat,
+        }
+    }
+}
+
+impl Default for Features {
+    /// The features enabled on new file systems.
+    fn default() -> Self {
+        Self::NONE
+            .with(XATTR)
+            .with(BACKUP_SUPER)
+            .with(METADATA_CSUM)
+    }
+}
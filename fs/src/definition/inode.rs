@@ -1,4 +1,8 @@
-use crate::definition::SuperBlock;
+use crate::crc32c::{crc32c, crc32c_append};
+use crate::definition::{
+    SuperBlock, read_u16, read_u32, read_u64, write_u16, write_u32, write_u64,
+};
+use crate::{BLOCK_SIZE, GenericFsData};
 
 /// The definition of the file type
 #[repr(u8)]
@@ -12,10 +16,54 @@ pub enum FileType {
 
     /// The device file.
     Device = 2,
+
+    /// The named pipe (FIFO).
+    Fifo = 3,
+
+    /// The Unix domain socket.
+    Socket = 4,
+}
+
+impl TryFrom<u8> for FileType {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Regular),
+            1 => Ok(Self::Directory),
+            2 => Ok(Self::Device),
+            3 => Ok(Self::Fifo),
+            4 => Ok(Self::Socket),
+            _ => Err("Invalid file type"),
+        }
+    }
 }
 
 /// The definition of the inode.
-#[repr(C)]
+///
+/// It's stored in the inode table as 256 bytes, the numbers are little-endian:
+///
+/// | Offset | Field |
+/// |---|---|
+/// | 0 | `is_used` (0 or 1) |
+/// | 4 | `inode_id` |
+/// | 8 | `file_type` |
+/// | 12 | `direct_blocks` |
+/// | 60 | `indirect_block` |
+/// | 64 | `double_indirect_block` |
+/// | 72 | `file_length` |
+/// | 80 | `xattr_block` |
+/// | 84 | `inline_xattr` |
+/// | 180 | `mode` |
+/// | 182 | `links_count` |
+/// | 184 | `device` |
+/// | 188 | `block_count` |
+/// | 192 | `atime` |
+/// | 200 | `mtime` |
+/// | 208 | `ctime` |
+/// | 216 | checksum, see [`Inode::checksum_matches`] |
+/// | 220 | `next_orphan` |
+/// | 224 | `_reserved` |
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Inode {
     /// Sign is this ID used.
@@ -30,56 +78,202 @@ pub struct Inode {
     /// 0: regular file;
     ///
     /// 1: directory;
+    ///
+    /// 2: device file;
+    ///
+    /// 3: named pipe (FIFO);
+    ///
+    /// 4: Unix domain socket;
     pub file_type: FileType,
 
-    /// The head block of the file.
-    pub head_block: u32,
+    /// The data blocks mapped directly, 0 means not allocated (a hole).
+    pub direct_blocks: [u32; DIRECT_BLOCKS],
+
+    /// The block holding the next data block numbers (0 if none).
+    pub indirect_block: u32,
+
+    /// The block holding the numbers of further indirect blocks (0 if none).
+    pub double_indirect_block: u32,
 
     /// The file length in bytes.
     pub file_length: u64,
 
+    /// The block storing the extended attributes which don't fit inline (0 if none).
+    pub xattr_block: u32,
+
+    /// The extended attributes stored inside the inode.
+    pub inline_xattr: [u8; INLINE_XATTR_SIZE],
+
+    /// The permission bits of the file.
+    pub mode: u16,
+
+    /// The number of directory entries pointing to this inode.
+    pub links_count: u16,
+
+    /// The device number, only meaningful for device files.
+    pub device: u32,
+
+    /// The number of blocks owned by the file, including indirect blocks, but not
+    /// the extended attribute block.
+    pub block_count: u32,
+
+    /// The last access time, in seconds since the Unix epoch.
+    pub atime: u64,
+
+    /// The last modification time of the data, in seconds since the Unix epoch.
+    pub mtime: u64,
+
+    /// The last change time of the inode, in seconds since the Unix epoch.
+    pub ctime: u64,
+
+    /// The next inode of the orphan list, 0 at its end, see
+    /// [`SuperBlock::orphan_head`](crate::definition::SuperBlock::orphan_head).
+    pub next_orphan: u32,
+
     /// Reserved data
-    pub _reserved: [u8; 7],
+    pub _reserved: [u8; 32],
 }
 
-impl crate::GenericFsData for Inode {
-    fn as_bytes(&self) -> &[u8] {
-        unsafe {
-            core::slice::from_raw_parts(
-                self as *const Self as *const u8,
-                core::mem::size_of::<Inode>(),
-            )
-        }
-    }
+/// The size of the inline extended attribute area in an inode.
+pub const INLINE_XATTR_SIZE: usize = 96;
 
-    fn as_mut_bytes(&mut self) -> &mut [u8] {
-        unsafe {
-            core::slice::from_raw_parts_mut(
-                self as *mut Self as *mut u8,
-                core::mem::size_of::<Inode>(),
-            )
-        }
-    }
+/// The number of data blocks mapped directly by an inode.
+pub const DIRECT_BLOCKS: usize = 12;
+
+/// The flag of a block pointer telling the block is allocated but was never
+/// written, so it reads as zeroes. Only with [`UNWRITTEN`](crate::definition::feature::UNWRITTEN).
+pub const UNWRITTEN_FLAG: u32 = 1 << 31;
 
-    fn from_bytes(buf: &[u8]) -> Option<&Self> {
-        if buf.len() < core::mem::size_of::<Self>() {
+/// The max length of a file, which is what the direct, indirect and double
+/// indirect blocks can map.
+pub const MAX_FILE_LENGTH: u64 = {
+    let per_block = (BLOCK_SIZE / 4) as u64;
+    (DIRECT_BLOCKS as u64 + per_block + per_block * per_block) * BLOCK_SIZE as u64
+};
+
+impl GenericFsData for Inode {
+    const SIZE: usize = 256;
+
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
             return None;
         }
-        let ptr = buf.as_ptr() as *const Self;
-        let inode: &Self = unsafe { &*ptr };
-        Some(inode)
+
+        let is_used = match buf[0] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let mut direct_blocks = [0; DIRECT_BLOCKS];
+        for (i, block_num) in direct_blocks.iter_mut().enumerate() {
+            *block_num = read_u32(buf, 12 + i * 4);
+        }
+        let inode = Self {
+            is_used,
+            inode_id: read_u32(buf, 4),
+            file_type: FileType::try_from(buf[8]).ok()?,
+            direct_blocks,
+            indirect_block: read_u32(buf, 60),
+            double_indirect_block: read_u32(buf, 64),
+            file_length: read_u64(buf, 72),
+            xattr_block: read_u32(buf, 80),
+            inline_xattr: buf[84..180].try_into().unwrap(),
+            mode: read_u16(buf, 180),
+            links_count: read_u16(buf, 182),
+            device: read_u32(buf, 184),
+            block_count: read_u32(buf, 188),
+            atime: read_u64(buf, 192),
+            mtime: read_u64(buf, 200),
+            ctime: read_u64(buf, 208),
+            next_orphan: read_u32(buf, 220),
+            _reserved: buf[224..256].try_into().unwrap(),
+        };
+        (inode.file_length <= MAX_FILE_LENGTH).then_some(inode)
     }
 
-    fn from_mut_bytes(buf: &mut [u8]) -> Option<&mut Self> {
-        if buf.len() < core::mem::size_of::<Self>() {
-            return None;
+    fn write_bytes(&self, buf: &mut [u8]) {
+        // The gaps between the fields are kept zeroed.
+        buf[..Self::SIZE].fill(0);
+        buf[0] = self.is_used as u8;
+        write_u32(buf, 4, self.inode_id);
+        buf[8] = self.file_type as u8;
+        for (i, &block_num) in self.direct_blocks.iter().enumerate() {
+            write_u32(buf, 12 + i * 4, block_num);
         }
-        let inode = unsafe { &mut *(buf[0] as *mut u8 as *mut Self) };
-        Some(inode)
+        write_u32(buf, 60, self.indirect_block);
+        write_u32(buf, 64, self.double_indirect_block);
+        write_u64(buf, 72, self.file_length);
+        write_u32(buf, 80, self.xattr_block);
+        buf[84..180].copy_from_slice(&self.inline_xattr);
+        write_u16(buf, 180, self.mode);
+        write_u16(buf, 182, self.links_count);
+        write_u32(buf, 184, self.device);
+        write_u32(buf, 188, self.block_count);
+        write_u64(buf, 192, self.atime);
+        write_u64(buf, 200, self.mtime);
+        write_u64(buf, 208, self.ctime);
+        write_u32(buf, 220, self.next_orphan);
+        buf[224..256].copy_from_slice(&self._reserved);
+        write_u32(buf, 216, Self::checksum(buf));
     }
 }
 
+// The offsets above rely on these sizes.
+const _: () = assert!(DIRECT_BLOCKS == 12 && INLINE_XATTR_SIZE == 96);
+const _: () = assert!(BLOCK_SIZE.is_multiple_of(Inode::SIZE));
+
 impl Inode {
+    /// Create a used inode without any data.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The id of the inode.
+    /// * `file_type` - The type of the file.
+    pub const fn new(inode_id: u32, file_type: FileType) -> Self {
+        let (mode, links_count) = match file_type {
+            FileType::Directory => (0o755, 2),
+            _ => (0o644, 1),
+        };
+        Self {
+            is_used: true,
+            inode_id,
+            file_type,
+            direct_blocks: [0; DIRECT_BLOCKS],
+            indirect_block: 0,
+            double_indirect_block: 0,
+            file_length: 0,
+            xattr_block: 0,
+            inline_xattr: [0; INLINE_XATTR_SIZE],
+            mode,
+            links_count,
+            device: 0,
+            block_count: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            next_orphan: 0,
+            _reserved: [0; 32],
+        }
+    }
+
+    /// Check the checksum of an encoded inode.
+    ///
+    /// The checksum is always written, but only checked on file systems with the
+    /// `metadata_csum` feature. The free inodes are zeroed, so it's only meaningful
+    /// for the used ones.
+    ///
+    /// # Parameters
+    ///
+    /// * `buf` - The bytes of the inode.
+    pub fn checksum_matches(buf: &[u8]) -> bool {
+        buf.len() >= Self::SIZE && read_u32(buf, 216) == Self::checksum(buf)
+    }
+
+    /// Compute the checksum of an encoded inode, which covers everything but itself.
+    fn checksum(buf: &[u8]) -> u32 {
+        crc32c_append(crc32c(&buf[..216]), &buf[220..Self::SIZE])
+    }
+
     /// Locate the inode in the file system.
     ///
     /// # Parameters
@@ -91,7 +285,7 @@ impl Inode {
     ///
     /// * `(u64, usize)` - The block index and the offset of the inode in the block.
     pub fn locate(inode_id: u32, super_block: &SuperBlock) -> (u64, usize) {
-        const INODE_SIZE: usize = core::mem::size_of::<Inode>();
+        const INODE_SIZE: usize = Inode::SIZE;
         let inodes_per_block = super_block.block_size as usize / INODE_SIZE;
         let inode_start_block = 1u64; // The first block is the super block, which has been used.
         let block_idx = inode_start_block + (inode_id as u64 / inodes_per_block as u64);
@@ -1,8 +1,49 @@
 pub mod direntry;
+pub mod feature;
 pub mod inode;
 pub mod superblock;
+pub mod uuid;
 
 pub use direntry::DirEntry;
+pub use inode::DIRECT_BLOCKS;
 pub use inode::FileType;
+pub use inode::INLINE_XATTR_SIZE;
 pub use inode::Inode;
+pub use inode::MAX_FILE_LENGTH;
+pub use inode::UNWRITTEN_FLAG;
+pub use superblock::CHECKSUM_ENTRY_SIZE;
+pub use superblock::MAGIC;
+pub use superblock::REVISION;
+pub use superblock::STATE_CLEAN;
 pub use superblock::SuperBlock;
+pub use uuid::Uuid;
+
+/// Read a little-endian `u16` at an offset of a slice.
+pub(crate) fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Read a little-endian `u32` at an offset of a slice.
+pub(crate) fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Read a little-endian `u64` at an offset of a slice.
+pub(crate) fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// Write a `u16` in little-endian at an offset of a slice.
+pub(crate) fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Write a `u32` in little-endian at an offset of a slice.
+pub(crate) fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Write a `u64` in little-endian at an offset of a slice.
+pub(crate) fn write_u64(buf: &mut [u8], offset: usize, value: u64) {
+    buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
@@ -1,5 +1,43 @@
+use crate::GenericFsData;
+use crate::crc32c::crc32c;
+use crate::definition::feature::{self, Features};
+use crate::definition::uuid::Uuid;
+use crate::definition::{read_u32, read_u64, write_u32, write_u64};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// The magic number of ProkaFS ("PKFS").
+pub const MAGIC: u32 = 0x504B4653;
+
+/// The revision of the on-disk format written by this driver.
+///
+/// Revision 0 is the original format, without the revision and the feature masks.
+/// Later changes of the layout are told by the feature masks, not by the revision.
+pub const REVISION: u32 = 1;
+
+/// The max length of the label in bytes.
+pub const LABEL_LEN: usize = 32;
+
+/// The max length of the name of the tool which created the file system, in bytes.
+pub const CREATOR_LEN: usize = 32;
+
+/// The size of the entry of a block in the checksum table, in bytes.
+pub const CHECKSUM_ENTRY_SIZE: usize = 8;
+
+/// The flag of [`SuperBlock::state`] telling the file system was cleanly unmounted.
+pub const STATE_CLEAN: u32 = 1 << 0;
+
+/// The min number of data blocks for a file system to have backups of its super block.
+const MIN_BACKUP_DATA_BLOCKS: u32 = 8;
+
+/// The number of blocks in the partition for each block of the inode table.
+const BLOCKS_PER_INODE_BLOCK: usize = 16;
+
 /// The definition of the super block.
-#[repr(C)]
+///
+/// It's stored at the start of block 0, with the fields in the order below, then
+/// its checksum. The numbers are little-endian, and the strings are UTF-8 padded
+/// with zeros.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SuperBlock {
     /// The magic number to identify the file system.
@@ -16,66 +54,384 @@ pub struct SuperBlock {
 
     /// The total block number in the partition.
     pub total_block: u32,
-}
 
-impl crate::GenericFsData for SuperBlock {
-    fn as_bytes(&self) -> &[u8] {
-        unsafe {
-            core::slice::from_raw_parts(
-                self as *const Self as *const u8,
-                core::mem::size_of::<Self>(),
-            )
-        }
-    }
+    /// The number of free blocks in the data area.
+    pub free_blocks: u32,
 
-    fn as_mut_bytes(&mut self) -> &mut [u8] {
-        unsafe {
-            core::slice::from_raw_parts_mut(
-                self as *mut Self as *mut u8,
-                core::mem::size_of::<Self>(),
-            )
-        }
-    }
+    /// The number of free inodes in the inode table.
+    pub free_inodes: u32,
 
-    fn from_bytes(buf: &[u8]) -> Option<&Self> {
-        if buf.len() < core::mem::size_of::<Self>() {
-            return None;
-        }
-        let ptr = buf.as_ptr() as *const Self;
-        let inode: &Self = unsafe { &*ptr };
-        Some(inode)
+    /// The number of data blocks kept for privileged users.
+    pub reserved_blocks: u32,
+
+    /// The revision of the on-disk format, see [`REVISION`].
+    pub revision: u32,
+
+    /// The compatible features, see [`Features`].
+    pub feature_compat: u32,
+
+    /// The read-only compatible features, see [`Features`].
+    pub feature_ro_compat: u32,
+
+    /// The incompatible features, see [`Features`].
+    pub feature_incompat: u32,
+
+    /// The UUID of the file system, nil for the images made before it existed.
+    pub uuid: Uuid,
+
+    /// The label of the file system, see [`SuperBlock::label`].
+    pub label: [u8; LABEL_LEN],
+
+    /// The creation time, in seconds since the Unix epoch.
+    pub created: u64,
+
+    /// The last mount time, in seconds since the Unix epoch.
+    pub last_mounted: u64,
+
+    /// The number of times the file system was mounted read-write.
+    pub mount_count: u32,
+
+    /// The name and version of the tool which created the file system, see
+    /// [`SuperBlock::creator`].
+    pub creator: [u8; CREATOR_LEN],
+
+    /// The first block of the checksum table, see [`SuperBlock::checksum_table_blocks`].
+    pub checksum_table: u32,
+
+    /// The state flags, see [`STATE_CLEAN`].
+    pub state: u32,
+
+    /// The first inode of the orphan list, 0 if it's empty.
+    ///
+    /// The orphans are the files unlinked while still open, which are released
+    /// when they're closed, or on the next mount after a crash. They're linked
+    /// by [`Inode::next_orphan`](crate::definition::Inode::next_orphan).
+    pub orphan_head: u32,
+}
+
+impl GenericFsData for SuperBlock {
+    const SIZE: usize = 164;
+
+    fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let super_block = Self::from_bytes_unchecked(buf)?;
+        // The checksum is always written, but older file systems may not have it.
+        let checksum_matches = read_u32(buf, 160) == crc32c(&buf[..160]);
+        (checksum_matches || !super_block.features().contains(feature::METADATA_CSUM))
+            .then_some(super_block)
     }
 
-    fn from_mut_bytes(buf: &mut [u8]) -> Option<&mut Self> {
-        if buf.len() < core::mem::size_of::<Self>() {
-            return None;
-        }
-        let inode = unsafe { &mut *(buf[0] as *mut u8 as *mut Self) };
-        Some(inode)
+    fn write_bytes(&self, buf: &mut [u8]) {
+        write_u32(buf, 0, self.magic);
+        write_u32(buf, 4, self.block_size);
+        write_u32(buf, 8, self.bitmap_start_block);
+        write_u32(buf, 12, self.data_start_block);
+        write_u32(buf, 16, self.total_block);
+        write_u32(buf, 20, self.free_blocks);
+        write_u32(buf, 24, self.free_inodes);
+        write_u32(buf, 28, self.reserved_blocks);
+        write_u32(buf, 32, self.revision);
+        write_u32(buf, 36, self.feature_compat);
+        write_u32(buf, 40, self.feature_ro_compat);
+        write_u32(buf, 44, self.feature_incompat);
+        buf[48..64].copy_from_slice(&self.uuid.0);
+        buf[64..96].copy_from_slice(&self.label);
+        write_u64(buf, 96, self.created);
+        write_u64(buf, 104, self.last_mounted);
+        write_u32(buf, 112, self.mount_count);
+        buf[116..148].copy_from_slice(&self.creator);
+        write_u32(buf, 148, self.checksum_table);
+        write_u32(buf, 152, self.state);
+        write_u32(buf, 156, self.orphan_head);
+        write_u32(buf, 160, crc32c(&buf[..160]));
     }
 }
 
+const _: () = assert!(SuperBlock::SIZE <= crate::BLOCK_SIZE);
+
 impl SuperBlock {
     /// Init a superblock object.
     ///
     /// # Parameters
     ///
-    /// * `fs_type` - The file system type.
     /// * `partition_size` - The partition size in bytes.
     ///
     /// # Returns
     ///
     /// * `Self` - The superblock object.
+    ///
+    /// # Note
+    ///
+    /// The inode table takes 1/16 of the partition, which is 1 inode for every 4 blocks.
+    /// The partition may be too small to hold any data, see [`SuperBlock::data_block_count`].
     pub fn new(partition_size: u64) -> Self {
         let total_block_num: usize = partition_size as usize / 1024;
-        let data_start_block = total_block_num as u32 / 1024 + 65536;
-        let bitmap_size = total_block_num as usize / 1024; // 1 bit per block, so 1 byte can represent 8 blocks, so 1024 bytes can represent 8192 blocks
-        Self {
-            magic: 0x504B4653,
+        let inode_table_size = (total_block_num / BLOCKS_PER_INODE_BLOCK).max(1);
+        let data_start_block = 1 + inode_table_size as u32;
+        let bitmap_size = total_block_num.div_ceil(1024); // 1 byte per block, so 1024 bytes can represent 1024 blocks
+        let bitmap_start_block = total_block_num.saturating_sub(bitmap_size) as u32;
+        let data_blocks = bitmap_start_block.saturating_sub(data_start_block);
+        let mut super_block = Self {
+            magic: MAGIC,
             block_size: 1024,
-            bitmap_start_block: (total_block_num - bitmap_size) as u32,
+            bitmap_start_block,
             data_start_block,
             total_block: total_block_num as u32,
+            free_blocks: data_blocks,
+            free_inodes: 0,
+            reserved_blocks: data_blocks / 20, // 5% of the data area
+            revision: REVISION,
+            feature_compat: 0,
+            feature_ro_compat: 0,
+            feature_incompat: 0,
+            uuid: Uuid::NIL,
+            label: [0; LABEL_LEN],
+            created: 0,
+            last_mounted: 0,
+            mount_count: 0,
+            creator: [0; CREATOR_LEN],
+            checksum_table: 0,
+            state: STATE_CLEAN,
+            orphan_head: 0,
+        };
+        super_block.set_features(Features::default());
+        super_block.free_inodes = super_block.inode_count();
+        super_block
+    }
+
+    /// Read a super block without checking its checksum.
+    ///
+    /// # Parameters
+    ///
+    /// * `buf` - The slice of bytes.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Self)` - The super block, see [`SuperBlock::validate`] for its fields.
+    /// * `None` - If the slice is too short.
+    pub fn from_bytes_unchecked(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
         }
+        Some(Self {
+            magic: read_u32(buf, 0),
+            block_size: read_u32(buf, 4),
+            bitmap_start_block: read_u32(buf, 8),
+            data_start_block: read_u32(buf, 12),
+            total_block: read_u32(buf, 16),
+            free_blocks: read_u32(buf, 20),
+            free_inodes: read_u32(buf, 24),
+            reserved_blocks: read_u32(buf, 28),
+            revision: read_u32(buf, 32),
+            feature_compat: read_u32(buf, 36),
+            feature_ro_compat: read_u32(buf, 40),
+            feature_incompat: read_u32(buf, 44),
+            uuid: Uuid(buf[48..64].try_into().unwrap()),
+            label: buf[64..96].try_into().unwrap(),
+            created: read_u64(buf, 96),
+            last_mounted: read_u64(buf, 104),
+            mount_count: read_u32(buf, 112),
+            creator: buf[116..148].try_into().unwrap(),
+            checksum_table: read_u32(buf, 148),
+            state: read_u32(buf, 152),
+            orphan_head: read_u32(buf, 156),
+        })
+    }
+
+    /// Get the number of inodes in the inode table.
+    pub fn inode_count(&self) -> u32 {
+        (self.data_start_block.saturating_sub(1) as usize * self.block_size as usize
+            / crate::definition::Inode::SIZE) as u32
     }
+
+    /// Get the number of blocks in the data area.
+    pub fn data_block_count(&self) -> u32 {
+        self.bitmap_start_block
+            .saturating_sub(self.data_start_block)
+    }
+
+    /// Check the layout of the super block is valid.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the super block can be mounted.
+    /// * `Err(&'static str)` - If a field is invalid.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.magic != MAGIC {
+            return Err("Not a ProkaFS file system");
+        }
+        if self.block_size as usize != crate::BLOCK_SIZE {
+            return Err("Unsupported block size");
+        }
+
+        // The bitmap holds one byte per block of the partition.
+        let bitmap_bytes = self.total_block.saturating_sub(self.bitmap_start_block) as u64
+            * self.block_size as u64;
+        if self.data_start_block < 2
+            || self.data_start_block > self.bitmap_start_block
+            || self.bitmap_start_block > self.total_block
+            || bitmap_bytes < self.total_block as u64
+        {
+            return Err("Invalid super block layout");
+        }
+        let table = self.checksum_table_blocks();
+        if self.features().contains(feature::METADATA_CSUM)
+            && (table.start < self.data_start_block || table.end > self.bitmap_start_block)
+        {
+            return Err("Invalid super block layout");
+        }
+        // The block numbers must leave the unwritten flag free.
+        if self.features().contains(feature::UNWRITTEN)
+            && self.total_block > crate::definition::UNWRITTEN_FLAG
+        {
+            return Err("Invalid super block layout");
+        }
+        Ok(())
+    }
+
+    /// Get the features of the file system.
+    ///
+    /// # Returns
+    ///
+    /// * `Features` - The features, which are [`Features::LEGACY`] for revision 0.
+    pub fn features(&self) -> Features {
+        if self.revision == 0 {
+            return Features::LEGACY;
+        }
+        Features {
+            compat: self.feature_compat,
+            ro_compat: self.feature_ro_compat,
+            incompat: self.feature_incompat,
+        }
+    }
+
+    /// Set the features of the file system, upgrading it to the current revision.
+    ///
+    /// # Parameters
+    ///
+    /// * `features` - The new features.
+    pub fn set_features(&mut self, features: Features) {
+        self.revision = self.revision.max(REVISION);
+        self.feature_compat = features.compat;
+        self.feature_ro_compat = features.ro_compat;
+        self.feature_incompat = features.incompat;
+    }
+
+    /// Get the blocks storing the backups of the super block.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<u32>` - The blocks, empty if the file system has no backup.
+    ///
+    /// # Note
+    ///
+    /// The backups are in the middle and at the end of the data area, which only
+    /// depend on the partition size, so they can be found without the primary super
+    /// block. They're marked used in the bitmap, and owned by no file.
+    pub fn backup_blocks(&self) -> Vec<u32> {
+        let data_blocks = self.data_block_count();
+        if !self.features().contains(feature::BACKUP_SUPER) || data_blocks < MIN_BACKUP_DATA_BLOCKS
+        {
+            return Vec::new();
+        }
+        alloc::vec![
+            self.data_start_block + data_blocks / 2,
+            self.bitmap_start_block - 1
+        ]
+    }
+
+    /// Get the blocks of the checksum table.
+    ///
+    /// # Returns
+    ///
+    /// * `Range<u32>` - The blocks, empty if the file system has no metadata checksums.
+    ///
+    /// # Note
+    ///
+    /// The table holds [`CHECKSUM_ENTRY_SIZE`] bytes for every block of the
+    /// partition, see [`crate::checksum`]. It's in the data area, marked used in
+    /// the bitmap and owned by no file.
+    pub fn checksum_table_blocks(&self) -> Range<u32> {
+        if !self.features().contains(feature::METADATA_CSUM) {
+            return 0..0;
+        }
+        let len = (self.total_block as u64 * CHECKSUM_ENTRY_SIZE as u64)
+            .div_ceil(self.block_size.max(1) as u64);
+        let end = (self.checksum_table as u64 + len).min(u32::MAX as u64) as u32;
+        self.checksum_table..end
+    }
+
+    /// Check if the file system was cleanly unmounted.
+    ///
+    /// It's false while the file system is mounted read-write, so a crash leaves
+    /// it false for the next mount.
+    pub fn is_clean(&self) -> bool {
+        self.state & STATE_CLEAN != 0
+    }
+
+    /// Check if another super block describes the same file system, ignoring the
+    /// counters and the state which are only meaningful in the primary super block.
+    pub fn same_file_system(&self, other: &Self) -> bool {
+        let without_counters = |super_block: &Self| Self {
+            free_blocks: 0,
+            free_inodes: 0,
+            last_mounted: 0,
+            mount_count: 0,
+            state: 0,
+            orphan_head: 0,
+            ..*super_block
+        };
+        without_counters(self) == without_counters(other)
+    }
+
+    /// Get the label of the file system.
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The label, empty if it's not set or not valid UTF-8.
+    pub fn label(&self) -> &str {
+        fixed_str(&self.label)
+    }
+
+    /// Set the label of the file system.
+    ///
+    /// # Parameters
+    ///
+    /// * `label` - The new label, up to [`LABEL_LEN`] bytes.
+    pub fn set_label(&mut self, label: &str) -> Result<(), &'static str> {
+        if label.len() > LABEL_LEN || label.contains('\0') {
+            return Err("Invalid label");
+        }
+        self.label = [0; LABEL_LEN];
+        self.label[..label.len()].copy_from_slice(label.as_bytes());
+        Ok(())
+    }
+
+    /// Get the name and version of the tool which created the file system.
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The name, empty if it's unknown or not valid UTF-8.
+    pub fn creator(&self) -> &str {
+        fixed_str(&self.creator)
+    }
+
+    /// Set the name and version of the tool which created the file system.
+    ///
+    /// # Parameters
+    ///
+    /// * `creator` - The name, cut to [`CREATOR_LEN`] bytes.
+    pub fn set_creator(&mut self, creator: &str) {
+        let mut len = creator.len().min(CREATOR_LEN);
+        while !creator.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.creator = [0; CREATOR_LEN];
+        self.creator[..len].copy_from_slice(&creator.as_bytes()[..len]);
+    }
+}
+
+/// Read a string padded with zeros.
+fn fixed_str(bytes: &[u8]) -> &str {
+    let len = bytes.iter().position(|&c| c == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..len]).unwrap_or("")
 }
@@ -0,0 +1,65 @@
+//! The UUID identifying a file system.
+
+/// A UUID, stored as its 16 bytes in the usual order (big-endian fields).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid(pub [u8; 16]);
+
+impl Uuid {
+    /// The nil UUID, used by the images made before the UUIDs existed.
+    pub const NIL: Self = Self([0; 16]);
+
+    /// Create a random (version 4) UUID.
+    ///
+    /// # Parameters
+    ///
+    /// * `bytes` - The random bytes, some bits are replaced by the version and the variant.
+    pub const fn from_random_bytes(mut bytes: [u8; 16]) -> Self {
+        bytes[6] = (bytes[6] & 0x0F) | 0x40;
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+        Self(bytes)
+    }
+
+    /// Check if this is the nil UUID.
+    pub fn is_nil(&self) -> bool {
+        *self == Self::NIL
+    }
+}
+
+impl core::fmt::Display for Uuid {
+    /// Format the UUID as `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                write!(f, "-")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl core::str::FromStr for Uuid {
+    type Err = &'static str;
+
+    /// Parse a UUID formatted as `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.as_bytes();
+        if s.len() != 36 || [8, 13, 18, 23].iter().any(|&i| s[i] != b'-') {
+            return Err("Invalid UUID");
+        }
+        let mut digits = s.iter().filter(|&&c| c != b'-').map(|&c| match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err("Invalid UUID"),
+        });
+        let mut bytes = [0; 16];
+        for byte in &mut bytes {
+            // There are exactly 32 digits, as the length and the dashes are checked.
+            let high = digits.next().ok_or("Invalid UUID")??;
+            let low = digits.next().ok_or("Invalid UUID")??;
+            *byte = high << 4 | low;
+        }
+        Ok(Self(bytes))
+    }
+}
@@ -0,0 +1,122 @@
+//! The errors of the file system which the callers may want to tell apart.
+//!
+//! The errors are `&'static str` messages. The ones here are shared by the code
+//! returning them and the code matching them, and have an [`ErrorKind`], which the
+//! I/O traits of the open files (see [`handle`](crate::handle)) turn into theirs.
+//!
+//! # Example
+//!
+//! ```rust
+//! use proka_fs::error::{self, ErrorKind};
+//!
+//! assert_eq!(error::kind(error::NOT_FOUND), ErrorKind::NotFound);
+//! assert_eq!(error::kind("Invalid label"), ErrorKind::Other);
+//! ```
+
+/// The path doesn't exist.
+pub const NOT_FOUND: &str = "No such file or directory";
+
+/// The inode isn't used.
+pub const INODE_NOT_FOUND: &str = "Inode not found";
+
+/// The file to create already exists.
+pub const EXISTS: &str = "File exists";
+
+/// The file system is read-only.
+pub const READ_ONLY_FS: &str = "Read-only file system";
+
+/// The block device can't be written.
+pub const READ_ONLY_DEVICE: &str = "Read-only device";
+
+/// The file is a directory, which can't be read or written as a file.
+pub const IS_A_DIRECTORY: &str = "Is a directory";
+
+/// The data area is full.
+pub const NO_FREE_BLOCK: &str = "No free block available";
+
+/// The inode table is full.
+pub const NO_FREE_INODE: &str = "No inode available";
+
+/// The file would be longer than [`MAX_FILE_LENGTH`](crate::definition::MAX_FILE_LENGTH).
+pub const FILE_TOO_LARGE: &str = "File too large";
+
+/// The position of a seek is negative, or past the max length of a file.
+pub const INVALID_SEEK: &str = "Invalid seek";
+
+/// Some metadata doesn't match its checksum, see [`crate::checksum`]. The block is
+/// kept in [`FileSystem::corrupted_block`](crate::FileSystem::corrupted_block).
+pub const CHECKSUM_MISMATCH: &str = "Checksum mismatch";
+
+/// A block pointer of a file is outside the blocks a file can use.
+pub const CORRUPTED_BLOCK_POINTER: &str = "Corrupted block pointer";
+
+/// A directory doesn't hold whole entries.
+pub const CORRUPTED_DIRECTORY: &str = "Corrupted directory";
+
+/// An entry of a directory can't be decoded.
+pub const INVALID_DIR_ENTRY: &str = "Invalid dir entry";
+
+/// The kind of an error, for the callers which can't match every message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The file or the inode doesn't exist.
+    NotFound,
+
+    /// The file already exists.
+    AlreadyExists,
+
+    /// Nothing can be written.
+    ReadOnly,
+
+    /// A directory was used as a file.
+    IsADirectory,
+
+    /// There's no space left.
+    StorageFull,
+
+    /// The file would be too large.
+    FileTooLarge,
+
+    /// An argument is invalid.
+    InvalidInput,
+
+    /// What was read is corrupted.
+    InvalidData,
+
+    /// Any other error.
+    Other,
+}
+
+/// The errors with a kind, the others are [`ErrorKind::Other`].
+const KINDS: [(&str, ErrorKind); 14] = [
+    (NOT_FOUND, ErrorKind::NotFound),
+    (INODE_NOT_FOUND, ErrorKind::NotFound),
+    (EXISTS, ErrorKind::AlreadyExists),
+    (READ_ONLY_FS, ErrorKind::ReadOnly),
+    (READ_ONLY_DEVICE, ErrorKind::ReadOnly),
+    (IS_A_DIRECTORY, ErrorKind::IsADirectory),
+    (NO_FREE_BLOCK, ErrorKind::StorageFull),
+    (NO_FREE_INODE, ErrorKind::StorageFull),
+    (FILE_TOO_LARGE, ErrorKind::FileTooLarge),
+    (INVALID_SEEK, ErrorKind::InvalidInput),
+    (CHECKSUM_MISMATCH, ErrorKind::InvalidData),
+    (CORRUPTED_BLOCK_POINTER, ErrorKind::InvalidData),
+    (CORRUPTED_DIRECTORY, ErrorKind::InvalidData),
+    (INVALID_DIR_ENTRY, ErrorKind::InvalidData),
+];
+
+/// Get the kind of an error.
+///
+/// # Parameters
+///
+/// * `error` - The error returned by the file system.
+///
+/// # Returns
+///
+/// * `ErrorKind` - Its kind, [`ErrorKind::Other`] if it has none.
+pub fn kind(error: &str) -> ErrorKind {
+    KINDS
+        .iter()
+        .find(|(message, _)| *message == error)
+        .map_or(ErrorKind::Other, |&(_, kind)| kind)
+}
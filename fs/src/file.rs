@@ -0,0 +1,1032 @@
+//! The data of the files, and the block mapping which locates it.
+//!
+//! An inode maps the blocks of its file like this:
+//!
+//! - The first [`DIRECT_BLOCKS`] blocks are in `Inode::direct_blocks`;
+//! - The next ones are listed in `Inode::indirect_block`;
+//! - The others are listed in the blocks listed in `Inode::double_indirect_block`.
+//!
+//! A block number of 0 means the block is not allocated (a hole), which reads as zeroes.
+//! The other block numbers read from the disk must be blocks of the data area
+//! which can belong to a file, or the file system is corrupted.
+//!
+//! With the `unwritten` feature, the blocks allocated by [`FileSystem::fallocate`]
+//! have [`UNWRITTEN_FLAG`] in their pointers until they are written, and read as
+//! zeroes too.
+
+use crate::definition::{DIRECT_BLOCKS, FileType, Inode, MAX_FILE_LENGTH, UNWRITTEN_FLAG};
+use crate::{BlockDevice, FileSystem, error};
+use alloc::vec::Vec;
+
+/// The max number of blocks zeroed by a single write.
+const ZERO_BATCH: usize = 64;
+
+/// The state of a block mapped by [`FileSystem::map_block`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockState {
+    /// The block holds the data of the file.
+    Written,
+
+    /// The block was just allocated, and still has its old content.
+    Fresh,
+
+    /// The block has [`UNWRITTEN_FLAG`], it reads as zeroes whatever its content.
+    Unwritten,
+}
+
+/// The pointer blocks already read and verified by a run of lookups, see
+/// [`FileSystem::lookup_data_block`].
+///
+/// With `metadata_csum`, reading an entry means reading the whole table to check
+/// its checksum, so the blocks of a file would check the same tables over and
+/// over. The tables can't change during a read, and the cache is dropped with it.
+#[derive(Default)]
+struct TableCache {
+    /// The tables and their entries, the least recently used first.
+    tables: Vec<(u32, Vec<u32>)>,
+}
+
+impl TableCache {
+    /// The max number of tables kept, the outer table of the double indirect block
+    /// and the table it points to.
+    const CAPACITY: usize = 2;
+}
+
+/// Get the end of a byte range in a file, checking the file can hold it.
+///
+/// # Parameters
+///
+/// * `offset` - The byte offset of the range.
+/// * `len` - The length of the range in bytes.
+///
+/// # Returns
+///
+/// * `Ok(u64)` - The byte offset of the end of the range.
+/// * `Err(&'static str)` - If the range ends past [`MAX_FILE_LENGTH`].
+fn range_end(offset: u64, len: u64) -> Result<u64, &'static str> {
+    offset
+        .checked_add(len)
+        .filter(|&end| end <= MAX_FILE_LENGTH)
+        .ok_or(error::FILE_TOO_LARGE)
+}
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Read the data of a file.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode of the file.
+    /// * `offset` - The byte offset to read from.
+    /// * `buf` - The buffer to store the data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of bytes read, which is less than `buf.len()` at the end of the file.
+    pub fn read_at(
+        &mut self,
+        inode_id: u32,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        let read = self.read_data(&inode, offset, buf)?;
+        self.touch_atime(&mut inode)?;
+        Ok(read)
+    }
+
+    /// Write data to a file, extending it if needed.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode of the file.
+    /// * `offset` - The byte offset to write to.
+    /// * `data` - The data to write.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of bytes written.
+    /// * `Err(&'static str)` - If the file is a directory, whose entries can't be
+    ///   written as data, the range is too large, or the file system is read-only.
+    pub fn write_at(
+        &mut self,
+        inode_id: u32,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<usize, &'static str> {
+        self.check_writable()?;
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        if inode.file_type == FileType::Directory {
+            return Err(error::IS_A_DIRECTORY);
+        }
+        let result = self.write_data(&mut inode, offset, data);
+
+        // Blocks may have been allocated even if the write failed halfway.
+        let result = self.write_inode(&inode).and(result);
+        self.sync_write(result)
+    }
+
+    /// Change the length of a file.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode of the file.
+    /// * `new_len` - The new length in bytes. The blocks past it are freed, and a
+    ///   longer file is extended with a hole, which reads as zeroes.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file has its new length.
+    /// * `Err(&'static str)` - If the file is a directory, the length is too large,
+    ///   or the file system is read-only.
+    pub fn truncate(&mut self, inode_id: u32, new_len: u64) -> Result<(), &'static str> {
+        self.check_writable()?;
+        if new_len > MAX_FILE_LENGTH {
+            return Err(error::FILE_TOO_LARGE);
+        }
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        if inode.file_type == FileType::Directory {
+            return Err(error::IS_A_DIRECTORY);
+        }
+        let result = self.set_length(&mut inode, new_len);
+        self.sync_write(result)
+    }
+
+    /// Allocate the blocks of a range of a file without writing any data, so the
+    /// writes there can't run out of space.
+    ///
+    /// The blocks are contiguous if the free space allows it. With the
+    /// [`UNWRITTEN`] feature, they are mapped unwritten, so they read as zeroes
+    /// without writing them. Otherwise they are zeroed, with a write per run of blocks.
+    ///
+    /// [`UNWRITTEN`]: crate::definition::feature::UNWRITTEN
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode of the file.
+    /// * `offset` - The byte offset of the range.
+    /// * `len` - The length of the range in bytes, the file is extended to its end.
+    ///   An empty range changes nothing.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the whole range is allocated.
+    /// * `Err(&'static str)` - If the file is a directory, the range is too large,
+    ///   there isn't enough free space, or the file system is read-only.
+    pub fn fallocate(&mut self, inode_id: u32, offset: u64, len: u64) -> Result<(), &'static str> {
+        self.check_writable()?;
+        let end = range_end(offset, len)?;
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        if inode.file_type == FileType::Directory {
+            return Err(error::IS_A_DIRECTORY);
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        let block_size = self.super_block.block_size as u64;
+
+        /* Stage 1: Find the holes in the range */
+        let mut holes = Vec::new();
+        for index in (offset / block_size) as u32..end.div_ceil(block_size) as u32 {
+            if self.lookup_block(&inode, index)? == 0 {
+                holes.push(index);
+            }
+        }
+
+        if holes.is_empty() && end <= inode.file_length {
+            return Ok(());
+        }
+
+        /* Stage 2: Claim blocks for them, zeroed unless they can be unwritten */
+        let blocks = self.find_free_blocks(holes.len())?;
+        for &block_num in &blocks {
+            self.claim_block(block_num)?;
+        }
+        if !self.has_unwritten() {
+            let checksummed = self.has_block_checksums(&inode);
+            self.zero_data_blocks(&blocks, checksummed)?;
+        }
+
+        /* Stage 3: Map them */
+        let mut result = Ok(());
+        for (i, (&index, &block_num)) in holes.iter().zip(&blocks).enumerate() {
+            if let Err(e) = self.map_block(&mut inode, index, Some(block_num)) {
+                // No room for a pointer block: the blocks left aren't used.
+                for &block_num in &blocks[i..] {
+                    self.free_block(block_num)?;
+                }
+                result = Err(e);
+                break;
+            }
+        }
+        inode.ctime = (self.clock)();
+        if result.is_ok() && end > inode.file_length {
+            inode.file_length = end;
+            inode.mtime = inode.ctime;
+        }
+        let result = self.write_inode(&inode).and(result);
+        self.sync_write(result)
+    }
+
+    /// Release a range in a file, which reads as zeroes then. The length of the
+    /// file doesn't change.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode of the file.
+    /// * `offset` - The byte offset of the range.
+    /// * `len` - The length of the range in bytes. Its whole blocks are freed, and
+    ///   the parts of blocks at its edges are zeroed.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the range is released.
+    /// * `Err(&'static str)` - If the file is a directory, or the file system is read-only.
+    pub fn punch_hole(&mut self, inode_id: u32, offset: u64, len: u64) -> Result<(), &'static str> {
+        self.check_writable()?;
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        if inode.file_type == FileType::Directory {
+            return Err(error::IS_A_DIRECTORY);
+        }
+        let end = offset.saturating_add(len).min(inode.file_length);
+        if offset >= end {
+            return Ok(());
+        }
+        let block_size = self.super_block.block_size as u64;
+        let first_whole = offset.div_ceil(block_size);
+        // The last block is whole up to the end of the file, as the rest reads as zeroes.
+        let end_whole = if end == inode.file_length {
+            end.div_ceil(block_size)
+        } else {
+            end / block_size
+        };
+
+        /* Stage 1: Zero the parts of blocks at the edges */
+        self.zero_range(&inode, offset, (first_whole * block_size).min(end))?;
+        if end_whole >= first_whole {
+            self.zero_range(&inode, end_whole * block_size, end)?;
+        }
+
+        /* Stage 2: Free the whole blocks */
+        let freed = self.unmap_blocks(&mut inode, first_whole..end_whole)?;
+        inode.mtime = (self.clock)();
+        inode.ctime = inode.mtime;
+        let mut result = self.write_inode(&inode);
+        if result.is_ok() {
+            for block_num in freed {
+                if let Err(e) = self.free_block(block_num) {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.sync_write(result)
+    }
+
+    /// Read the data of a loaded inode, checking the checksums of the blocks if
+    /// they have some.
+    pub(crate) fn read_data(
+        &mut self,
+        inode: &Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        // Reading past the end of the file is fine, but not past where a file can end.
+        if offset > MAX_FILE_LENGTH {
+            return Err(error::FILE_TOO_LARGE);
+        }
+        let block_size = self.super_block.block_size as u64;
+        let checksummed = self.has_block_checksums(inode);
+        let end = inode
+            .file_length
+            .min(offset.saturating_add(buf.len() as u64));
+        let mut pos = offset;
+        let mut tables = TableCache::default();
+
+        while pos < end {
+            let done = (pos - offset) as usize;
+            let index = (pos / block_size) as u32;
+            let in_block = pos % block_size;
+            let block_num = self.lookup_data_block(inode, index, &mut tables)?;
+
+            if in_block == 0 && end - pos >= block_size {
+                // Whole blocks: read the physically contiguous ones at once.
+                let mut count = 1;
+                while block_num != 0
+                    && pos + (count + 1) * block_size <= end
+                    && Some(self.lookup_data_block(inode, index + count as u32, &mut tables)?)
+                        == block_num.checked_add(count as u32)
+                {
+                    count += 1;
+                }
+                let len = (count * block_size) as usize;
+                let dst = &mut buf[done..done + len];
+                if block_num == 0 {
+                    dst.fill(0);
+                } else {
+                    self.block_device.read_blocks(block_num, dst)?;
+                    if checksummed {
+                        for (i, data) in dst.chunks(block_size as usize).enumerate() {
+                            if !self.block_checksum_matches(block_num + i as u32, data)? {
+                                self.checksum_error(block_num + i as u32)?;
+                            }
+                        }
+                    }
+                }
+                pos += len as u64;
+            } else {
+                let len = (block_size - in_block).min(end - pos) as usize;
+                let dst = &mut buf[done..done + len];
+                if block_num == 0 {
+                    dst.fill(0);
+                } else if checksummed {
+                    // The checksum covers the whole block.
+                    let mut block_buf = alloc::vec![0u8; block_size as usize];
+                    self.read_checksummed_block(block_num, &mut block_buf)?;
+                    dst.copy_from_slice(&block_buf[in_block as usize..in_block as usize + len]);
+                } else {
+                    self.block_device
+                        .read_block(block_num, in_block as u32, dst)?;
+                }
+                pos += len as u64;
+            }
+        }
+        Ok(end.saturating_sub(offset) as usize)
+    }
+
+    /// Write data to a loaded inode, the caller must write the inode back.
+    ///
+    /// The checksums of the blocks are updated too, if they have some.
+    pub(crate) fn write_data(
+        &mut self,
+        inode: &mut Inode,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<usize, &'static str> {
+        // Checked first, so nothing is written if the file can't hold the data.
+        let end = range_end(offset, data.len() as u64)?;
+        let block_size = self.super_block.block_size as u64;
+        let checksummed = self.has_block_checksums(inode);
+        let mut pos = offset;
+
+        while pos < end {
+            let done = (pos - offset) as usize;
+            let index = (pos / block_size) as u32;
+            let in_block = pos % block_size;
+            let (block_num, state) = self.map_block(inode, index, None)?;
+
+            if in_block == 0 && end - pos >= block_size {
+                // Whole blocks: write the physically contiguous ones at once.
+                let mut states = alloc::vec![state];
+                while pos + (states.len() as u64 + 1) * block_size <= end {
+                    let next = index + states.len() as u32;
+                    let (next_block, next_state) = self.map_block(inode, next, None)?;
+                    if next_block != block_num + states.len() as u32 {
+                        break;
+                    }
+                    states.push(next_state);
+                }
+                let count = states.len() as u64;
+                let len = (count * block_size) as usize;
+                if checksummed {
+                    let chunks = data[done..done + len].chunks(block_size as usize);
+                    for (i, chunk) in chunks.enumerate() {
+                        self.write_checksummed(block_num + i as u32, 0, chunk)?;
+                    }
+                } else {
+                    self.block_device
+                        .write_blocks(block_num, &data[done..done + len])?;
+                }
+                // The flags are cleared once the data is there.
+                for (i, &state) in states.iter().enumerate() {
+                    if state == BlockState::Unwritten {
+                        self.mark_written(inode, index + i as u32)?;
+                    }
+                }
+                pos += len as u64;
+            } else {
+                let len = (block_size - in_block).min(end - pos) as usize;
+                let chunk = &data[done..done + len];
+                if state == BlockState::Written {
+                    if checksummed {
+                        self.write_checksummed(block_num, in_block as u32, chunk)?;
+                    } else {
+                        self.block_device
+                            .write_block(block_num, in_block as u32, chunk)?;
+                    }
+                } else {
+                    // Don't leave the old content of the block around the data.
+                    let mut block_buf = alloc::vec![0u8; block_size as usize];
+                    block_buf[in_block as usize..in_block as usize + len].copy_from_slice(chunk);
+                    if checksummed {
+                        self.write_checksummed(block_num, 0, &block_buf)?;
+                    } else {
+                        self.block_device.write_block(block_num, 0, &block_buf)?;
+                    }
+                    if state == BlockState::Unwritten {
+                        self.mark_written(inode, index)?;
+                    }
+                }
+                pos += len as u64;
+            }
+            inode.file_length = inode.file_length.max(pos);
+        }
+
+        inode.mtime = (self.clock)();
+        inode.ctime = inode.mtime;
+        Ok(data.len())
+    }
+
+    /// Change the length of a loaded inode, and write it.
+    ///
+    /// The blocks past the new end are freed, and the end of the last block is
+    /// zeroed, so it reads as zeroes if the file grows again. Growing the file only
+    /// changes its length, the new part is a hole.
+    ///
+    /// The inode is written before its blocks are freed, so an interrupted
+    /// change only leaks blocks, and never leaves them shared.
+    pub(crate) fn set_length(
+        &mut self,
+        inode: &mut Inode,
+        new_len: u64,
+    ) -> Result<(), &'static str> {
+        range_end(new_len, 0)?;
+        let block_size = self.super_block.block_size as u64;
+        let mut freed = Vec::new();
+        if new_len < inode.file_length {
+            let block_end = new_len.next_multiple_of(block_size);
+            self.zero_range(inode, new_len, block_end.min(inode.file_length))?;
+            freed = self.unmap_blocks(inode, new_len.div_ceil(block_size)..u64::MAX)?;
+        }
+        inode.file_length = new_len;
+        inode.mtime = (self.clock)();
+        inode.ctime = inode.mtime;
+        self.write_inode(inode)?;
+
+        for block_num in freed {
+            self.free_block(block_num)?;
+        }
+        Ok(())
+    }
+
+    /// Zero a range of a file inside a single block, if the block is allocated
+    /// and written.
+    fn zero_range(&mut self, inode: &Inode, start: u64, end: u64) -> Result<(), &'static str> {
+        if start >= end {
+            return Ok(());
+        }
+        range_end(end, 0)?;
+        let block_size = self.super_block.block_size as u64;
+        let index = (start / block_size) as u32;
+        let block_num = self.lookup_data_block(inode, index, &mut TableCache::default())?;
+        if block_num == 0 {
+            return Ok(());
+        }
+        let in_block = (start % block_size) as u32;
+        let zero = alloc::vec![0u8; (end - start) as usize];
+        if self.has_block_checksums(inode) {
+            self.write_checksummed(block_num, in_block, &zero)
+        } else {
+            self.block_device.write_block(block_num, in_block, &zero)
+        }
+    }
+
+    /// Fill data blocks with zeroes, writing the consecutive ones together.
+    ///
+    /// # Parameters
+    ///
+    /// * `blocks` - The block numbers.
+    /// * `checksummed` - Whether the blocks have checksums to update.
+    fn zero_data_blocks(&mut self, blocks: &[u32], checksummed: bool) -> Result<(), &'static str> {
+        let block_size = self.super_block.block_size as usize;
+        let zero = alloc::vec![0u8; block_size * ZERO_BATCH];
+        let mut rest = blocks;
+        while let Some(&first) = rest.first() {
+            let count = rest
+                .iter()
+                .take(ZERO_BATCH)
+                .enumerate()
+                .take_while(|&(i, &block_num)| block_num == first + i as u32)
+                .count();
+            if checksummed {
+                for &block_num in &rest[..count] {
+                    self.write_checksummed(block_num, 0, &zero[..block_size])?;
+                }
+            } else {
+                self.block_device
+                    .write_blocks(first, &zero[..count * block_size])?;
+            }
+            rest = &rest[count..];
+        }
+        Ok(())
+    }
+
+    /// Unmap the blocks of a file in a range of block indexes, and the pointer
+    /// blocks left empty.
+    ///
+    /// The pointer blocks still in use are written, but the blocks aren't freed:
+    /// the caller must write the inode first, then free them.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode of the file, updated with the remaining blocks.
+    /// * `range` - The indexes of the blocks in the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u32>)` - The unmapped blocks, to free.
+    fn unmap_blocks(
+        &mut self,
+        inode: &mut Inode,
+        range: core::ops::Range<u64>,
+    ) -> Result<Vec<u32>, &'static str> {
+        let per_block = (self.super_block.block_size / 4) as u64;
+        let direct = DIRECT_BLOCKS as u64;
+        // The part of the range in `lo..hi`, relative to `lo`.
+        let part = |lo: u64, hi: u64| range.start.clamp(lo, hi) - lo..range.end.clamp(lo, hi) - lo;
+        let mut freed = Vec::new();
+
+        for index in part(0, direct) {
+            let block_num = self.check_block_pointer(inode.direct_blocks[index as usize])?;
+            if block_num != 0 {
+                freed.push(block_num);
+                inode.direct_blocks[index as usize] = 0;
+            }
+        }
+
+        let slots = part(direct, direct + per_block);
+        if !slots.is_empty()
+            && inode.indirect_block != 0
+            && self.unmap_table(inode.indirect_block, slots, &mut freed)?
+        {
+            freed.push(inode.indirect_block);
+            inode.indirect_block = 0;
+        }
+
+        let slots = part(
+            direct + per_block,
+            direct + per_block + per_block * per_block,
+        );
+        if !slots.is_empty() && inode.double_indirect_block != 0 {
+            let outer = inode.double_indirect_block;
+            let mut tables = self.read_table(outer)?;
+            let mut changed = false;
+            for (i, table) in tables.iter_mut().enumerate() {
+                let base = i as u64 * per_block;
+                let inner = slots.start.clamp(base, base + per_block) - base
+                    ..slots.end.clamp(base, base + per_block) - base;
+                if !inner.is_empty()
+                    && *table != 0
+                    && self.unmap_table(*table, inner, &mut freed)?
+                {
+                    freed.push(*table);
+                    *table = 0;
+                    changed = true;
+                }
+            }
+            if tables.iter().all(|&table| table == 0) {
+                freed.push(outer);
+                inode.double_indirect_block = 0;
+            } else if changed {
+                self.write_table(outer, &tables)?;
+            }
+        }
+
+        inode.block_count = inode.block_count.saturating_sub(freed.len() as u32);
+        Ok(freed)
+    }
+
+    /// Unmap some entries of a pointer block.
+    ///
+    /// # Parameters
+    ///
+    /// * `table` - The pointer block.
+    /// * `slots` - The entries to unmap.
+    /// * `freed` - The list the unmapped blocks are appended to.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - Whether the pointer block is empty, it's left unchanged then,
+    ///   as it's freed with its blocks.
+    fn unmap_table(
+        &mut self,
+        table: u32,
+        slots: core::ops::Range<u64>,
+        freed: &mut Vec<u32>,
+    ) -> Result<bool, &'static str> {
+        let mut pointers = self.read_table(table)?;
+        let mut changed = false;
+        for slot in &mut pointers[slots.start as usize..slots.end as usize] {
+            if *slot != 0 {
+                freed.push(self.check_block_pointer(*slot)?);
+                *slot = 0;
+                changed = true;
+            }
+        }
+        if pointers.iter().all(|&block_num| block_num == 0) {
+            return Ok(true);
+        }
+        if changed {
+            self.write_table(table, &pointers)?;
+        }
+        Ok(false)
+    }
+
+    /// Read all the entries of a pointer block, checking the pointer to it and
+    /// its checksum.
+    fn read_table(&mut self, table: u32) -> Result<Vec<u32>, &'static str> {
+        let table = self.check_block_pointer(table)?;
+        let mut buf = alloc::vec![0u8; self.super_block.block_size as usize];
+        self.read_checksummed_block(table, &mut buf)?;
+        Ok(buf
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect())
+    }
+
+    /// Write all the entries of a pointer block, and its checksum.
+    fn write_table(&mut self, table: u32, pointers: &[u32]) -> Result<(), &'static str> {
+        let buf: Vec<u8> = pointers.iter().flat_map(|ptr| ptr.to_le_bytes()).collect();
+        self.write_checksummed_block(table, &buf)
+    }
+
+    /// Find the block storing a block of a file.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode of the file.
+    /// * `index` - The index of the block in the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The block number, or 0 if the block is not allocated.
+    /// * `Err(&'static str)` - If the device can't be read, or a pointer on the
+    ///   way is corrupted, see [`FileSystem::check_block_pointer`].
+    pub(crate) fn lookup_block(&mut self, inode: &Inode, index: u32) -> Result<u32, &'static str> {
+        let pointer = self.walk_block(inode, index, Some(&mut TableCache::default()))?;
+        Ok(self.pointer_block(pointer))
+    }
+
+    /// Find the block storing the data of a block of a file, see
+    /// [`FileSystem::lookup_block`].
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode of the file.
+    /// * `index` - The index of the block in the file.
+    /// * `tables` - The pointer blocks verified by the previous lookups of the
+    ///   same read.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The block number, or 0 if the block is not allocated or unwritten.
+    fn lookup_data_block(
+        &mut self,
+        inode: &Inode,
+        index: u32,
+        tables: &mut TableCache,
+    ) -> Result<u32, &'static str> {
+        let pointer = self.walk_block(inode, index, Some(tables))?;
+        if self.is_unwritten(pointer) {
+            return Ok(0);
+        }
+        Ok(pointer)
+    }
+
+    /// Find the block storing a block of a file, without checking the pointers,
+    /// so the checker can report the broken ones.
+    ///
+    /// A pointer block outside the data area isn't followed, and reads as holes.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode of the file.
+    /// * `index` - The index of the block in the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The block pointer as stored, with its unwritten flag, or 0 if
+    ///   the block is not allocated.
+    pub(crate) fn find_block(&mut self, inode: &Inode, index: u32) -> Result<u32, &'static str> {
+        self.walk_block(inode, index, None)
+    }
+
+    /// Follow the pointers to a block of a file, see [`FileSystem::lookup_block`]
+    /// and [`FileSystem::find_block`], and get its pointer as stored.
+    ///
+    /// The pointers are checked if `tables` is given, with the verified tables
+    /// kept there.
+    fn walk_block(
+        &mut self,
+        inode: &Inode,
+        index: u32,
+        mut tables: Option<&mut TableCache>,
+    ) -> Result<u32, &'static str> {
+        let per_block = (self.super_block.block_size / 4) as u64;
+        let index = index as u64;
+        let direct = DIRECT_BLOCKS as u64;
+        let mut read = |fs: &mut Self, table: u32, slot: u32| match tables.as_deref_mut() {
+            Some(tables) => fs.read_cached_pointer(tables, table, slot),
+            None => fs.read_entry(table, slot),
+        };
+
+        if index < direct {
+            let pointer = inode.direct_blocks[index as usize];
+            if tables.is_some() {
+                self.check_block_pointer(pointer)?;
+            }
+            Ok(pointer)
+        } else if index < direct + per_block {
+            read(self, inode.indirect_block, (index - direct) as u32)
+        } else if index < direct + per_block + per_block * per_block {
+            let index = index - direct - per_block;
+            let table = read(
+                self,
+                inode.double_indirect_block,
+                (index / per_block) as u32,
+            )?;
+            read(self, table, (index % per_block) as u32)
+        } else {
+            Err(error::FILE_TOO_LARGE)
+        }
+    }
+
+    /// List the blocks owned by a file, including the pointer blocks.
+    ///
+    /// Pointer blocks outside the data area are listed but not followed, so the
+    /// checker can walk broken files too.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode of the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u32>)` - The block numbers without their unwritten flags, in
+    ///   mapping order.
+    pub(crate) fn file_blocks(&mut self, inode: &Inode) -> Result<Vec<u32>, &'static str> {
+        let mut blocks: Vec<u32> = inode
+            .direct_blocks
+            .iter()
+            .filter(|&&pointer| pointer != 0)
+            .map(|&pointer| self.pointer_block(pointer))
+            .collect();
+        if inode.indirect_block != 0 {
+            blocks.push(inode.indirect_block);
+            self.table_blocks(inode.indirect_block, &mut blocks)?;
+        }
+        if inode.double_indirect_block != 0 {
+            blocks.push(inode.double_indirect_block);
+            let mut tables = Vec::new();
+            self.table_blocks(inode.double_indirect_block, &mut tables)?;
+            for table in tables {
+                blocks.push(table);
+                self.table_blocks(table, &mut blocks)?;
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Append the non-zero entries of a pointer block, if it's in the data area.
+    fn table_blocks(&mut self, table: u32, blocks: &mut Vec<u32>) -> Result<(), &'static str> {
+        if !self.is_data_block(table) {
+            return Ok(());
+        }
+        let mut buf = alloc::vec![0u8; self.super_block.block_size as usize];
+        self.block_device.read_block(table, 0, &mut buf)?;
+        blocks.extend(
+            buf.chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .filter(|&pointer| pointer != 0)
+                .map(|pointer| self.pointer_block(pointer)),
+        );
+        Ok(())
+    }
+
+    /// Find the block storing a block of a file, allocating it if needed.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode of the file, updated with the new blocks.
+    /// * `index` - The index of the block in the file.
+    /// * `claimed` - The block to map if it's not allocated, already marked used in
+    ///   the bitmap and mapped unwritten if the file system can, or `None` to
+    ///   allocate one.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((u32, BlockState))` - The block number, and the state of the block.
+    fn map_block(
+        &mut self,
+        inode: &mut Inode,
+        index: u32,
+        claimed: Option<u32>,
+    ) -> Result<(u32, BlockState), &'static str> {
+        let per_block = (self.super_block.block_size / 4) as u64;
+        let index = index as u64;
+        let direct = DIRECT_BLOCKS as u64;
+
+        if index < direct {
+            let pointer = inode.direct_blocks[index as usize];
+            let block_num = self.check_block_pointer(pointer)?;
+            if block_num != 0 {
+                return Ok((block_num, self.block_state(pointer)));
+            }
+            let (pointer, state) = self.new_block(claimed)?;
+            inode.direct_blocks[index as usize] = pointer;
+            inode.block_count += 1;
+            Ok((pointer & !UNWRITTEN_FLAG, state))
+        } else if index < direct + per_block {
+            let table = self.map_table(inode, |inode| &mut inode.indirect_block)?;
+            self.map_pointer(inode, table, (index - direct) as u32, claimed)
+        } else if index < direct + per_block + per_block * per_block {
+            let index = index - direct - per_block;
+            let outer = self.map_table(inode, |inode| &mut inode.double_indirect_block)?;
+            let table = self.map_child_table(inode, outer, (index / per_block) as u32)?;
+            self.map_pointer(inode, table, (index % per_block) as u32, claimed)
+        } else {
+            Err(error::FILE_TOO_LARGE)
+        }
+    }
+
+    /// Get a pointer block referenced by the inode, allocating an empty one if needed.
+    fn map_table(
+        &mut self,
+        inode: &mut Inode,
+        field: fn(&mut Inode) -> &mut u32,
+    ) -> Result<u32, &'static str> {
+        if *field(inode) == 0 {
+            let block_num = self.alloc_block()?;
+            self.zero_block(block_num)?;
+            *field(inode) = block_num;
+            inode.block_count += 1;
+        }
+        self.check_block_pointer(*field(inode))
+    }
+
+    /// Get a pointer block listed in the double indirect block, allocating an empty
+    /// one if needed.
+    ///
+    /// The new block is zeroed before it's listed, so an interrupted write never
+    /// leaves its old content mapped as pointers.
+    fn map_child_table(
+        &mut self,
+        inode: &mut Inode,
+        outer: u32,
+        slot: u32,
+    ) -> Result<u32, &'static str> {
+        let table = self.read_pointer(outer, slot)?;
+        if table != 0 {
+            return Ok(table);
+        }
+        let table = self.alloc_block()?;
+        self.zero_block(table)?;
+        self.write_checksummed(outer, slot * 4, &table.to_le_bytes())?;
+        inode.block_count += 1;
+        Ok(table)
+    }
+
+    /// Get an entry of a pointer block, allocating a block for it if needed, or
+    /// using the `claimed` one, see [`FileSystem::map_block`].
+    fn map_pointer(
+        &mut self,
+        inode: &mut Inode,
+        table: u32,
+        slot: u32,
+        claimed: Option<u32>,
+    ) -> Result<(u32, BlockState), &'static str> {
+        let pointer = self.read_pointer(table, slot)?;
+        if pointer != 0 {
+            return Ok((self.pointer_block(pointer), self.block_state(pointer)));
+        }
+        let (pointer, state) = self.new_block(claimed)?;
+        self.write_checksummed(table, slot * 4, &pointer.to_le_bytes())?;
+        inode.block_count += 1;
+        Ok((pointer & !UNWRITTEN_FLAG, state))
+    }
+
+    /// Get the pointer to a new block of a file, see [`FileSystem::map_block`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((u32, BlockState))` - The pointer to store, and the state of the block.
+    fn new_block(&mut self, claimed: Option<u32>) -> Result<(u32, BlockState), &'static str> {
+        match claimed {
+            Some(block_num) if self.has_unwritten() => {
+                Ok((block_num | UNWRITTEN_FLAG, BlockState::Unwritten))
+            }
+            // Zeroed by the caller.
+            Some(block_num) => Ok((block_num, BlockState::Written)),
+            None => Ok((self.alloc_block()?, BlockState::Fresh)),
+        }
+    }
+
+    /// Get the state of a block from its pointer, which isn't a new one.
+    fn block_state(&self, pointer: u32) -> BlockState {
+        if self.is_unwritten(pointer) {
+            BlockState::Unwritten
+        } else {
+            BlockState::Written
+        }
+    }
+
+    /// Clear the unwritten flag of a block of a file, once its data is written.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode of the file, updated if the block is a direct one.
+    /// * `index` - The index of the block in the file, which must be mapped.
+    fn mark_written(&mut self, inode: &mut Inode, index: u32) -> Result<(), &'static str> {
+        let per_block = (self.super_block.block_size / 4) as u64;
+        let index = index as u64;
+        let direct = DIRECT_BLOCKS as u64;
+
+        let (table, slot) = if index < direct {
+            inode.direct_blocks[index as usize] &= !UNWRITTEN_FLAG;
+            return Ok(());
+        } else if index < direct + per_block {
+            (inode.indirect_block, index - direct)
+        } else {
+            let index = index - direct - per_block;
+            let outer = inode.double_indirect_block;
+            (
+                self.read_pointer(outer, (index / per_block) as u32)?,
+                index % per_block,
+            )
+        };
+        let pointer = self.read_pointer(table, slot as u32)? & !UNWRITTEN_FLAG;
+        self.write_checksummed(table, slot as u32 * 4, &pointer.to_le_bytes())
+    }
+
+    /// Read an entry of a pointer block, a missing table reads as 0.
+    ///
+    /// Both the pointer to the table and the entry are checked, see
+    /// [`FileSystem::check_block_pointer`], and so is the checksum of the table.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The entry as stored, with its unwritten flag.
+    fn read_pointer(&mut self, table: u32, slot: u32) -> Result<u32, &'static str> {
+        let table = self.check_block_pointer(table)?;
+        if table == 0 {
+            return Ok(0);
+        }
+        let pointer = if self.has_metadata_csum() {
+            // The checksum covers the whole table.
+            self.read_table(table)?[slot as usize]
+        } else {
+            self.read_entry(table, slot)?
+        };
+        self.check_block_pointer(pointer)?;
+        Ok(pointer)
+    }
+
+    /// Read an entry of a pointer block like [`FileSystem::read_pointer`], reusing
+    /// the tables already verified.
+    fn read_cached_pointer(
+        &mut self,
+        tables: &mut TableCache,
+        table: u32,
+        slot: u32,
+    ) -> Result<u32, &'static str> {
+        if !self.has_metadata_csum() {
+            // A single entry is read, there's nothing to verify twice.
+            return self.read_pointer(table, slot);
+        }
+        let table = self.check_block_pointer(table)?;
+        if table == 0 {
+            return Ok(0);
+        }
+        let entry = match tables
+            .tables
+            .iter()
+            .position(|&(cached, _)| cached == table)
+        {
+            Some(i) => tables.tables.remove(i),
+            None => (table, self.read_table(table)?),
+        };
+        let pointer = entry.1[slot as usize];
+        tables.tables.push(entry);
+        if tables.tables.len() > TableCache::CAPACITY {
+            tables.tables.remove(0);
+        }
+        self.check_block_pointer(pointer)?;
+        Ok(pointer)
+    }
+
+    /// Read an entry of a pointer block without checking it, a missing table or
+    /// one outside the data area reads as 0.
+    fn read_entry(&mut self, table: u32, slot: u32) -> Result<u32, &'static str> {
+        if !self.is_data_block(table) {
+            return Ok(0);
+        }
+        let mut buf = [0u8; 4];
+        self.block_device.read_block(table, slot * 4, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Fill a pointer block with zeroes, and update its checksum.
+    fn zero_block(&mut self, block_num: u32) -> Result<(), &'static str> {
+        let zero = alloc::vec![0u8; self.super_block.block_size as usize];
+        self.write_checksummed_block(block_num, &zero)
+    }
+}
@@ -0,0 +1,522 @@
+//! The open files, which read and write a file at a position like a file descriptor.
+//!
+//! [`FileSystem::open`] gives a [`FileHandle`], which holds the file open (see
+//! [`FileSystem::open_inode`]) until it's closed, so it can still be used once the
+//! file is unlinked.
+//!
+//! A handle only knows its file and its position: every operation borrows the file
+//! system, so several files can be open at once and the file system stays usable
+//! between the operations. `FileHandle::io` binds a handle to the file system for
+//! a while, to get `std::io::{Read, Write, Seek}` under the `std` feature, and the
+//! `embedded_io` traits under the `embedded-io` feature, so the parsers built on
+//! them (ELF loaders, config readers...) can read the files directly.
+//!
+//! # Example
+//!
+//! ```rust
+//! use proka_fs::FileSystem;
+//! use proka_fs::handle::{OpenOptions, SeekFrom};
+//! use proka_fs::mkfs::{FormatOptions, format};
+//! use proka_fs::ram::RamBlockDevice;
+//!
+//! let mut bd = RamBlockDevice::new(vec![0u8; 1024 * 1024]);
+//! # let options = FormatOptions {
+//! #     uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+//! #     ..FormatOptions::default()
+//! # };
+//! format(&mut bd, 1024 * 1024, &options).unwrap();
+//! let mut fs = FileSystem::mount(bd).unwrap();
+//!
+//! let create = OpenOptions::new().write(true).create(true);
+//! let mut hello = fs.open("/hello.txt", &create).unwrap();
+//! let mut log = fs.open("/log.txt", &create).unwrap();
+//! hello.write(&mut fs, b"hello world").unwrap();
+//! log.write(&mut fs, b"hello.txt written").unwrap();
+//! hello.close(&mut fs).unwrap();
+//! log.close(&mut fs).unwrap();
+//!
+//! let mut file = fs.open("/hello.txt", &OpenOptions::new().read(true)).unwrap();
+//! file.seek(&mut fs, SeekFrom::Start(6)).unwrap();
+//! let mut buf = [0u8; 5];
+//! file.read(&mut fs, &mut buf).unwrap();
+//! assert_eq!(&buf, b"world");
+//! file.close(&mut fs).unwrap();
+//! ```
+
+use crate::definition::{FileType, MAX_FILE_LENGTH};
+use crate::{BlockDevice, FileSystem, Metadata, error};
+
+/// How a file is opened, see [`FileSystem::open`].
+///
+/// It follows `std::fs::OpenOptions`: nothing is allowed by default, at least
+/// one of [`OpenOptions::read`], [`OpenOptions::write`] and [`OpenOptions::append`]
+/// must be set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    /// Whether the file can be read.
+    pub read: bool,
+
+    /// Whether the file can be written.
+    pub write: bool,
+
+    /// Whether every write goes to the end of the file, which allows writing.
+    pub append: bool,
+
+    /// Whether the file is emptied when it's opened, which needs `write`.
+    pub truncate: bool,
+
+    /// Whether the file is created if it doesn't exist, which needs `write` or `append`.
+    pub create: bool,
+
+    /// Whether the file must be created, failing if it exists. `create` and
+    /// `truncate` are ignored then.
+    pub create_new: bool,
+}
+
+impl OpenOptions {
+    /// Create the options allowing nothing, to be set by the other methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set [`OpenOptions::read`].
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// Set [`OpenOptions::write`].
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+
+    /// Set [`OpenOptions::append`].
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Set [`OpenOptions::truncate`].
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Set [`OpenOptions::create`].
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Set [`OpenOptions::create_new`].
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Check that the options make sense together.
+    fn validate(&self) -> Result<(), &'static str> {
+        let writable = self.write || self.append;
+        if !writable && (!self.read || self.create || self.create_new)
+            || self.truncate && (!self.write || self.append) && !self.create_new
+        {
+            return Err("Invalid open options");
+        }
+        Ok(())
+    }
+}
+
+/// Where to move the position of a [`FileHandle`] to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// This number of bytes from the start of the file.
+    Start(u64),
+
+    /// This number of bytes from the end of the file.
+    End(i64),
+
+    /// This number of bytes from the current position.
+    Current(i64),
+}
+
+/// An open file, see [`FileSystem::open`].
+///
+/// It's released by [`FileHandle::close`], or when the file system is unmounted.
+/// Dropping it keeps the file open until then, so an unlinked file keeps its blocks.
+#[derive(Debug, PartialEq, Eq)]
+#[must_use = "the file stays open until the handle is closed"]
+pub struct FileHandle {
+    /// The inode of the file.
+    inode_id: u32,
+
+    /// The byte offset of the next read or write.
+    pos: u64,
+
+    /// How the file was opened.
+    options: OpenOptions,
+}
+
+/// A [`FileHandle`] bound to its file system, which implements the I/O traits, see
+/// [`FileHandle::io`].
+#[cfg(any(feature = "std", feature = "embedded-io"))]
+pub struct HandleIo<'a, B: BlockDevice> {
+    /// The file system of the file.
+    fs: &'a mut FileSystem<B>,
+
+    /// The open file.
+    handle: &'a mut FileHandle,
+}
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Open a file.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path of the file, relative to the root directory.
+    /// * `options` - How to open the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(FileHandle)` - The open file, at position 0.
+    /// * `Err(&'static str)` - If the options are invalid, the file doesn't exist
+    ///   and isn't created, exists and must be created, is a directory, or is
+    ///   opened for writing on a read-only file system.
+    ///
+    /// # Note
+    ///
+    /// The handle doesn't borrow the file system, which is given back to each of
+    /// its operations instead. It must be used with the file system it was opened on.
+    pub fn open(&mut self, path: &str, options: &OpenOptions) -> Result<FileHandle, &'static str> {
+        options.validate()?;
+        let writable = options.write || options.append;
+        if writable {
+            self.check_writable()?;
+        }
+
+        /* Stage 1: Find the file, or create it */
+        let inode_id = match self.lookup(path) {
+            Ok(_) if options.create_new => return Err(error::EXISTS),
+            Ok(inode_id) => inode_id,
+            Err(error::NOT_FOUND) if options.create || options.create_new => {
+                let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+                let parent_inode_id = self.lookup(parent)?;
+                self.mkfile(parent_inode_id, name)?;
+                self.lookup_in(parent_inode_id, name)?
+            }
+            Err(e) => return Err(e),
+        };
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        if inode.file_type == FileType::Directory {
+            return Err(error::IS_A_DIRECTORY);
+        }
+
+        /* Stage 2: Empty it if asked */
+        if options.truncate && !options.create_new && inode.file_length > 0 {
+            let result = self.set_length(&mut inode, 0);
+            self.sync_write(result)?;
+        }
+
+        self.open_inode(inode_id)?;
+        Ok(FileHandle {
+            inode_id,
+            pos: 0,
+            options: *options,
+        })
+    }
+}
+
+impl FileHandle {
+    /// Get the inode of the file.
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+
+    /// Get the byte offset of the next read or write.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Get the metadata of the file.
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file.
+    pub fn metadata<B: BlockDevice>(
+        &self,
+        fs: &mut FileSystem<B>,
+    ) -> Result<Metadata, &'static str> {
+        fs.stat(self.inode_id)
+    }
+
+    /// Read from the position, and move it after the data read.
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file.
+    /// * `buf` - The buffer to store the data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of bytes read, which is 0 at the end of the file.
+    /// * `Err(&'static str)` - If the file isn't open for reading, or can't be read.
+    pub fn read<B: BlockDevice>(
+        &mut self,
+        fs: &mut FileSystem<B>,
+        buf: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        if !self.options.read {
+            return Err("File not open for reading");
+        }
+        let read = fs.read_at(self.inode_id, self.pos, buf)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+
+    /// Write at the position, or at the end of the file in append mode, and move
+    /// the position after the data written.
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file.
+    /// * `data` - The data to write.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of bytes written.
+    /// * `Err(&'static str)` - If the file isn't open for writing, or can't be written.
+    pub fn write<B: BlockDevice>(
+        &mut self,
+        fs: &mut FileSystem<B>,
+        data: &[u8],
+    ) -> Result<usize, &'static str> {
+        if !self.options.write && !self.options.append {
+            return Err("File not open for writing");
+        }
+        if self.options.append {
+            self.pos = self.metadata(fs)?.size;
+        }
+        let written = fs.write_at(self.inode_id, self.pos, data)?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
+    /// Change the length of the file, see [`FileSystem::truncate`]. The position
+    /// doesn't move.
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file.
+    /// * `len` - The new length in bytes.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file has its new length.
+    /// * `Err(&'static str)` - If the file isn't open for writing, or can't be resized.
+    pub fn set_len<B: BlockDevice>(
+        &self,
+        fs: &mut FileSystem<B>,
+        len: u64,
+    ) -> Result<(), &'static str> {
+        if !self.options.write && !self.options.append {
+            return Err("File not open for writing");
+        }
+        fs.truncate(self.inode_id, len)
+    }
+
+    /// Move the position, which may go past the end of the file: a write there
+    /// leaves a hole, which reads as zeroes.
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file, to find its end.
+    /// * `pos` - Where to move the position to.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The new position, from the start of the file.
+    /// * `Err(&'static str)` - [`error::INVALID_SEEK`] if the position would be
+    ///   negative or past [`MAX_FILE_LENGTH`], it doesn't move then.
+    pub fn seek<B: BlockDevice>(
+        &mut self,
+        fs: &mut FileSystem<B>,
+        pos: SeekFrom,
+    ) -> Result<u64, &'static str> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(offset) => (self.metadata(fs)?.size, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base
+            .checked_add_signed(offset)
+            .filter(|&pos| pos <= MAX_FILE_LENGTH)
+            .ok_or(error::INVALID_SEEK)?;
+        Ok(self.pos)
+    }
+
+    /// Write the changes to the block device, see [`FileSystem::sync`].
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file.
+    pub fn flush<B: BlockDevice>(&self, fs: &mut FileSystem<B>) -> Result<(), &'static str> {
+        fs.sync()
+    }
+
+    /// Close the file.
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file is closed.
+    /// * `Err(&'static str)` - If the file was unlinked and can't be released.
+    pub fn close<B: BlockDevice>(self, fs: &mut FileSystem<B>) -> Result<(), &'static str> {
+        fs.close_inode(self.inode_id)
+    }
+
+    /// Bind the handle to its file system, to use it through the I/O traits of the
+    /// `std` and `embedded-io` features.
+    ///
+    /// # Parameters
+    ///
+    /// * `fs` - The file system of the file.
+    ///
+    /// # Returns
+    ///
+    /// * `HandleIo` - The handle and the file system, borrowed until it's dropped.
+    #[cfg(any(feature = "std", feature = "embedded-io"))]
+    pub fn io<'a, B: BlockDevice>(&'a mut self, fs: &'a mut FileSystem<B>) -> HandleIo<'a, B> {
+        HandleIo { fs, handle: self }
+    }
+}
+
+#[cfg(feature = "std")]
+mod std_io {
+    use super::{HandleIo, SeekFrom};
+    use crate::BlockDevice;
+    use crate::error::{self, ErrorKind};
+    use std::io;
+
+    /// Convert an error of the file system to an I/O error of the same kind.
+    fn io_error(error: &'static str) -> io::Error {
+        let kind = match error::kind(error) {
+            ErrorKind::NotFound => io::ErrorKind::NotFound,
+            ErrorKind::AlreadyExists => io::ErrorKind::AlreadyExists,
+            ErrorKind::ReadOnly => io::ErrorKind::ReadOnlyFilesystem,
+            ErrorKind::IsADirectory => io::ErrorKind::IsADirectory,
+            ErrorKind::StorageFull => io::ErrorKind::StorageFull,
+            ErrorKind::FileTooLarge => io::ErrorKind::FileTooLarge,
+            ErrorKind::InvalidInput => io::ErrorKind::InvalidInput,
+            ErrorKind::InvalidData => io::ErrorKind::InvalidData,
+            ErrorKind::Other => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, error)
+    }
+
+    impl<B: BlockDevice> io::Read for HandleIo<'_, B> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.handle.read(self.fs, buf).map_err(io_error)
+        }
+    }
+
+    impl<B: BlockDevice> io::Write for HandleIo<'_, B> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.handle.write(self.fs, buf).map_err(io_error)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.handle.flush(self.fs).map_err(io_error)
+        }
+    }
+
+    impl<B: BlockDevice> io::Seek for HandleIo<'_, B> {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.handle.seek(self.fs, pos.into()).map_err(io_error)
+        }
+    }
+
+    impl From<io::SeekFrom> for SeekFrom {
+        fn from(pos: io::SeekFrom) -> Self {
+            match pos {
+                io::SeekFrom::Start(pos) => Self::Start(pos),
+                io::SeekFrom::End(offset) => Self::End(offset),
+                io::SeekFrom::Current(offset) => Self::Current(offset),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+pub use embedded::IoError;
+
+#[cfg(feature = "embedded-io")]
+mod embedded {
+    use super::{HandleIo, SeekFrom};
+    use crate::BlockDevice;
+    use crate::error::{self, ErrorKind};
+
+    /// An error of the file system, as an `embedded_io` error.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IoError(pub &'static str);
+
+    impl core::fmt::Display for IoError {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl embedded_io::Error for IoError {
+        fn kind(&self) -> embedded_io::ErrorKind {
+            match error::kind(self.0) {
+                ErrorKind::NotFound => embedded_io::ErrorKind::NotFound,
+                ErrorKind::AlreadyExists => embedded_io::ErrorKind::AlreadyExists,
+                ErrorKind::ReadOnly => embedded_io::ErrorKind::PermissionDenied,
+                ErrorKind::StorageFull => embedded_io::ErrorKind::OutOfMemory,
+                ErrorKind::InvalidInput => embedded_io::ErrorKind::InvalidInput,
+                ErrorKind::InvalidData => embedded_io::ErrorKind::InvalidData,
+                ErrorKind::IsADirectory | ErrorKind::FileTooLarge | ErrorKind::Other => {
+                    embedded_io::ErrorKind::Other
+                }
+            }
+        }
+    }
+
+    impl<B: BlockDevice> embedded_io::ErrorType for HandleIo<'_, B> {
+        type Error = IoError;
+    }
+
+    impl<B: BlockDevice> embedded_io::Read for HandleIo<'_, B> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+            self.handle.read(self.fs, buf).map_err(IoError)
+        }
+    }
+
+    impl<B: BlockDevice> embedded_io::Write for HandleIo<'_, B> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+            self.handle.write(self.fs, buf).map_err(IoError)
+        }
+
+        fn flush(&mut self) -> Result<(), IoError> {
+            self.handle.flush(self.fs).map_err(IoError)
+        }
+    }
+
+    impl<B: BlockDevice> embedded_io::Seek for HandleIo<'_, B> {
+        fn seek(&mut self, pos: embedded_io::SeekFrom) -> Result<u64, IoError> {
+            self.handle.seek(self.fs, pos.into()).map_err(IoError)
+        }
+    }
+
+    impl From<embedded_io::SeekFrom> for SeekFrom {
+        fn from(pos: embedded_io::SeekFrom) -> Self {
+            match pos {
+                embedded_io::SeekFrom::Start(pos) => Self::Start(pos),
+                embedded_io::SeekFrom::End(offset) => Self::End(offset),
+                embedded_io::SeekFrom::Current(offset) => Self::Current(offset),
+            }
+        }
+    }
+}
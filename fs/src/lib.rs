@@ -2,11 +2,34 @@
 
 extern crate alloc;
 pub mod bitmap;
+pub mod cache;
+pub mod check;
+pub mod checksum;
+pub mod crc32;
+pub mod crc32c;
 pub mod definition;
+pub mod error;
+pub mod file;
+pub mod handle;
+pub mod metadata;
+pub mod mkfs;
+pub mod options;
+pub mod orphan;
+pub mod overlay;
+pub mod partition;
+pub mod ram;
+pub mod range;
+pub mod readonly;
+pub mod scrub;
+pub mod xattr;
 
 pub use bitmap::Bitmap;
+pub use checksum::ChecksumPolicy;
+pub use metadata::{Metadata, StatFs};
+pub use options::MountOptions;
 
 use crate::definition::Inode;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 
 #[cfg(feature = "std")]
@@ -39,6 +62,163 @@ pub trait BlockDevice {
     /// * `block_num` - The block number to write.
     /// * `buf` - The data to write.
     fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str>;
+
+    /// Read several contiguous blocks.
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The first block number to read.
+    /// * `buf` - The buffer to store the data, whose length is a multiple of the block size.
+    fn read_blocks(&mut self, block_num: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+        let block_size = self.block_size() as usize;
+        for (i, chunk) in buf.chunks_mut(block_size).enumerate() {
+            self.read_block(block_num + i as u32, 0, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Write several contiguous blocks.
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The first block number to write.
+    /// * `buf` - The data to write, whose length is a multiple of the block size.
+    fn write_blocks(&mut self, block_num: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let block_size = self.block_size() as usize;
+        for (i, chunk) in buf.chunks(block_size).enumerate() {
+            self.write_block(block_num + i as u32, 0, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Read contiguous blocks into several buffers (scatter).
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The first block number to read.
+    /// * `bufs` - The buffers filled one after another, each one's length is a
+    ///   multiple of the block size.
+    fn read_blocks_vectored(
+        &mut self,
+        block_num: u32,
+        bufs: &mut [&mut [u8]],
+    ) -> Result<(), &'static str> {
+        let block_size = self.block_size() as usize;
+        let mut block_num = block_num;
+        for buf in bufs {
+            self.read_blocks(block_num, buf)?;
+            block_num += (buf.len() / block_size) as u32;
+        }
+        Ok(())
+    }
+
+    /// Write several buffers to contiguous blocks (gather).
+    ///
+    /// # Parameters
+    ///
+    /// * `block_num` - The first block number to write.
+    /// * `bufs` - The buffers written one after another, each one's length is a
+    ///   multiple of the block size.
+    fn write_blocks_vectored(
+        &mut self,
+        block_num: u32,
+        bufs: &[&[u8]],
+    ) -> Result<(), &'static str> {
+        let block_size = self.block_size() as usize;
+        let mut block_num = block_num;
+        for buf in bufs {
+            self.write_blocks(block_num, buf)?;
+            block_num += (buf.len() / block_size) as u32;
+        }
+        Ok(())
+    }
+
+    /// Make sure all the written data has reached the storage.
+    ///
+    /// The default implementation does nothing, which fits devices writing synchronously.
+    fn flush(&mut self) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /// Tell the device that some blocks are unused, so it can release them
+    /// (TRIM on SSDs, erase on flash, holes in image files...).
+    ///
+    /// The content of discarded blocks is undefined until they are written again.
+    ///
+    /// # Parameters
+    ///
+    /// * `blocks` - The range of block numbers to discard.
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        let _ = blocks;
+        Ok(())
+    }
+
+    /// Get the number of blocks of the device, or `None` if it's unknown.
+    fn block_count(&mut self) -> Option<u32> {
+        None
+    }
+
+    /// Get the size of each block in bytes.
+    fn block_size(&self) -> u32 {
+        BLOCK_SIZE as u32
+    }
+}
+
+// A borrowed block device is a block device too, so one disk can be wrapped
+// several times (e.g. to open its partitions one after another).
+impl<B: BlockDevice + ?Sized> BlockDevice for &mut B {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        (**self).read_block(block_num, offset, buf)
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        (**self).write_block(block_num, offset, buf)
+    }
+
+    fn read_blocks(&mut self, block_num: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+        (**self).read_blocks(block_num, buf)
+    }
+
+    fn write_blocks(&mut self, block_num: u32, buf: &[u8]) -> Result<(), &'static str> {
+        (**self).write_blocks(block_num, buf)
+    }
+
+    fn read_blocks_vectored(
+        &mut self,
+        block_num: u32,
+        bufs: &mut [&mut [u8]],
+    ) -> Result<(), &'static str> {
+        (**self).read_blocks_vectored(block_num, bufs)
+    }
+
+    fn write_blocks_vectored(
+        &mut self,
+        block_num: u32,
+        bufs: &[&[u8]],
+    ) -> Result<(), &'static str> {
+        (**self).write_blocks_vectored(block_num, bufs)
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        (**self).flush()
+    }
+
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        (**self).discard(blocks)
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        (**self).block_count()
+    }
+
+    fn block_size(&self) -> u32 {
+        (**self).block_size()
+    }
 }
 
 #[cfg(feature = "std")]
@@ -69,8 +249,103 @@ impl BlockDevice for FileBlockDevice {
                 (block_num as u64 * BLOCK_SIZE as u64) + offset as u64,
             ))
             .map_err(|_| "Failed to seek to block")?;
-        self.0.write_all(buf).map_err(|_| "Failed to write block")?;
-        self.0.sync_all().map_err(|_| "Failed to sync block")
+        self.0.write_all(buf).map_err(|_| "Failed to write block")
+    }
+
+    fn read_blocks(&mut self, block_num: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+        // A single read, the file is contiguous.
+        self.read_block(block_num, 0, buf)
+    }
+
+    fn write_blocks(&mut self, block_num: u32, buf: &[u8]) -> Result<(), &'static str> {
+        self.write_block(block_num, 0, buf)
+    }
+
+    fn read_blocks_vectored(
+        &mut self,
+        block_num: u32,
+        bufs: &mut [&mut [u8]],
+    ) -> Result<(), &'static str> {
+        use std::io::IoSliceMut;
+
+        self.0
+            .seek(SeekFrom::Start(block_num as u64 * BLOCK_SIZE as u64))
+            .map_err(|_| "Failed to seek to block")?;
+        let mut slices: Vec<IoSliceMut> = bufs.iter_mut().map(|buf| IoSliceMut::new(buf)).collect();
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match self.0.read_vectored(slices) {
+                Ok(0) => return Err("Failed to read block"),
+                Ok(n) => IoSliceMut::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Err("Failed to read block"),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blocks_vectored(
+        &mut self,
+        block_num: u32,
+        bufs: &[&[u8]],
+    ) -> Result<(), &'static str> {
+        use std::io::IoSlice;
+
+        self.0
+            .seek(SeekFrom::Start(block_num as u64 * BLOCK_SIZE as u64))
+            .map_err(|_| "Failed to seek to block")?;
+        let mut slices: Vec<IoSlice> = bufs.iter().map(|buf| IoSlice::new(buf)).collect();
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match self.0.write_vectored(slices) {
+                Ok(0) => return Err("Failed to write block"),
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Err("Failed to write block"),
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.0.sync_data().map_err(|_| "Failed to sync block")
+    }
+
+    #[cfg(target_os = "linux")]
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        use std::os::fd::AsRawFd;
+
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        // Punch a hole, the file keeps its size and the range reads as zeroes.
+        let ret = unsafe {
+            libc::fallocate(
+                self.0.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                blocks.start as libc::off_t * BLOCK_SIZE as libc::off_t,
+                blocks.len() as libc::off_t * BLOCK_SIZE as libc::off_t,
+            )
+        };
+        if ret != 0 {
+            return Err("Failed to discard blocks");
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        // No portable way to punch holes, zero the blocks instead.
+        let zero = [0u8; BLOCK_SIZE];
+        for block_num in blocks {
+            self.write_block(block_num, 0, &zero)?;
+        }
+        Ok(())
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        let len = self.0.metadata().ok()?.len();
+        Some((len / BLOCK_SIZE as u64) as u32)
     }
 }
 
@@ -89,6 +364,7 @@ pub fn init_block_device(file_path: &str) -> Result<FileBlockDevice, String> {
         .read(true)
         .write(true)
         .create(true)
+        .truncate(false)
         .open(file_path)
         .map_err(|e| format!("Failed to open file: {}", e))?;
 
@@ -97,8 +373,14 @@ pub fn init_block_device(file_path: &str) -> Result<FileBlockDevice, String> {
 }
 
 /// The generic data in the file system.
-pub trait GenericFsData {
-    /// Create an inode object by a slice.
+///
+/// Every structure is encoded field by field in little-endian at fixed offsets,
+/// so the images are the same on every architecture.
+pub trait GenericFsData: Sized {
+    /// The size of the object on the disk in bytes.
+    const SIZE: usize;
+
+    /// Read the object from a slice, checking every field is valid.
     ///
     /// # Parameters
     ///
@@ -106,37 +388,27 @@ pub trait GenericFsData {
     ///
     /// # Returns
     ///
-    /// * `Self` - The inode object.
-    fn from_bytes(bytes: &[u8]) -> Option<&Self>
-    where
-        Self: Sized;
+    /// * `Some(Self)` - The object.
+    /// * `None` - If the slice is too short, or holds an invalid value.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
 
-    /// Get the object as a byte slice.
-    ///
-    /// # Returns
-    ///
-    /// * `&[u8]` - The object as a byte slice.
-    fn as_bytes(&self) -> &[u8];
-
-    /// Create this object by a mutable slice.
+    /// Write the object into a slice.
     ///
     /// # Parameters
     ///
-    /// * `buf` - The slice of bytes.
-    ///
-    /// # Returns
-    ///
-    /// * `Self` - The inode object.
-    fn from_mut_bytes(bytes: &mut [u8]) -> Option<&mut Self>
-    where
-        Self: Sized;
+    /// * `buf` - The slice of bytes, at least [`Self::SIZE`] long.
+    fn write_bytes(&self, buf: &mut [u8]);
 
-    /// Get the super block as a mutable byte slice.
+    /// Encode the object.
     ///
     /// # Returns
     ///
-    /// * `&mut [u8]` - The object as a mutable byte slice.
-    fn as_mut_bytes(&mut self) -> &mut [u8];
+    /// * `Vec<u8>` - The [`Self::SIZE`] bytes of the object.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = alloc::vec![0u8; Self::SIZE];
+        self.write_bytes(&mut buf);
+        buf
+    }
 }
 
 /// The basic structure of the whole file system.
@@ -150,6 +422,53 @@ pub struct FileSystem<B: BlockDevice> {
 
     /// The data start block number.
     pub data_start_block: u32,
+
+    /// The clock giving the current time, in seconds since the Unix epoch.
+    ///
+    /// It uses the system time under the `std` feature, otherwise it always
+    /// returns 0 until the kernel provides its own clock.
+    pub clock: fn() -> u64,
+
+    /// Whether the file system refuses every change.
+    ///
+    /// It's set on mount if the file system has read-only compatible features
+    /// this driver doesn't know, or is mounted with [`FileSystem::mount_read_only`]
+    /// or [`MountOptions::read_only`]. Some [`options::ErrorBehavior`] set it too.
+    pub read_only: bool,
+
+    /// The backup super block the file system was mounted from, if the primary
+    /// one was invalid.
+    ///
+    /// The primary super block is rewritten from it on the next sync.
+    pub backup_used: Option<u32>,
+
+    /// What to do when the checksum of some metadata doesn't match.
+    pub checksum_policy: ChecksumPolicy,
+
+    /// The last block found not matching its checksum, so the caller of a failed
+    /// operation can tell which block is corrupted.
+    ///
+    /// It's the block of the inode table for an inode, and 0 for the super block.
+    pub corrupted_block: Option<u32>,
+
+    /// What to do when the file system is found corrupted.
+    pub errors: options::ErrorBehavior,
+
+    /// When the access times are updated.
+    pub atime: options::AtimePolicy,
+
+    /// Whether the block device is flushed after every change, before it returns.
+    pub sync_writes: bool,
+
+    /// Whether the file system was cleanly unmounted before this mount, see
+    /// [`definition::SuperBlock::is_clean`].
+    pub was_clean: bool,
+
+    /// The number of holders of every open inode, see [`FileSystem::open_inode`].
+    open_inodes: BTreeMap<u32, u32>,
+
+    /// Whether [`FileSystem::unmount`] was already called.
+    unmounted: bool,
 }
 
 impl<B: BlockDevice> FileSystem<B> {
@@ -161,23 +480,203 @@ impl<B: BlockDevice> FileSystem<B> {
     ///
     /// # Returns
     ///
-    /// * `Self` - The mounted file system.
-    pub fn mount(mut bd: B) -> Self {
-        let mut super_block_buf = [0u8; core::mem::size_of::<definition::SuperBlock>()];
-        bd.read_block(0, 0, &mut super_block_buf).unwrap();
-        let super_block = definition::SuperBlock::from_bytes(&super_block_buf).unwrap();
+    /// * `Ok(Self)` - The mounted file system.
+    /// * `Err(&'static str)` - If the super block or the bitmap can't be read, the
+    ///   super block and its backups are invalid, or it has incompatible features
+    ///   this driver doesn't know.
+    ///
+    /// # Note
+    ///
+    /// The file system is mounted read-only if it has read-only compatible features
+    /// this driver doesn't know, see [`FileSystem::read_only`].
+    ///
+    /// If the primary super block is invalid and the device knows its size, the
+    /// backups are tried instead, see [`FileSystem::backup_used`]. If none is valid
+    /// and only the checksum of the primary is wrong, it's used as it is with
+    /// [`ChecksumPolicy::ReadOnly`], and the file system is mounted read-only.
+    ///
+    /// If the file system wasn't cleanly unmounted, the free counters are rebuilt,
+    /// see [`FileSystem::rebuild_counters`].
+    ///
+    /// Unless it's read-only, the file system is marked not clean on the device
+    /// until [`FileSystem::unmount`], and the orphans are released. See
+    /// [`FileSystem::mount_read_only`] to never write to the device.
+    pub fn mount(bd: B) -> Result<Self, &'static str> {
+        Self::mount_with(bd, &MountOptions::default())
+    }
+
+    /// Mount the file system with some options.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device driver.
+    /// * `options` - The mount options, which already apply while mounting.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The mounted file system.
+    /// * `Err(&'static str)` - See [`FileSystem::mount`], or if the journal mode
+    ///   isn't supported, or [`MountOptions::cache_blocks`] is given (see
+    ///   [`FileSystem::mount_cached`] for a block cache).
+    pub fn mount_with(mut bd: B, options: &MountOptions) -> Result<Self, &'static str> {
+        if options.journal != options::JournalMode::None {
+            return Err("Unsupported journal mode");
+        }
+        if options.cache_blocks.is_some() {
+            return Err("Unsupported block cache");
+        }
+        let mut checksum_mismatch = false;
+        let (super_block, backup_used) = match Self::read_super_block(&mut bd, 0, true) {
+            Ok(super_block) => (super_block, None),
+            Err(e) => match Self::read_backup_super_block(&mut bd) {
+                Some((super_block, block)) => (super_block, Some(block)),
+                // Without a backup, the policy lets a primary with a bad checksum through.
+                None if options.checksum_policy == ChecksumPolicy::ReadOnly => {
+                    let super_block = Self::read_super_block(&mut bd, 0, false).map_err(|_| e)?;
+                    checksum_mismatch = true;
+                    (super_block, None)
+                }
+                None => return Err(e),
+            },
+        };
+        let unknown = super_block.features().unknown();
+        if unknown.incompat != 0 {
+            return Err("Unsupported incompatible features");
+        }
 
-        Self {
+        let mut fs = Self {
             block_device: bd,
-            super_block: *super_block,
+            super_block,
             data_start_block: super_block.data_start_block,
+            clock: default_clock,
+            read_only: options.read_only || unknown.ro_compat != 0 || checksum_mismatch,
+            backup_used,
+            checksum_policy: options.checksum_policy,
+            corrupted_block: checksum_mismatch.then_some(0),
+            errors: options.errors,
+            atime: options.atime,
+            sync_writes: options.sync,
+            was_clean: super_block.is_clean(),
+            open_inodes: BTreeMap::new(),
+            unmounted: false,
+        };
+
+        // The counters are only written on sync, so they may be stale after a crash.
+        // A clean file system has them right, or the check reports them.
+        if !fs.was_clean {
+            fs.rebuild_counters()?;
+        }
+        if !fs.read_only {
+            fs.super_block.last_mounted = (fs.clock)();
+            fs.super_block.mount_count = fs.super_block.mount_count.saturating_add(1);
+            fs.release_orphans()?;
+            fs.super_block.state &= !definition::STATE_CLEAN;
+            fs.write_super_block()?;
         }
+        Ok(fs)
+    }
+
+    /// Unmount the file system.
+    ///
+    /// The inodes still open are closed, the orphans are released, and the file
+    /// system is synced and marked clean. It's also done when the file system is
+    /// dropped, but the errors are lost then.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file system is cleanly unmounted.
+    /// * `Err(&'static str)` - If it can't be written, it's then left not clean.
+    pub fn unmount(mut self) -> Result<(), &'static str> {
+        self.unmounted = true;
+        self.close_all()
+    }
+
+    /// Close everything and mark the file system clean, see [`FileSystem::unmount`].
+    fn close_all(&mut self) -> Result<(), &'static str> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.open_inodes.clear();
+        self.release_orphans()?;
+        self.super_block.state |= definition::STATE_CLEAN;
+        self.sync()
     }
 
     /// Synchronize the file system to the block device.
+    ///
+    /// Nothing is written if the file system is read-only.
     pub fn sync(&mut self) -> Result<(), &'static str> {
+        if self.read_only {
+            return Ok(());
+        }
+        let bytes = self.super_block.to_bytes();
+        self.block_device.write_block(0, 0, &bytes)?;
+        for block in self.super_block.backup_blocks() {
+            self.block_device.write_block(block, 0, &bytes)?;
+        }
+        self.block_device.flush()?;
+        self.backup_used = None;
+        Ok(())
+    }
+
+    /// Write the super block where it was read from, without its backups.
+    pub(crate) fn write_super_block(&mut self) -> Result<(), &'static str> {
+        let bytes = self.super_block.to_bytes();
         self.block_device
-            .write_block(0, 0, &self.super_block.as_bytes())
+            .write_block(self.backup_used.unwrap_or(0), 0, &bytes)
+    }
+
+    /// Read and check a super block.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device driver.
+    /// * `block` - The block of the super block.
+    /// * `checked` - Whether its checksum must match.
+    fn read_super_block(
+        bd: &mut B,
+        block: u32,
+        checked: bool,
+    ) -> Result<definition::SuperBlock, &'static str> {
+        let mut super_block_buf = [0u8; definition::SuperBlock::SIZE];
+        bd.read_block(block, 0, &mut super_block_buf)?;
+        let super_block = if checked {
+            definition::SuperBlock::from_bytes(&super_block_buf)
+        } else {
+            definition::SuperBlock::from_bytes_unchecked(&super_block_buf)
+        }
+        .ok_or("Invalid super block")?;
+        super_block.validate()?;
+        if bd
+            .block_count()
+            .is_some_and(|count| count < super_block.total_block)
+        {
+            return Err("The device is smaller than the file system");
+        }
+        Ok(super_block)
+    }
+
+    /// Find a valid backup super block, at the places given by the size of the device.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device driver.
+    ///
+    /// # Returns
+    ///
+    /// * `Some((SuperBlock, u32))` - The backup super block, and its block.
+    /// * `None` - If the device size is unknown, or no backup is valid.
+    fn read_backup_super_block(bd: &mut B) -> Option<(definition::SuperBlock, u32)> {
+        let device_size = bd.block_count()? as u64 * BLOCK_SIZE as u64;
+        let layout = definition::SuperBlock::new(device_size);
+        layout.backup_blocks().into_iter().find_map(|block| {
+            let super_block = Self::read_super_block(bd, block, true).ok()?;
+            // A file system smaller than the device has its backups elsewhere.
+            super_block
+                .backup_blocks()
+                .contains(&block)
+                .then_some((super_block, block))
+        })
     }
 
     /// Get the max inode (which means the file we can store in this fs)
@@ -193,75 +692,315 @@ impl<B: BlockDevice> FileSystem<B> {
     /// # Example
     ///
     /// ```no_run
-    /// use proka_fs:FileSystem;
+    /// use proka_fs::{FileSystem, init_block_device};
     ///
     /// let bd = init_block_device("test.img").unwrap();
     ///
-    /// let fs = FileSystem::mount(bd);
+    /// let fs = FileSystem::mount(bd).unwrap();
     /// let max_inode = fs.get_max_inode();
     /// let max_inode_id = max_inode - 1;
     /// ```
     pub fn get_max_inode(&self) -> usize {
-        ((self.data_start_block - 1) as usize * self.super_block.block_size as usize)
-            / core::mem::size_of::<definition::Inode>()
+        self.super_block.inode_count() as usize
+    }
+
+    /// Recount the free blocks and free inodes from the block bitmap and the inode table.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(bool)` - Whether the counters in the super block were wrong and got fixed.
+    /// * `Err(&'static str)` - If the bitmap or the inode table can't be read.
+    pub fn rebuild_counters(&mut self) -> Result<bool, &'static str> {
+        let (free_blocks, free_inodes) = self.count_free()?;
+        let changed = free_blocks != self.super_block.free_blocks
+            || free_inodes != self.super_block.free_inodes;
+        self.super_block.free_blocks = free_blocks;
+        self.super_block.free_inodes = free_inodes;
+        Ok(changed)
+    }
+
+    /// Count the free blocks and the free inodes, see [`FileSystem::rebuild_counters`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((u32, u32))` - The number of free blocks and of free inodes.
+    /// * `Err(&'static str)` - If the bitmap or the inode table can't be read.
+    pub(crate) fn count_free(&mut self) -> Result<(u32, u32), &'static str> {
+        let block_size = self.super_block.block_size as usize;
+        let mut buf = alloc::vec![0u8; block_size];
+
+        // 1. Count the free blocks in the data area.
+        let mut free_blocks = 0;
+        let (start, end) = (
+            self.data_start_block as usize,
+            self.super_block.bitmap_start_block as usize,
+        );
+        for bitmap_block in start / block_size..end.div_ceil(block_size) {
+            let chunk_start = bitmap_block * block_size;
+            let len = block_size.min(end - chunk_start);
+            self.read_checksummed_block(
+                self.super_block.bitmap_start_block + bitmap_block as u32,
+                &mut buf,
+            )?;
+            let first = start.saturating_sub(chunk_start);
+            free_blocks += buf[first..len].iter().filter(|&&used| used == 0).count() as u32;
+        }
+
+        // 2. Count the free inodes in the inode table.
+        let inode_size = Inode::SIZE;
+        let mut free_inodes = 0;
+        for block in 1..self.data_start_block {
+            self.block_device.read_block(block, 0, &mut buf)?;
+            free_inodes += buf
+                .chunks_exact(inode_size)
+                .filter(|chunk| !Inode::from_bytes(chunk).is_some_and(|inode| inode.is_used))
+                .count() as u32;
+        }
+        Ok((free_blocks, free_inodes))
     }
 
     /// Allocate an inode.
     ///
     /// # Parameters
     ///
-    /// * `block_num` - The block number to store the inode.
     /// * `file_type` - The type of the file.
     /// * `max_range` - The max inode number to search.
     ///
     /// # Returns
     ///
-    /// * `(Inode, u32)` - The inode and the block number.
+    /// * `Ok(Inode)` - The inode, which isn't written to the block device yet.
     /// * `Err(&'static str)` - If no inode available.
     fn alloc_inode(
         &mut self,
-        block_num: u32,
         file_type: definition::FileType,
         max_range: usize,
     ) -> Result<Inode, &'static str> {
         for inode_id in 0..max_range {
-            let inode = self.get_inode(inode_id as u32).unwrap();
+            let inode = self.read_inode(inode_id as u32)?;
             if !inode.is_used {
                 // Define that inode
-                let inode = Inode {
-                    is_used: true,
-                    inode_id: inode_id as u32,
-                    file_type,
-                    head_block: block_num, // Problem: Can't sure that the behind block is free, being optimized.
-                    file_length: 0,
-                    _reserved: [0; 7],
-                };
+                let inode = Inode::new(inode_id as u32, file_type);
+                self.super_block.free_inodes = self.super_block.free_inodes.saturating_sub(1);
                 return Ok(inode);
             } else {
                 continue;
             }
         }
-        Err("No inode available")
+        Err(error::NO_FREE_INODE)
     }
 
-    fn get_inode(&mut self, inode_id: u32) -> Option<Inode> {
-        // Second, read the inode from the block device.
-        let mut buf = [0u8; core::mem::size_of::<Inode>()];
+    /// Read an inode from the inode table, whether it's used or not.
+    fn read_inode(&mut self, inode_id: u32) -> Result<Inode, &'static str> {
+        if inode_id >= self.super_block.inode_count() {
+            return Err("Inode out of range");
+        }
+        let mut buf = [0u8; Inode::SIZE];
         let (block_idx, offset) = Inode::locate(inode_id, &self.super_block);
-        if self
-            .block_device
-            .read_block(block_idx as u32, offset as u32, &mut buf)
-            .is_err()
-        {
-            return None;
+        self.block_device
+            .read_block(block_idx as u32, offset as u32, &mut buf)?;
+        let Some(inode) = Inode::from_bytes(&buf) else {
+            return self.corruption_error("Invalid inode");
+        };
+        if inode.is_used && self.has_metadata_csum() && !Inode::checksum_matches(&buf) {
+            self.checksum_error(block_idx as u32)?;
+        }
+        // Free inodes don't store their id, but the used ones must be where they belong.
+        if inode.is_used && inode.inode_id != inode_id {
+            return self.corruption_error("Invalid inode");
         }
-        let inode = Inode::from_bytes(&buf)?;
+        Ok(inode)
+    }
+
+    /// Write an inode back to the inode table.
+    fn write_inode(&mut self, inode: &Inode) -> Result<(), &'static str> {
+        let (block_idx, offset) = Inode::locate(inode.inode_id, &self.super_block);
+        self.block_device
+            .write_block(block_idx as u32, offset as u32, &inode.to_bytes())
+    }
+
+    fn get_inode(&mut self, inode_id: u32) -> Option<Inode> {
+        // Read the inode from the block device.
+        let inode = self.read_inode(inode_id).ok()?;
 
         // Check is the inode used.
         if !inode.is_used {
             return None;
         }
-        Some(*inode)
+        Some(inode)
+    }
+
+    /// Allocate a free data block in the block bitmap.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The allocated block number.
+    /// * `Err(&'static str)` - If no block available.
+    fn alloc_block(&mut self) -> Result<u32, &'static str> {
+        let block_size = self.super_block.block_size as usize;
+        let bitmap_start = self.super_block.bitmap_start_block;
+        let end = self.super_block.bitmap_start_block as usize;
+        let mut buf = alloc::vec![0u8; block_size];
+        let mut index = self.data_start_block as usize;
+
+        while index < end {
+            // Each bitmap block describes `block_size` blocks.
+            let bitmap_block = index / block_size;
+            let chunk_start = bitmap_block * block_size;
+            let len = block_size.min(end - chunk_start);
+            self.read_checksummed_block(bitmap_start + bitmap_block as u32, &mut buf)?;
+
+            let first = index - chunk_start;
+            if let Some(i) = buf[first..len].iter().position(|&used| used == 0) {
+                let i = first + i;
+                self.write_checksummed(bitmap_start + bitmap_block as u32, i as u32, &[1])?;
+                self.super_block.free_blocks = self.super_block.free_blocks.saturating_sub(1);
+                return Ok((chunk_start + i) as u32);
+            }
+            index = chunk_start + len;
+        }
+        Err(error::NO_FREE_BLOCK)
+    }
+
+    /// Check if a block number is in the data area.
+    pub(crate) fn is_data_block(&self, block_num: u32) -> bool {
+        (self.data_start_block..self.super_block.bitmap_start_block).contains(&block_num)
+    }
+
+    /// Check if a block can belong to a file: it's in the data area, and isn't a
+    /// backup of the super block nor a block of the checksum table.
+    pub(crate) fn is_file_block(&self, block_num: u32) -> bool {
+        self.is_data_block(block_num)
+            && !self
+                .super_block
+                .checksum_table_blocks()
+                .contains(&block_num)
+            && !self.super_block.backup_blocks().contains(&block_num)
+    }
+
+    /// Check if a block pointer read from the disk has [`UNWRITTEN_FLAG`], which
+    /// only means something with the [`definition::feature::UNWRITTEN`] feature.
+    ///
+    /// [`UNWRITTEN_FLAG`]: crate::definition::UNWRITTEN_FLAG
+    pub(crate) fn is_unwritten(&self, pointer: u32) -> bool {
+        pointer & definition::UNWRITTEN_FLAG != 0 && self.has_unwritten()
+    }
+
+    /// Check if the file system has the [`definition::feature::UNWRITTEN`] feature.
+    pub(crate) fn has_unwritten(&self) -> bool {
+        self.super_block
+            .features()
+            .contains(definition::feature::UNWRITTEN)
+    }
+
+    /// Get the block number of a block pointer read from the disk, without its
+    /// unwritten flag, see [`FileSystem::is_unwritten`].
+    pub(crate) fn pointer_block(&self, pointer: u32) -> u32 {
+        if self.is_unwritten(pointer) {
+            pointer & !definition::UNWRITTEN_FLAG
+        } else {
+            pointer
+        }
+    }
+
+    /// Check a block pointer read from the disk.
+    ///
+    /// # Parameters
+    ///
+    /// * `pointer` - The block pointer, 0 for a hole.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The block number without its unwritten flag, if it's 0 or a
+    ///   block which can belong to a file.
+    /// * `Err(&'static str)` - If the pointer is corrupted, see [`FileSystem::errors`].
+    pub(crate) fn check_block_pointer(&mut self, pointer: u32) -> Result<u32, &'static str> {
+        let block_num = self.pointer_block(pointer);
+        if block_num == 0 || self.is_file_block(block_num) {
+            return Ok(block_num);
+        }
+        self.corruption_error(error::CORRUPTED_BLOCK_POINTER)
+    }
+
+    /// Release a data block in the block bitmap.
+    fn free_block(&mut self, block_num: u32) -> Result<(), &'static str> {
+        if !self.is_data_block(block_num) {
+            return Err("Block out of data area");
+        }
+        let block_size = self.super_block.block_size;
+        let (bitmap_block, offset) = (
+            self.super_block.bitmap_start_block + block_num / block_size,
+            block_num % block_size,
+        );
+        let mut buf = alloc::vec![0u8; block_size as usize];
+        self.read_checksummed_block(bitmap_block, &mut buf)?;
+        if buf[offset as usize] == 0 {
+            return Err("Block already free");
+        }
+        self.write_checksummed(bitmap_block, offset, &[0])?;
+        self.super_block.free_blocks += 1;
+        self.block_device.discard(block_num..block_num + 1)
+    }
+
+    /// Find free data blocks, without claiming them.
+    ///
+    /// # Parameters
+    ///
+    /// * `count` - The number of blocks.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<u32>)` - The first run of `count` contiguous free blocks, or the
+    ///   first free blocks if there is no such run.
+    /// * `Err(&'static str)` - If there are less than `count` free blocks.
+    fn find_free_blocks(&mut self, count: usize) -> Result<Vec<u32>, &'static str> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let block_size = self.super_block.block_size as usize;
+        let bitmap_start = self.super_block.bitmap_start_block;
+        let end = self.super_block.bitmap_start_block as usize;
+        let mut buf = alloc::vec![0u8; block_size];
+        let mut index = self.data_start_block as usize;
+        let mut first_free = Vec::new();
+        let mut run_start = index;
+
+        while index < end {
+            let bitmap_block = index / block_size;
+            let chunk_start = bitmap_block * block_size;
+            let len = block_size.min(end - chunk_start);
+            self.read_checksummed_block(bitmap_start + bitmap_block as u32, &mut buf)?;
+
+            for (i, &used) in buf[..len].iter().enumerate().skip(index - chunk_start) {
+                let block_num = chunk_start + i;
+                if used != 0 {
+                    run_start = block_num + 1;
+                    continue;
+                }
+                if first_free.len() < count {
+                    first_free.push(block_num as u32);
+                }
+                if block_num + 1 - run_start == count {
+                    return Ok((run_start as u32..=block_num as u32).collect());
+                }
+            }
+            index = chunk_start + len;
+        }
+        if first_free.len() < count {
+            return Err(error::NO_FREE_BLOCK);
+        }
+        Ok(first_free)
+    }
+
+    /// Mark a free data block as used in the block bitmap.
+    fn claim_block(&mut self, block_num: u32) -> Result<(), &'static str> {
+        if !self.is_data_block(block_num) {
+            return Err("Block out of data area");
+        }
+        let block_size = self.super_block.block_size;
+        let bitmap_block = self.super_block.bitmap_start_block + block_num / block_size;
+        self.write_checksummed(bitmap_block, block_num % block_size, &[1])?;
+        self.super_block.free_blocks = self.super_block.free_blocks.saturating_sub(1);
+        Ok(())
     }
 
     fn add_dir_entry(
@@ -277,68 +1016,302 @@ impl<B: BlockDevice> FileSystem<B> {
             return Err("Parent inode not found");
         };
 
-        // 2. Calculate which block and offset the dir entry should be written.
-        let entry_size = core::mem::size_of::<definition::DirEntry>();
-        let data_offset = parent_inode.file_length as usize;
-        parent_inode.file_length += entry_size as u64;
-
-        let data_block_head_idx = parent_inode.head_block as usize;
-
-        // 3. Create a dir entry.
+        // 2. Create a dir entry.
         let name = convert_name(name.as_bytes());
         let dir_entry = definition::DirEntry {
             inode: inode_id,
             name,
         };
 
-        // 4. Write the dir entry to the block device.
-        self.block_device.write_block(
-            data_block_head_idx as u32,
-            data_offset as u32,
-            &dir_entry.as_bytes(),
+        // 3. Put the dir entry in a free slot, or append it to the directory data,
+        // which grows as needed.
+        let data_offset = match self.find_dir_entry(&parent_inode, b"")? {
+            Some((offset, _)) => offset,
+            None => parent_inode.file_length,
+        };
+        let result = self.write_data(&mut parent_inode, data_offset, &dir_entry.to_bytes());
+
+        // 4. Update the parent inode.
+        self.write_inode(&parent_inode)?;
+        result.map(|_| ())
+    }
+
+    /// Find an entry in a directory.
+    ///
+    /// # Parameters
+    ///
+    /// * `dir_inode` - The inode of the directory.
+    /// * `name` - The name to find, an empty name finds a free slot.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some((u64, DirEntry)))` - The byte offset of the entry in the directory, and the entry.
+    /// * `Ok(None)` - If there's no such entry.
+    fn find_dir_entry(
+        &mut self,
+        dir_inode: &Inode,
+        name: &[u8],
+    ) -> Result<Option<(u64, definition::DirEntry)>, &'static str> {
+        let entry_size = definition::DirEntry::SIZE;
+        Ok(self
+            .read_dir_entries(dir_inode)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, dir_entry)| ((i * entry_size) as u64, dir_entry))
+            .find(|(_, dir_entry)| dir_entry.name_bytes() == name))
+    }
+
+    /// Read every entry of a directory, including the free slots.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<DirEntry>)` - The entries, in the order they're stored.
+    /// * `Err(&'static str)` - If the directory is larger than the data area, or
+    ///   an entry is invalid.
+    pub(crate) fn read_dir_entries(
+        &mut self,
+        dir_inode: &Inode,
+    ) -> Result<Vec<definition::DirEntry>, &'static str> {
+        // Don't trust the length before allocating a buffer for it.
+        let max_length =
+            self.super_block.data_block_count() as u64 * self.super_block.block_size as u64;
+        if dir_inode.file_length > max_length {
+            return self.corruption_error(error::CORRUPTED_DIRECTORY);
+        }
+
+        // Read block by block, to check the checksums.
+        let entry_size = definition::DirEntry::SIZE;
+        let block_size = self.super_block.block_size as usize;
+        let mut data = alloc::vec![0u8; dir_inode.file_length as usize];
+        let mut buf = alloc::vec![0u8; block_size];
+        for (index, chunk) in data.chunks_mut(block_size).enumerate() {
+            let block_num = self.lookup_block(dir_inode, index as u32)?;
+            // The holes read as zeroes.
+            if block_num != 0 {
+                self.read_checksummed_block(block_num, &mut buf)?;
+                chunk.copy_from_slice(&buf[..chunk.len()]);
+            }
+        }
+        let entries = data
+            .chunks_exact(entry_size)
+            .map(definition::DirEntry::from_bytes)
+            .collect();
+        match entries {
+            Some(entries) => Ok(entries),
+            None => self.corruption_error(error::INVALID_DIR_ENTRY),
+        }
+    }
+
+    /// Remove an entry from a directory, leaving a free slot.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Inode)` - The inode the entry pointed to.
+    fn remove_dir_entry(
+        &mut self,
+        parent_inode_id: u32,
+        name: &str,
+    ) -> Result<Inode, &'static str> {
+        let mut parent_inode = self
+            .get_inode(parent_inode_id)
+            .ok_or("Parent inode not found")?;
+        if parent_inode.file_type != definition::FileType::Directory {
+            return Err("Not a directory");
+        }
+        if name.is_empty() || name == "." || name == ".." {
+            return Err("Invalid name");
+        }
+        let (offset, dir_entry) = self
+            .find_dir_entry(&parent_inode, name.as_bytes())?
+            .ok_or(error::NOT_FOUND)?;
+        let inode = self
+            .get_inode(dir_entry.inode)
+            .ok_or(error::INODE_NOT_FOUND)?;
+
+        // A single write, so the entry is either there or gone after a crash.
+        let block_size = self.super_block.block_size as u64;
+        let block_num = self.lookup_block(&parent_inode, (offset / block_size) as u32)?;
+        self.write_checksummed(
+            block_num,
+            (offset % block_size) as u32,
+            &definition::DirEntry::empty().to_bytes(),
         )?;
 
-        // 5. Update the parent inode.
-        let (block_idx, offset) = Inode::locate(parent_inode_id, &self.super_block);
+        parent_inode.mtime = (self.clock)();
+        parent_inode.ctime = parent_inode.mtime;
+        self.write_inode(&parent_inode)?;
+        Ok(inode)
+    }
+
+    /// Release an inode and all of its blocks.
+    ///
+    /// The inode is cleared before its blocks are freed, so an interrupted
+    /// release only leaks blocks, and never leaves them shared.
+    fn release_inode(&mut self, inode: &Inode) -> Result<(), &'static str> {
+        let mut blocks = self.file_blocks(inode)?;
+        if inode.xattr_block != 0 {
+            blocks.push(inode.xattr_block);
+        }
+        // Don't free the blocks of someone else.
+        for &block_num in &blocks {
+            self.check_block_pointer(block_num)?;
+        }
+
+        let (block_idx, offset) = Inode::locate(inode.inode_id, &self.super_block);
         self.block_device
-            .write_block(block_idx as u32, offset as u32, &parent_inode.as_bytes())?;
+            .write_block(block_idx as u32, offset as u32, &[0u8; Inode::SIZE])?;
+        self.super_block.free_inodes += 1;
+
+        for block_num in blocks {
+            self.free_block(block_num)?;
+        }
         Ok(())
     }
 
-    /// Create a file.
-    pub fn mkfile(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+    /// Remove a file which is not a directory.
+    ///
+    /// # Parameters
+    ///
+    /// * `parent_inode_id` - The directory containing the file.
+    /// * `name` - The name of the file.
+    pub fn unlink(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.check_writable()?;
+        let inode_id = self.lookup_in(parent_inode_id, name)?;
+        let inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        if inode.file_type == definition::FileType::Directory {
+            return Err(error::IS_A_DIRECTORY);
+        }
+
+        let mut inode = self.remove_dir_entry(parent_inode_id, name)?;
+        inode.links_count = inode.links_count.saturating_sub(1);
+        let result = if inode.links_count > 0 {
+            inode.ctime = (self.clock)();
+            self.write_inode(&inode)
+        } else if self.is_open(inode.inode_id) {
+            // It's released when it's closed.
+            self.add_orphan(inode)
+        } else {
+            self.release_inode(&inode)
+        };
+        self.sync_write(result)
+    }
+
+    /// Remove an empty directory.
+    ///
+    /// # Parameters
+    ///
+    /// * `parent_inode_id` - The directory containing the directory.
+    /// * `name` - The name of the directory.
+    pub fn rmdir(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.check_writable()?;
+        let inode_id = self.lookup_in(parent_inode_id, name)?;
+        let (_, entries) = self.list_dir(inode_id)?;
+        if entries
+            .iter()
+            .any(|entry| entry.name_bytes() != b"." && entry.name_bytes() != b"..")
+        {
+            return Err("Directory not empty");
+        }
+
+        let inode = self.remove_dir_entry(parent_inode_id, name)?;
+
+        // Its '..' entry doesn't link to the parent anymore.
+        let mut parent_inode = self
+            .get_inode(parent_inode_id)
+            .ok_or("Parent inode not found")?;
+        parent_inode.links_count = parent_inode.links_count.saturating_sub(1);
+        self.write_inode(&parent_inode)?;
+
+        let result = self.release_inode(&inode);
+        self.sync_write(result)
+    }
+
+    /// Check that the file system can be changed.
+    pub(crate) fn check_writable(&self) -> Result<(), &'static str> {
+        if self.read_only {
+            return Err(error::READ_ONLY_FS);
+        }
+        Ok(())
+    }
+
+    /// Check that a new entry can be added to a directory, before allocating anything.
+    fn check_new_entry(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.check_writable()?;
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err("Invalid name");
+        }
+        if name.len() > definition::direntry::MAX_NAME_LEN {
+            return Err("Name too long");
+        }
+        match self.lookup_in(parent_inode_id, name) {
+            Ok(_) => Err(error::EXISTS),
+            Err(error::NOT_FOUND) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create a node without data blocks (regular file, FIFO, socket...).
+    fn mknode(
+        &mut self,
+        parent_inode_id: u32,
+        name: &str,
+        file_type: definition::FileType,
+    ) -> Result<(), &'static str> {
+        self.check_new_entry(parent_inode_id, name)?;
+
         /* Stage 1: Allocate an inode. */
         // 1.1: Allocate an inode.
-        let inode = self.alloc_inode(
-            self.super_block.total_block,
-            definition::FileType::Regular,
-            self.get_max_inode(),
-        )?;
+        let mut inode = self.alloc_inode(file_type, self.get_max_inode())?;
+        inode.atime = (self.clock)();
+        inode.mtime = inode.atime;
+        inode.ctime = inode.atime;
 
         // 1.2: Write the inode to the block device.
         let (block_idx, offset) = Inode::locate(inode.inode_id, &self.super_block);
         self.block_device
-            .write_block(block_idx as u32, offset as u32, &inode.as_bytes())?;
+            .write_block(block_idx as u32, offset as u32, &inode.to_bytes())?;
 
         /* Stage 2: Create the dir entry for its parent directory. */
         // Write the dir entry to the block device.
-        self.add_dir_entry(parent_inode_id, name, inode.inode_id)?;
-        Ok(())
+        let result = self.add_dir_entry(parent_inode_id, name, inode.inode_id);
+        self.sync_write(result)
+    }
+
+    /// Create a file.
+    pub fn mkfile(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.mknode(parent_inode_id, name, definition::FileType::Regular)
+    }
+
+    /// Create a named pipe (FIFO).
+    ///
+    /// # Note
+    ///
+    /// Only the node is stored on disk, the pipe itself is handled by the kernel.
+    pub fn mkfifo(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.mknode(parent_inode_id, name, definition::FileType::Fifo)
+    }
+
+    /// Create a Unix domain socket node.
+    ///
+    /// # Note
+    ///
+    /// Only the node is stored on disk, the socket itself is handled by the kernel.
+    pub fn mksock(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.mknode(parent_inode_id, name, definition::FileType::Socket)
     }
 
     /// Create a directory.
     pub fn mkdir(&mut self, parent_inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.check_new_entry(parent_inode_id, name)?;
+
         // 1. Allocate an inode.
-        let inode = self.alloc_inode(
-            self.data_start_block,
-            definition::FileType::Directory,
-            self.get_max_inode(),
-        )?;
+        let mut inode = self.alloc_inode(definition::FileType::Directory, self.get_max_inode())?;
+        inode.atime = (self.clock)();
+        inode.mtime = inode.atime;
+        inode.ctime = inode.atime;
 
         // 2. Write the inode to the block device.
-        let (block_idx, offset) = Inode::locate(inode.inode_id, &self.super_block);
-        self.block_device
-            .write_block(block_idx as u32, offset as u32, &inode.as_bytes())?;
+        self.write_inode(&inode)?;
 
         // 3. Create a '.' and '..' entry in the directory.
         // 3.1 Create a '.' entry.
@@ -355,46 +1328,113 @@ impl<B: BlockDevice> FileSystem<B> {
             name: parent_name,
         };
 
-        // 3.3 Write the '.' and '..' entry to the block device.
-        let (block_idx, offset) = Inode::locate(inode.inode_id, &self.super_block);
-        self.block_device.write_block(
-            block_idx as u32,
-            offset as u32,
-            &dot_dir_entry.as_bytes(),
-        )?;
-        self.block_device.write_block(
-            block_idx as u32,
-            (offset + core::mem::size_of::<definition::DirEntry>()) as u32,
-            &dot_dot_dir_entry.as_bytes(),
-        )?;
+        // 3.3 Write the '.' and '..' entry to the directory's data.
+        let mut entries = dot_dir_entry.to_bytes();
+        entries.extend_from_slice(&dot_dot_dir_entry.to_bytes());
+        let result = self.write_data(&mut inode, 0, &entries);
+        self.write_inode(&inode)?;
+        result?;
 
         /* Stage 4: Add dir entry to parent direcotry */
-        // 4.1: Get the parent inode
-        self.add_dir_entry(parent_inode_id, name, inode.inode_id)?;
+        // 4.1: The '..' entry links to the parent, count it first so a crash
+        // can only leave the count too high
+        let mut parent_inode = self
+            .get_inode(parent_inode_id)
+            .ok_or("Parent inode not found")?;
+        parent_inode.links_count += 1;
+        self.write_inode(&parent_inode)?;
 
-        Ok(())
+        // 4.2: Add the dir entry
+        let result = self.add_dir_entry(parent_inode_id, name, inode.inode_id);
+        self.sync_write(result)
     }
 
-    /// List a directory.
+    /// List a directory, updating its access time following [`FileSystem::atime`].
     pub fn ls(&mut self, inode_id: u32) -> Result<Vec<definition::DirEntry>, &'static str> {
+        let (mut inode, dir_entries) = self.list_dir(inode_id)?;
+        self.touch_atime(&mut inode)?;
+        Ok(dir_entries)
+    }
+
+    /// List a directory without updating its access time, as looking up a path does.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((Inode, Vec<DirEntry>))` - The inode of the directory, and its entries.
+    pub(crate) fn list_dir(
+        &mut self,
+        inode_id: u32,
+    ) -> Result<(Inode, Vec<definition::DirEntry>), &'static str> {
         // 1. Check is the directory exists.
         let inode = if let Some(inode) = self.get_inode(inode_id) {
             inode
         } else {
-            return Err("Inode not found");
+            return Err(error::INODE_NOT_FOUND);
         };
+        if inode.file_type != definition::FileType::Directory {
+            return Err("Not a directory");
+        }
 
         // 2. Read the directory entries from the block device.
-        let mut buf = [0u8; 1024];
-        self.block_device
-            .read_block(inode.head_block, 0, &mut buf)?;
-        let dir_entries = unsafe {
-            core::slice::from_raw_parts(buf.as_ptr() as *const definition::DirEntry, 128)
-        };
-        Ok(dir_entries.to_vec())
+        let mut dir_entries = self.read_dir_entries(&inode)?;
+        // Skip the free slots left by removed entries.
+        dir_entries.retain(|dir_entry| !dir_entry.name_bytes().is_empty());
+        Ok((inode, dir_entries))
+    }
+
+    /// Find the inode of a path.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path to look up, relative to the root directory.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u32)` - The inode id of the path.
+    /// * `Err(&'static str)` - If any component of the path doesn't exist.
+    pub fn lookup(&mut self, path: &str) -> Result<u32, &'static str> {
+        let mut inode_id = 0;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            inode_id = self.lookup_in(inode_id, component)?;
+        }
+        Ok(inode_id)
+    }
+
+    /// Find the inode of a name in a directory.
+    fn lookup_in(&mut self, dir_inode_id: u32, name: &str) -> Result<u32, &'static str> {
+        Ok(self
+            .list_dir(dir_inode_id)?
+            .1
+            .iter()
+            .find(|entry| entry.name_bytes() == name.as_bytes())
+            .ok_or(error::NOT_FOUND)?
+            .inode)
     }
 }
 
+impl<B: BlockDevice> Drop for FileSystem<B> {
+    fn drop(&mut self) {
+        if !self.unmounted {
+            let _ = self.close_all();
+        }
+    }
+}
+
+/// The default clock of the file system.
+#[cfg(feature = "std")]
+fn default_clock() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The default clock of the file system.
+#[cfg(not(feature = "std"))]
+fn default_clock() -> u64 {
+    0
+}
+
 /// Convert a name to a 252 bytes array.
 ///
 /// # Parameters
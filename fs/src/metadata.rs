@@ -0,0 +1,143 @@
+//! The metadata of the files and the file system, which is decoupled from the on-disk layout.
+
+use crate::definition::direntry::MAX_NAME_LEN;
+use crate::definition::{FileType, Inode};
+use crate::{BlockDevice, FileSystem, error};
+
+/// The metadata of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// The inode id of the file.
+    pub inode_id: u32,
+
+    /// The type of the file.
+    pub file_type: FileType,
+
+    /// The size of the file in bytes.
+    pub size: u64,
+
+    /// The number of blocks owned by the file: its data blocks, and the pointer
+    /// blocks mapping them. The extended attribute block isn't counted.
+    pub blocks: u32,
+
+    /// The number of hard links to the file.
+    pub links: u16,
+
+    /// The permission bits of the file.
+    pub mode: u16,
+
+    /// The device number, only meaningful for device files.
+    pub device: u32,
+
+    /// The last access time, in seconds since the Unix epoch.
+    pub accessed: u64,
+
+    /// The last modification time, in seconds since the Unix epoch.
+    pub modified: u64,
+
+    /// The last status change time, in seconds since the Unix epoch.
+    pub changed: u64,
+}
+
+impl Metadata {
+    /// Check if the file is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
+    /// Check if the file is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::Regular
+    }
+}
+
+impl From<&Inode> for Metadata {
+    fn from(inode: &Inode) -> Self {
+        Self {
+            inode_id: inode.inode_id,
+            file_type: inode.file_type,
+            size: inode.file_length,
+            blocks: inode.block_count,
+            links: inode.links_count,
+            mode: inode.mode,
+            device: inode.device,
+            accessed: inode.atime,
+            modified: inode.mtime,
+            changed: inode.ctime,
+        }
+    }
+}
+
+/// The usage statistics of a file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatFs {
+    /// The size of each block in bytes.
+    pub block_size: u32,
+
+    /// The number of blocks in the data area.
+    pub total_blocks: u32,
+
+    /// The number of free blocks in the data area.
+    pub free_blocks: u32,
+
+    /// The number of free blocks available to unprivileged users.
+    pub available_blocks: u32,
+
+    /// The number of inodes in the inode table.
+    pub total_inodes: u32,
+
+    /// The number of free inodes.
+    pub free_inodes: u32,
+
+    /// The max length of a file name in bytes.
+    pub max_name_len: u32,
+}
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Get the usage statistics of the file system.
+    pub fn statfs(&self) -> StatFs {
+        let super_block = &self.super_block;
+        StatFs {
+            block_size: super_block.block_size,
+            total_blocks: super_block.data_block_count(),
+            free_blocks: super_block.free_blocks,
+            available_blocks: super_block
+                .free_blocks
+                .saturating_sub(super_block.reserved_blocks),
+            total_inodes: super_block.inode_count(),
+            free_inodes: super_block.free_inodes,
+            max_name_len: MAX_NAME_LEN as u32,
+        }
+    }
+
+    /// Get the metadata of an inode.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode to query.
+    pub fn stat(&mut self, inode_id: u32) -> Result<Metadata, &'static str> {
+        let inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        Ok(Metadata::from(&inode))
+    }
+
+    /// Get the metadata of a path.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - The path to query, relative to the root directory.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use proka_fs::{FileSystem, init_block_device};
+    ///
+    /// let bd = init_block_device("test.img").unwrap();
+    /// let mut fs = FileSystem::mount(bd).unwrap();
+    /// let metadata = fs.stat_path("/etc/passwd").unwrap();
+    /// println!("{} bytes", metadata.size);
+    /// ```
+    pub fn stat_path(&mut self, path: &str) -> Result<Metadata, &'static str> {
+        let inode_id = self.lookup(path)?;
+        self.stat(inode_id)
+    }
+}
@@ -0,0 +1,254 @@
+//! Create a new file system on a block device.
+//!
+//! This is what `mkpkfs` uses, and it works on any [`BlockDevice`] in `no_std` as well,
+//! so tests and the kernel can format RAM disks or partitions directly.
+
+use crate::checksum::block_checksum;
+use crate::definition::feature::{self, Features};
+use crate::definition::{
+    CHECKSUM_ENTRY_SIZE, DirEntry, FileType, Inode, SuperBlock, UNWRITTEN_FLAG, Uuid,
+};
+use crate::{BlockDevice, GenericFsData, convert_name};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The options used to create a file system.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// The creation time of the file system, in seconds since the Unix epoch.
+    pub time: u64,
+
+    /// The features of the file system.
+    pub features: Features,
+
+    /// The label of the file system, up to [`LABEL_LEN`](crate::definition::superblock::LABEL_LEN) bytes.
+    pub label: String,
+
+    /// The UUID of the file system, or `None` for a random one.
+    pub uuid: Option<Uuid>,
+
+    /// The source of randomness, which fills a buffer with random bytes.
+    ///
+    /// It's the randomly seeded hasher of the standard library under the `std`
+    /// feature. There is none by default without `std`, so the callers must give
+    /// either a source or [`FormatOptions::uuid`].
+    pub random: Option<fn(&mut [u8])>,
+
+    /// The name and version of the tool creating the file system.
+    pub creator: &'static str,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            time: 0,
+            features: Features::default(),
+            label: String::new(),
+            uuid: None,
+            random: default_random(),
+            creator: concat!("proka-fs ", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// Create a new file system on a block device.
+///
+/// # Parameters
+///
+/// * `bd` - The block device to format.
+/// * `partition_size` - The size of the partition in bytes.
+/// * `options` - The options of the new file system.
+///
+/// # Returns
+///
+/// * `Ok(SuperBlock)` - The super block of the new file system.
+/// * `Err(&'static str)` - If the partition is too small, a feature is unknown, the
+///   label is invalid, there is neither a UUID nor a random source, or the device fails.
+///
+/// # Example
+///
+/// ```rust
+/// use proka_fs::FileSystem;
+/// use proka_fs::mkfs::{FormatOptions, format};
+/// use proka_fs::ram::RamBlockDevice;
+///
+/// let mut bd = RamBlockDevice::new(vec![0u8; 1024 * 1024]);
+/// // Without `std`, there is no random source to make a UUID, so give one.
+/// let options = FormatOptions {
+///     uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+///     ..FormatOptions::default()
+/// };
+/// format(&mut bd, 1024 * 1024, &options).unwrap();
+///
+/// let mut fs = FileSystem::mount(bd).unwrap();
+/// fs.mkdir(0, "boot").unwrap();
+/// ```
+pub fn format<B: BlockDevice>(
+    bd: &mut B,
+    partition_size: u64,
+    options: &FormatOptions,
+) -> Result<SuperBlock, &'static str> {
+    /* Stage 1: Initialize the super block */
+    let mut super_block = SuperBlock::new(partition_size);
+    if options.features.unknown() != Features::NONE {
+        return Err("Unsupported features");
+    }
+    if options.features.contains(feature::DATA_CSUM)
+        && !options.features.contains(feature::METADATA_CSUM)
+    {
+        return Err("The data_csum feature needs metadata_csum");
+    }
+    // The block numbers must leave the flag of the unwritten blocks free.
+    if options.features.contains(feature::UNWRITTEN) && super_block.total_block > UNWRITTEN_FLAG {
+        return Err("The unwritten feature needs less than 2^31 blocks");
+    }
+    super_block.set_features(options.features);
+    super_block.set_label(&options.label)?;
+    super_block.set_creator(options.creator);
+    super_block.created = options.time;
+    super_block.uuid = match (options.uuid, options.random) {
+        (Some(uuid), _) => uuid,
+        (None, Some(random)) => {
+            let mut bytes = [0; 16];
+            random(&mut bytes);
+            Uuid::from_random_bytes(bytes)
+        }
+        // A fixed UUID would make every file system look the same.
+        (None, None) => return Err("No random source"),
+    };
+    let block_size = super_block.block_size as usize;
+    let data_start_block = super_block.data_start_block;
+    let bitmap_start_block = super_block.bitmap_start_block;
+    let total_block = super_block.total_block;
+    if super_block.data_block_count() < 1 {
+        return Err("The partition is too small");
+    }
+    let backup_blocks = super_block.backup_blocks();
+    // The checksum table follows the root directory's data block.
+    if options.features.contains(feature::METADATA_CSUM) {
+        super_block.checksum_table = data_start_block + 1;
+    }
+    let checksum_table = super_block.checksum_table_blocks();
+    if checksum_table.end > bitmap_start_block
+        || backup_blocks
+            .iter()
+            .any(|block| checksum_table.contains(block))
+    {
+        return Err("The partition is too small");
+    }
+
+    /* Stage 2: Clear the inode table */
+    // The device may contain anything, so no inode may look used.
+    let zero = alloc::vec![0u8; block_size * 64];
+    let mut block = 1;
+    while block < data_start_block {
+        let count = (data_start_block - block).min(64);
+        bd.write_blocks(block, &zero[..count as usize * block_size])?;
+        block += count;
+    }
+
+    /* Stage 3: Initialize the root inode */
+    let mut root_inode = Inode::new(0, FileType::Directory);
+    root_inode.direct_blocks[0] = data_start_block;
+    root_inode.file_length = 2 * DirEntry::SIZE as u64;
+    root_inode.block_count = 1;
+    root_inode.atime = options.time;
+    root_inode.mtime = options.time;
+    root_inode.ctime = options.time;
+    bd.write_block(1, 0, &root_inode.to_bytes())?;
+    super_block.free_inodes -= 1;
+    super_block.free_blocks -= 1;
+
+    /* Stage 4: Initialize the block bitmap */
+    // This bitmap is 0 for all, but except 5 places:
+    //
+    // 1. Super Block (const, 0)
+    // 2. Block bitmap itself (From `bitmap_start_block` to `total_block`)
+    // 3. The root directory's data block (`data_start_block`)
+    // 4. The backups of the super block
+    // 5. The checksum table
+    //
+    // The bitmap is padded to whole blocks, so it can be written at once.
+    let mut block_bitmap: Vec<u8> =
+        alloc::vec![0; (total_block - bitmap_start_block) as usize * block_size];
+    block_bitmap[0] = 1;
+    block_bitmap[bitmap_start_block as usize..total_block as usize].fill(1);
+    block_bitmap[data_start_block as usize] = 1;
+    for &block in &backup_blocks {
+        block_bitmap[block as usize] = 1;
+    }
+    super_block.free_blocks -= backup_blocks.len() as u32;
+    block_bitmap[checksum_table.start as usize..checksum_table.end as usize].fill(1);
+    super_block.free_blocks -= checksum_table.len() as u32;
+    bd.write_blocks(bitmap_start_block, &block_bitmap)?;
+
+    /* Stage 5: Initialize the root directory's "." and ".." entries */
+    // These entries are pointed at the same directory: root.
+    let entry_dot = DirEntry {
+        inode: 0,
+        name: convert_name(b"."),
+    };
+    let entry_parent = DirEntry {
+        inode: 0,
+        name: convert_name(b".."),
+    };
+    let mut root_data = alloc::vec![0u8; block_size];
+    entry_dot.write_bytes(&mut root_data);
+    entry_parent.write_bytes(&mut root_data[DirEntry::SIZE..]);
+    bd.write_block(data_start_block, 0, &root_data)?;
+
+    /* Stage 6: Write the checksum table */
+    // Only the bitmap and the root directory have checksums for now.
+    if !checksum_table.is_empty() {
+        let mut table = alloc::vec![0u8; checksum_table.len() * block_size];
+        let mut set_entry = |block_num: u32, data: &[u8]| {
+            let checksum = block_checksum(block_num, data);
+            let pos = block_num as usize * CHECKSUM_ENTRY_SIZE;
+            table[pos..pos + 4].copy_from_slice(&checksum.to_le_bytes());
+            table[pos + 4..pos + 8].copy_from_slice(&checksum.to_le_bytes());
+        };
+        for (i, data) in block_bitmap.chunks_exact(block_size).enumerate() {
+            set_entry(bitmap_start_block + i as u32, data);
+        }
+        set_entry(data_start_block, &root_data);
+        bd.write_blocks(checksum_table.start, &table)?;
+    }
+
+    /* Stage 7: Write the super block, and make sure everything reaches the disk */
+    // The rest of the block is kept for later fields, so it must be zeroed too.
+    // The primary super block goes last, once everything else is in place.
+    let mut super_block_data = alloc::vec![0u8; block_size];
+    super_block.write_bytes(&mut super_block_data);
+    for &block in &backup_blocks {
+        bd.write_block(block, 0, &super_block_data)?;
+    }
+    bd.write_block(0, 0, &super_block_data)?;
+    bd.flush()?;
+    Ok(super_block)
+}
+
+/// Get the default source of randomness.
+#[cfg(feature = "std")]
+fn default_random() -> Option<fn(&mut [u8])> {
+    Some(std_random)
+}
+
+/// Get the default source of randomness, there is none without `std`.
+#[cfg(not(feature = "std"))]
+fn default_random() -> Option<fn(&mut [u8])> {
+    None
+}
+
+/// Fill a buffer with random bytes, from the standard library.
+#[cfg(feature = "std")]
+fn std_random(buf: &mut [u8]) {
+    use std::hash::{BuildHasher, Hasher};
+
+    // Each `RandomState` has random keys, so its hashes are random too.
+    let state = std::collections::hash_map::RandomState::new();
+    for (i, chunk) in buf.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+    }
+}
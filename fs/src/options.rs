@@ -0,0 +1,301 @@
+//! The mount options, which choose how a mounted file system behaves.
+//!
+//! [`MountOptions`] is given to [`FileSystem::mount_with`], and can be parsed from
+//! the comma-separated option string of the mount syscall:
+//!
+//! | Option | Meaning |
+//! |---|---|
+//! | `ro`, `rw` | Mount read-only or read-write |
+//! | `noatime`, `relatime`, `strictatime` | See [`AtimePolicy`] |
+//! | `cache=N` | Keep up to `N` blocks in memory, only with [`FileSystem::mount_cached`] |
+//! | `errors=continue`, `errors=remount-ro`, `errors=panic` | See [`ErrorBehavior`] |
+//! | `csum=enforce`, `csum=ro` | See [`ChecksumPolicy`] |
+//! | `journal=none`, `journal=ordered`, `journal=writeback`, `journal=data` | See [`JournalMode`] |
+//! | `sync`, `async` | Flush the device after every change or not |
+//!
+//! The later options override the earlier ones.
+//!
+//! # Example
+//!
+//! ```rust
+//! use proka_fs::FileSystem;
+//! use proka_fs::mkfs::{FormatOptions, format};
+//! use proka_fs::options::{AtimePolicy, ErrorBehavior, MountOptions};
+//! use proka_fs::ram::RamBlockDevice;
+//!
+//! let mut bd = RamBlockDevice::new(vec![0u8; 1024 * 1024]);
+//! # let options = FormatOptions {
+//! #     uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+//! #     ..FormatOptions::default()
+//! # };
+//! format(&mut bd, 1024 * 1024, &options).unwrap();
+//!
+//! let options = MountOptions::parse("rw,noatime,errors=remount-ro").unwrap();
+//! assert_eq!(options.atime, AtimePolicy::NoAtime);
+//! assert_eq!(options.errors, ErrorBehavior::RemountReadOnly);
+//! let mut fs = FileSystem::mount_with(bd, &options).unwrap();
+//! fs.mkdir(0, "boot").unwrap();
+//! ```
+//!
+//! [`FileSystem::mount_cached`]: crate::FileSystem::mount_cached
+
+use crate::definition::Inode;
+use crate::{BlockDevice, ChecksumPolicy, FileSystem};
+
+/// The number of blocks kept in memory by [`FileSystem::mount_cached`] when
+/// [`MountOptions::cache_blocks`] isn't given.
+///
+/// [`FileSystem::mount_cached`]: crate::FileSystem::mount_cached
+pub const DEFAULT_CACHE_BLOCKS: usize = 64;
+
+/// When the access time of the files and directories is updated on read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AtimePolicy {
+    /// Never, so reading writes nothing.
+    NoAtime,
+
+    /// Only if it's older than the modification or change time, or than a day, so
+    /// the tools looking for files read since their last change still work.
+    #[default]
+    Relatime,
+
+    /// On every read.
+    Strict,
+}
+
+/// What to do when the file system is found corrupted, such as some metadata
+/// which doesn't match its checksum with [`ChecksumPolicy::Enforce`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorBehavior {
+    /// Fail the operation, and go on with the others.
+    #[default]
+    Continue,
+
+    /// Fail the operation, and make the file system read-only so nothing is built on
+    /// top of the corruption.
+    RemountReadOnly,
+
+    /// Panic, for the systems which rather stop than run on a corrupted file system.
+    Panic,
+}
+
+/// How the changes are journaled.
+///
+/// PKFS has no journal yet, its metadata writes are ordered to survive a crash
+/// instead (see [`crate::checksum`]). Only [`JournalMode::None`] can be mounted, the
+/// others are parsed to tell the options of a later driver apart from typos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JournalMode {
+    /// No journal.
+    #[default]
+    None,
+
+    /// Journal the metadata, after the data they point to is written.
+    Ordered,
+
+    /// Journal the metadata only.
+    Writeback,
+
+    /// Journal the metadata and the data.
+    Data,
+}
+
+/// The options used to mount a file system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MountOptions {
+    /// Whether the file system refuses every change, nothing is written on mount
+    /// then. See [`FileSystem::mount_read_only`] to be sure the device is never written.
+    ///
+    /// [`FileSystem::mount_read_only`]: crate::FileSystem::mount_read_only
+    pub read_only: bool,
+
+    /// When the access times are updated.
+    pub atime: AtimePolicy,
+
+    /// The max number of blocks kept in memory by [`FileSystem::mount_cached`], or
+    /// `None` for [`DEFAULT_CACHE_BLOCKS`]. The other mounts use the device as it is,
+    /// and refuse it.
+    ///
+    /// [`FileSystem::mount_cached`]: crate::FileSystem::mount_cached
+    pub cache_blocks: Option<usize>,
+
+    /// What to do when the file system is found corrupted.
+    pub errors: ErrorBehavior,
+
+    /// What to do when the checksum of some metadata doesn't match.
+    pub checksum_policy: ChecksumPolicy,
+
+    /// How the changes are journaled.
+    pub journal: JournalMode,
+
+    /// Whether the block device is flushed after every change, before it returns.
+    pub sync: bool,
+}
+
+impl MountOptions {
+    /// Parse a comma-separated option string, such as `"ro,noatime,cache=128"`.
+    ///
+    /// # Parameters
+    ///
+    /// * `options` - The option string, the empty options are ignored.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The default options, changed by the given ones.
+    /// * `Err(&'static str)` - If an option is unknown or has an invalid value.
+    pub fn parse(options: &str) -> Result<Self, &'static str> {
+        let mut parsed = Self::default();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (option, None),
+            };
+            match (name, value) {
+                ("ro", None) => parsed.read_only = true,
+                ("rw", None) => parsed.read_only = false,
+                ("noatime", None) => parsed.atime = AtimePolicy::NoAtime,
+                ("relatime", None) => parsed.atime = AtimePolicy::Relatime,
+                ("strictatime", None) => parsed.atime = AtimePolicy::Strict,
+                ("cache", Some(value)) => {
+                    parsed.cache_blocks =
+                        Some(value.parse().map_err(|_| "Invalid mount option value")?)
+                }
+                ("errors", Some(value)) => {
+                    parsed.errors = match value {
+                        "continue" => ErrorBehavior::Continue,
+                        "remount-ro" => ErrorBehavior::RemountReadOnly,
+                        "panic" => ErrorBehavior::Panic,
+                        _ => return Err("Invalid mount option value"),
+                    }
+                }
+                ("csum", Some(value)) => {
+                    parsed.checksum_policy = match value {
+                        "enforce" => ChecksumPolicy::Enforce,
+                        "ro" => ChecksumPolicy::ReadOnly,
+                        _ => return Err("Invalid mount option value"),
+                    }
+                }
+                ("journal", Some(value)) => {
+                    parsed.journal = match value {
+                        "none" => JournalMode::None,
+                        "ordered" => JournalMode::Ordered,
+                        "writeback" => JournalMode::Writeback,
+                        "data" => JournalMode::Data,
+                        _ => return Err("Invalid mount option value"),
+                    }
+                }
+                ("sync", None) => parsed.sync = true,
+                ("async", None) => parsed.sync = false,
+                _ => return Err("Unknown mount option"),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Set [`MountOptions::read_only`].
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Set [`MountOptions::atime`].
+    pub fn atime(mut self, atime: AtimePolicy) -> Self {
+        self.atime = atime;
+        self
+    }
+
+    /// Set [`MountOptions::cache_blocks`].
+    pub fn cache_blocks(mut self, cache_blocks: usize) -> Self {
+        self.cache_blocks = Some(cache_blocks);
+        self
+    }
+
+    /// Set [`MountOptions::errors`].
+    pub fn errors(mut self, errors: ErrorBehavior) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// Set [`MountOptions::checksum_policy`].
+    pub fn checksum_policy(mut self, checksum_policy: ChecksumPolicy) -> Self {
+        self.checksum_policy = checksum_policy;
+        self
+    }
+
+    /// Set [`MountOptions::journal`].
+    pub fn journal(mut self, journal: JournalMode) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Set [`MountOptions::sync`].
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+}
+
+/// The time after which [`AtimePolicy::Relatime`] updates an access time anyway, in seconds.
+const RELATIME_PERIOD: u64 = 24 * 60 * 60;
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Handle a corruption of the file system, following [`FileSystem::errors`].
+    ///
+    /// # Parameters
+    ///
+    /// * `error` - The error of the operation which found it.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(&'static str)` - The error, unless it panics.
+    pub(crate) fn corruption_error<T>(&mut self, error: &'static str) -> Result<T, &'static str> {
+        match self.errors {
+            ErrorBehavior::Continue => {}
+            ErrorBehavior::RemountReadOnly => self.read_only = true,
+            ErrorBehavior::Panic => panic!("proka-fs: {}", error),
+        }
+        Err(error)
+    }
+
+    /// Update the access time of an inode which was just read, following
+    /// [`FileSystem::atime`].
+    ///
+    /// Nothing is written if the file system is read-only.
+    pub(crate) fn touch_atime(&mut self, inode: &mut Inode) -> Result<(), &'static str> {
+        if self.read_only {
+            return Ok(());
+        }
+        let now = (self.clock)();
+        let update = match self.atime {
+            AtimePolicy::NoAtime => false,
+            AtimePolicy::Relatime => {
+                inode.atime <= inode.mtime
+                    || inode.atime <= inode.ctime
+                    || now >= inode.atime.saturating_add(RELATIME_PERIOD)
+            }
+            AtimePolicy::Strict => true,
+        };
+        // Nothing changes within the same second.
+        if update && now != inode.atime {
+            inode.atime = now;
+            self.write_inode(inode)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the block device after a change if [`FileSystem::sync_writes`] is set.
+    ///
+    /// # Parameters
+    ///
+    /// * `result` - The result of the change, which is flushed even if it failed halfway.
+    pub(crate) fn sync_write<T>(
+        &mut self,
+        result: Result<T, &'static str>,
+    ) -> Result<T, &'static str> {
+        if !self.sync_writes {
+            return result;
+        }
+        let flushed = self.block_device.flush();
+        result.and_then(|value| flushed.map(|()| value))
+    }
+}
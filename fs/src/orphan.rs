@@ -0,0 +1,169 @@
+//! The open inodes, and the orphan list of the files unlinked while still open.
+//!
+//! A process holding a file keeps it with [`FileSystem::open_inode`]. If its last
+//! link is removed meanwhile, the inode and its blocks aren't released, but the
+//! inode is put in the orphan list, from [`SuperBlock::orphan_head`] through
+//! [`Inode::next_orphan`]. It's released by the last [`FileSystem::close_inode`],
+//! or on the next mount if the file system wasn't cleanly unmounted.
+//!
+//! The list is on the device, so a crash never leaks the orphans. A crash while
+//! changing it only leaves an inode in no directory, which the checker reports
+//! as a warning.
+//!
+//! [`SuperBlock::orphan_head`]: crate::definition::SuperBlock::orphan_head
+
+use crate::definition::Inode;
+use crate::{BlockDevice, FileSystem, error};
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Hold an inode, so it isn't released by unlinking it until it's closed.
+    ///
+    /// An inode can be held several times, and must be closed as many times.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode to hold.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the inode is held.
+    /// * `Err(&'static str)` - If the inode isn't used.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use proka_fs::FileSystem;
+    /// use proka_fs::mkfs::{FormatOptions, format};
+    /// use proka_fs::ram::RamBlockDevice;
+    ///
+    /// let mut bd = RamBlockDevice::new(vec![0u8; 1024 * 1024]);
+    /// # let options = FormatOptions {
+    /// #     uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+    /// #     ..FormatOptions::default()
+    /// # };
+    /// format(&mut bd, 1024 * 1024, &options).unwrap();
+    ///
+    /// let mut fs = FileSystem::mount(bd).unwrap();
+    /// fs.mkfile(0, "tmp").unwrap();
+    /// let tmp = fs.lookup("/tmp").unwrap();
+    /// fs.open_inode(tmp).unwrap();
+    /// fs.unlink(0, "tmp").unwrap();
+    ///
+    /// // The file is still there for its holder.
+    /// fs.write_at(tmp, 0, b"scratch").unwrap();
+    /// fs.close_inode(tmp).unwrap();
+    /// assert!(fs.stat(tmp).is_err());
+    /// ```
+    pub fn open_inode(&mut self, inode_id: u32) -> Result<(), &'static str> {
+        self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        *self.open_inodes.entry(inode_id).or_insert(0) += 1;
+        Ok(())
+    }
+
+    /// Release a hold on an inode, see [`FileSystem::open_inode`].
+    ///
+    /// If it was the last hold and the inode has no link anymore, it's released.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode to close.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the inode is closed.
+    /// * `Err(&'static str)` - If the inode isn't open, or can't be released.
+    pub fn close_inode(&mut self, inode_id: u32) -> Result<(), &'static str> {
+        let count = self
+            .open_inodes
+            .get_mut(&inode_id)
+            .ok_or("Inode not open")?;
+        *count -= 1;
+        if *count > 0 {
+            return Ok(());
+        }
+        self.open_inodes.remove(&inode_id);
+
+        // A read-only file system keeps its orphans for the next mount.
+        let inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        if inode.links_count > 0 || self.read_only {
+            return Ok(());
+        }
+        self.remove_orphan(&inode)?;
+        let result = self.release_inode(&inode);
+        self.sync_write(result)
+    }
+
+    /// Check if an inode is open, see [`FileSystem::open_inode`].
+    pub fn is_open(&self, inode_id: u32) -> bool {
+        self.open_inodes.contains_key(&inode_id)
+    }
+
+    /// Put an inode without links in the orphan list.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode, whose link count is already 0.
+    pub(crate) fn add_orphan(&mut self, mut inode: Inode) -> Result<(), &'static str> {
+        inode.ctime = (self.clock)();
+        inode.next_orphan = self.super_block.orphan_head;
+        self.write_inode(&inode)?;
+        self.super_block.orphan_head = inode.inode_id;
+        self.write_super_block()
+    }
+
+    /// Take an inode out of the orphan list, before releasing it.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode` - The inode, which is in the list.
+    fn remove_orphan(&mut self, inode: &Inode) -> Result<(), &'static str> {
+        if self.super_block.orphan_head == inode.inode_id {
+            self.super_block.orphan_head = inode.next_orphan;
+            return self.write_super_block();
+        }
+
+        // The list can't be longer than the inode table, unless it's broken.
+        let mut current = self.super_block.orphan_head;
+        for _ in 0..self.super_block.inode_count() {
+            if current == 0 {
+                break;
+            }
+            let mut previous = self.read_inode(current)?;
+            if previous.next_orphan == inode.inode_id {
+                previous.next_orphan = inode.next_orphan;
+                return self.write_inode(&previous);
+            }
+            current = previous.next_orphan;
+        }
+        Err("Inode not in the orphan list")
+    }
+
+    /// Release every orphan, when nothing is open anymore.
+    ///
+    /// The orphans are taken out of the list one at a time before being released,
+    /// so an interrupted release leaks at most one inode.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the orphans are released, or the list was broken and dropped.
+    /// * `Err(&'static str)` - If the file system can't be written.
+    pub(crate) fn release_orphans(&mut self) -> Result<(), &'static str> {
+        for _ in 0..self.super_block.inode_count() {
+            let inode_id = self.super_block.orphan_head;
+            if inode_id == 0 {
+                return Ok(());
+            }
+            // A broken list is dropped, what it held is only leaked.
+            let Some(inode) = self.get_inode(inode_id) else {
+                break;
+            };
+            self.super_block.orphan_head = inode.next_orphan;
+            self.write_super_block()?;
+            if inode.links_count == 0 {
+                self.release_inode(&inode)?;
+            }
+        }
+        self.super_block.orphan_head = 0;
+        self.write_super_block()
+    }
+}
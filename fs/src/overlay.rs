@@ -0,0 +1,251 @@
+//! The copy-on-write overlay, which keeps a base block device untouched.
+//!
+//! [`OverlayBlockDevice`] reads from a base device, but writes every modified
+//! block to a separate delta device. The delta can later be committed into the
+//! base, or rolled back to get the pristine base again.
+//!
+//! The delta is either kept in memory ([`MemoryDelta`], the default) or in any
+//! other block device of the same geometry, such as a sparse sidecar file.
+//!
+//! # Note
+//!
+//! The list of the modified blocks is kept in memory only, it isn't stored in
+//! the delta device. So the delta can't be saved and reopened by another overlay,
+//! even in a sidecar file: commit before dropping the overlay to keep the changes.
+//!
+//! # Example
+//!
+//! ```rust
+//! use proka_fs::FileSystem;
+//! use proka_fs::mkfs::{FormatOptions, format};
+//! use proka_fs::overlay::OverlayBlockDevice;
+//! use proka_fs::ram::{RamBlockDevice, RomBlockDevice};
+//!
+//! let mut image = vec![0u8; 1024 * 1024];
+//! # let options = FormatOptions {
+//! #     uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+//! #     ..FormatOptions::default()
+//! # };
+//! format(&mut RamBlockDevice::new(&mut image[..]), 1024 * 1024, &options).unwrap();
+//!
+//! // The base is read-only, every write goes to memory.
+//! let mut fs = FileSystem::mount(OverlayBlockDevice::new(RomBlockDevice::new(&image[..]))).unwrap();
+//! fs.mkdir(0, "tmp").unwrap();
+//! assert!(fs.lookup("/tmp").is_ok());
+//!
+//! // Forget about the changes.
+//! fs.block_device.rollback().unwrap();
+//! ```
+
+use crate::{BLOCK_SIZE, BlockDevice};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+/// A sparse block device stored in memory, used as the delta of an overlay.
+///
+/// Only the written blocks take memory, the others read as zeroes.
+#[derive(Default)]
+pub struct MemoryDelta {
+    /// The written blocks.
+    blocks: BTreeMap<u32, Vec<u8>>,
+}
+
+impl MemoryDelta {
+    /// Create an empty delta.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlockDevice for MemoryDelta {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        let mut pos = offset as usize;
+        let mut done = 0;
+        while done < buf.len() {
+            let block = block_num + (pos / BLOCK_SIZE) as u32;
+            let in_block = pos % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - in_block).min(buf.len() - done);
+
+            let dst = &mut buf[done..done + len];
+            match self.blocks.get(&block) {
+                Some(data) => dst.copy_from_slice(&data[in_block..in_block + len]),
+                None => dst.fill(0),
+            }
+            pos += len;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let mut pos = offset as usize;
+        let mut done = 0;
+        while done < buf.len() {
+            let block = block_num + (pos / BLOCK_SIZE) as u32;
+            let in_block = pos % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - in_block).min(buf.len() - done);
+
+            let data = self
+                .blocks
+                .entry(block)
+                .or_insert_with(|| alloc::vec![0u8; BLOCK_SIZE]);
+            data[in_block..in_block + len].copy_from_slice(&buf[done..done + len]);
+            pos += len;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        self.blocks.retain(|block, _| !blocks.contains(block));
+        Ok(())
+    }
+}
+
+/// A block device writing to a delta device instead of its base device.
+pub struct OverlayBlockDevice<B: BlockDevice, D: BlockDevice = MemoryDelta> {
+    /// The base block device, only written by `commit`.
+    base: B,
+
+    /// The block device storing the modified blocks, at the same block numbers.
+    delta: D,
+
+    /// The blocks which are in the delta.
+    changed: BTreeSet<u32>,
+}
+
+impl<B: BlockDevice> OverlayBlockDevice<B> {
+    /// Create an overlay keeping the delta in memory.
+    ///
+    /// # Parameters
+    ///
+    /// * `base` - The block device to keep untouched.
+    pub fn new(base: B) -> Self {
+        Self::with_delta(base, MemoryDelta::new())
+    }
+}
+
+impl<B: BlockDevice, D: BlockDevice> OverlayBlockDevice<B, D> {
+    /// Create an overlay keeping the delta in another block device.
+    ///
+    /// # Parameters
+    ///
+    /// * `base` - The block device to keep untouched.
+    /// * `delta` - The block device storing the modified blocks, which must be
+    ///   as large as `base` (a sparse file is a good fit).
+    pub fn with_delta(base: B, delta: D) -> Self {
+        Self {
+            base,
+            delta,
+            changed: BTreeSet::new(),
+        }
+    }
+
+    /// Get the number of modified blocks.
+    pub fn changed_blocks(&self) -> usize {
+        self.changed.len()
+    }
+
+    /// Write the modified blocks into the base device, and empty the delta.
+    pub fn commit(&mut self) -> Result<(), &'static str> {
+        let mut data = alloc::vec![0u8; BLOCK_SIZE];
+        for &block in &self.changed {
+            self.delta.read_block(block, 0, &mut data)?;
+            self.base.write_block(block, 0, &data)?;
+        }
+        self.base.flush()?;
+        self.rollback()
+    }
+
+    /// Drop the modified blocks, so the device reads like the base device again.
+    pub fn rollback(&mut self) -> Result<(), &'static str> {
+        if let (Some(&first), Some(&last)) = (self.changed.first(), self.changed.last()) {
+            // The blocks between are not used in the delta, discarding them too is fine.
+            self.delta.discard(first..last + 1)?;
+        }
+        self.changed.clear();
+        Ok(())
+    }
+
+    /// Get the base and the delta devices, the delta is lost if not committed.
+    pub fn into_parts(self) -> (B, D) {
+        (self.base, self.delta)
+    }
+}
+
+impl<B: BlockDevice, D: BlockDevice> BlockDevice for OverlayBlockDevice<B, D> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        // Each block comes from either device.
+        let mut pos = offset as usize;
+        let mut done = 0;
+        while done < buf.len() {
+            let block = block_num + (pos / BLOCK_SIZE) as u32;
+            let in_block = pos % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - in_block).min(buf.len() - done);
+
+            let dst = &mut buf[done..done + len];
+            if self.changed.contains(&block) {
+                self.delta.read_block(block, in_block as u32, dst)?;
+            } else {
+                self.base.read_block(block, in_block as u32, dst)?;
+            }
+            pos += len;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let mut pos = offset as usize;
+        let mut done = 0;
+        while done < buf.len() {
+            let block = block_num + (pos / BLOCK_SIZE) as u32;
+            let in_block = pos % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - in_block).min(buf.len() - done);
+
+            if len < BLOCK_SIZE && !self.changed.contains(&block) {
+                // Copy the rest of the block before modifying it.
+                let mut data = alloc::vec![0u8; BLOCK_SIZE];
+                self.base.read_block(block, 0, &mut data)?;
+                self.delta.write_block(block, 0, &data)?;
+            }
+            self.delta
+                .write_block(block, in_block as u32, &buf[done..done + len])?;
+            self.changed.insert(block);
+            pos += len;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.delta.flush()
+    }
+
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        // The discarded content is undefined, so the base content will do.
+        if self.changed.range(blocks.clone()).next().is_some() {
+            self.changed.retain(|block| !blocks.contains(block));
+            self.delta.discard(blocks)?;
+        }
+        Ok(())
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        self.base.block_count()
+    }
+
+    fn block_size(&self) -> u32 {
+        self.base.block_size()
+    }
+}
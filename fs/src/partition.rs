@@ -0,0 +1,327 @@
+//! The discovery of ProkaFS partitions in MBR and GPT partition tables.
+//!
+//! A partition is a ProkaFS partition if its type is [`PKFS_MBR_TYPE`] or
+//! [`PKFS_GPT_TYPE`], or if it starts with a ProkaFS super block (so that
+//! partitions with a generic type, such as "Linux data", are found as well).
+//!
+//! # Note
+//!
+//! Sectors are 512 bytes. Logical partitions inside an MBR extended partition
+//! are not listed.
+//!
+//! The GPT header and partition entries are checked against their CRC-32, and
+//! the backup GPT at the end of the disk is used if the primary one is broken.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use proka_fs::{FileSystem, init_block_device};
+//! use proka_fs::partition::find_pkfs;
+//!
+//! let disk = init_block_device("disk.img").unwrap();
+//! let mut fs = FileSystem::mount(find_pkfs(disk).unwrap()).unwrap();
+//! ```
+//!
+//! A bootloader looks for its root file system by UUID or label instead:
+//!
+//! ```no_run
+//! use proka_fs::{FileSystem, init_block_device};
+//! use proka_fs::partition::find_by_label;
+//!
+//! let disk = init_block_device("disk.img").unwrap();
+//! let mut fs = FileSystem::mount(find_by_label(disk, "root").unwrap()).unwrap();
+//! ```
+
+use crate::crc32::crc32;
+use crate::definition::{MAGIC, SuperBlock, Uuid};
+use crate::range::RangeBlockDevice;
+use crate::{BLOCK_SIZE, BlockDevice, GenericFsData};
+use alloc::vec::Vec;
+
+/// The size of a sector in bytes.
+pub const SECTOR_SIZE: u64 = 512;
+
+/// The MBR partition type of ProkaFS.
+pub const PKFS_MBR_TYPE: u8 = 0x7F;
+
+/// The GPT partition type GUID of ProkaFS, `504B4653-524F-4B41-9F53-50524F4B4146`,
+/// in its on-disk byte order.
+pub const PKFS_GPT_TYPE: [u8; 16] = [
+    0x53, 0x46, 0x4B, 0x50, 0x4F, 0x52, 0x41, 0x4B, 0x9F, 0x53, 0x50, 0x52, 0x4F, 0x4B, 0x41, 0x46,
+];
+
+/// The MBR partition type of a GPT protective MBR.
+const GPT_PROTECTIVE_TYPE: u8 = 0xEE;
+
+/// The type of a partition, as written in the partition table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    /// The type ID of an MBR partition.
+    Mbr(u8),
+
+    /// The type GUID of a GPT partition, in its on-disk byte order.
+    Gpt([u8; 16]),
+}
+
+/// A partition found in a partition table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    /// The number of the partition, starting from 1.
+    pub number: u32,
+
+    /// The type of the partition.
+    pub partition_type: PartitionType,
+
+    /// The start of the partition in bytes.
+    pub start: u64,
+
+    /// The length of the partition in bytes.
+    pub len: u64,
+
+    /// Whether the partition is a ProkaFS partition.
+    pub is_pkfs: bool,
+}
+
+impl Partition {
+    /// Open the partition as a block device, ready to be mounted.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device of the whole disk.
+    pub fn open<B: BlockDevice>(&self, bd: B) -> Result<RangeBlockDevice<B>, &'static str> {
+        RangeBlockDevice::new(bd, self.start, self.len)
+    }
+
+    /// Read the super block of the partition.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device of the whole disk.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(SuperBlock))` - The super block, if the partition holds a valid one.
+    /// * `Ok(None)` - If the partition isn't a ProkaFS partition.
+    /// * `Err(&'static str)` - If the device can't be read.
+    pub fn read_super_block<B: BlockDevice>(
+        &self,
+        bd: &mut B,
+    ) -> Result<Option<SuperBlock>, &'static str> {
+        if !self.is_pkfs || self.len < SuperBlock::SIZE as u64 {
+            return Ok(None);
+        }
+        let mut buf = [0u8; SuperBlock::SIZE];
+        read_bytes(bd, self.start, &mut buf)?;
+        Ok(SuperBlock::from_bytes(&buf).filter(|super_block| super_block.validate().is_ok()))
+    }
+}
+
+/// Read the partition table of a disk.
+///
+/// # Parameters
+///
+/// * `bd` - The block device of the whole disk.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Partition>)` - The partitions, empty if there is no partition table.
+/// * `Err(&'static str)` - If the device can't be read or the table is broken.
+pub fn read_partitions<B: BlockDevice>(bd: &mut B) -> Result<Vec<Partition>, &'static str> {
+    let mut mbr = [0u8; SECTOR_SIZE as usize];
+    bd.read_block(0, 0, &mut mbr)?;
+    if mbr[510..512] != [0x55, 0xAA] {
+        return Ok(Vec::new());
+    }
+
+    // 1. Read the 4 primary partitions.
+    let mut partitions = Vec::new();
+    for (i, entry) in mbr[446..510].chunks_exact(16).enumerate() {
+        let partition_type = entry[4];
+        let start = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+        let sectors = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as u64;
+        if partition_type == GPT_PROTECTIVE_TYPE {
+            return read_gpt(bd);
+        }
+        if partition_type == 0 || sectors == 0 {
+            continue;
+        }
+        partitions.push(Partition {
+            number: i as u32 + 1,
+            partition_type: PartitionType::Mbr(partition_type),
+            start: start * SECTOR_SIZE,
+            len: sectors * SECTOR_SIZE,
+            is_pkfs: partition_type == PKFS_MBR_TYPE,
+        });
+    }
+
+    // 2. Probe the others for a super block.
+    for partition in partitions.iter_mut().filter(|p| !p.is_pkfs) {
+        partition.is_pkfs = probe(bd, partition.start)?;
+    }
+    Ok(partitions)
+}
+
+/// Find the first ProkaFS partition of a disk, and open it.
+///
+/// # Parameters
+///
+/// * `bd` - The block device of the whole disk.
+pub fn find_pkfs<B: BlockDevice>(mut bd: B) -> Result<RangeBlockDevice<B>, &'static str> {
+    let partition = read_partitions(&mut bd)?
+        .into_iter()
+        .find(|partition| partition.is_pkfs)
+        .ok_or("No ProkaFS partition found")?;
+    partition.open(bd)
+}
+
+/// Find the ProkaFS partition with a UUID, and open it.
+///
+/// # Parameters
+///
+/// * `bd` - The block device of the whole disk.
+/// * `uuid` - The UUID of the file system.
+pub fn find_by_uuid<B: BlockDevice>(
+    bd: B,
+    uuid: Uuid,
+) -> Result<RangeBlockDevice<B>, &'static str> {
+    find_by(bd, |super_block| super_block.uuid == uuid)
+}
+
+/// Find the first ProkaFS partition with a label, and open it.
+///
+/// # Parameters
+///
+/// * `bd` - The block device of the whole disk.
+/// * `label` - The label of the file system.
+pub fn find_by_label<B: BlockDevice>(
+    bd: B,
+    label: &str,
+) -> Result<RangeBlockDevice<B>, &'static str> {
+    find_by(bd, |super_block| super_block.label() == label)
+}
+
+/// Find the first ProkaFS partition whose super block matches, and open it.
+fn find_by<B: BlockDevice>(
+    mut bd: B,
+    matches: impl Fn(&SuperBlock) -> bool,
+) -> Result<RangeBlockDevice<B>, &'static str> {
+    for partition in read_partitions(&mut bd)? {
+        if partition
+            .read_super_block(&mut bd)?
+            .is_some_and(|super_block| matches(&super_block))
+        {
+            return partition.open(bd);
+        }
+    }
+    Err("No matching ProkaFS partition found")
+}
+
+/// Read the partitions of a GPT disk.
+///
+/// The backup table at the end of the disk is used if the primary one is broken,
+/// which needs the size of the device.
+fn read_gpt<B: BlockDevice>(bd: &mut B) -> Result<Vec<Partition>, &'static str> {
+    // 1. Read the primary table, which is in the second sector, or the backup one.
+    let (entries, entry_size) = match read_gpt_table(bd, 1) {
+        Ok(table) => table,
+        Err(e) => {
+            let last_lba = bd
+                .block_count()
+                .map(|count| count as u64 * BLOCK_SIZE as u64 / SECTOR_SIZE)
+                .filter(|&sectors| sectors > 2)
+                .ok_or(e)?
+                - 1;
+            read_gpt_table(bd, last_lba).map_err(|_| e)?
+        }
+    };
+
+    // 2. Read the partition entries.
+    let mut partitions = Vec::new();
+    for (i, entry) in entries.chunks_exact(entry_size).enumerate() {
+        let type_guid: [u8; 16] = entry[0..16].try_into().unwrap();
+        if type_guid == [0; 16] {
+            continue;
+        }
+        let first = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+        let last = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+        let (Some(start), Some(len)) = (
+            first.checked_mul(SECTOR_SIZE),
+            last.checked_sub(first)
+                .and_then(|sectors| sectors.checked_add(1))
+                .and_then(|sectors| sectors.checked_mul(SECTOR_SIZE)),
+        ) else {
+            return Err("Invalid GPT partition entry");
+        };
+        let is_pkfs = type_guid == PKFS_GPT_TYPE || probe(bd, start)?;
+        partitions.push(Partition {
+            number: i as u32 + 1,
+            partition_type: PartitionType::Gpt(type_guid),
+            start,
+            len,
+            is_pkfs,
+        });
+    }
+    Ok(partitions)
+}
+
+/// Read a GPT header and its partition entries, checking their CRC-32.
+///
+/// # Parameters
+///
+/// * `bd` - The block device of the whole disk.
+/// * `lba` - The sector of the header.
+///
+/// # Returns
+///
+/// * `Ok((Vec<u8>, usize))` - The partition entries, and the size of an entry.
+/// * `Err(&'static str)` - If the device can't be read, or the header or the
+///   entries are broken.
+fn read_gpt_table<B: BlockDevice>(bd: &mut B, lba: u64) -> Result<(Vec<u8>, usize), &'static str> {
+    // 1. Check the header, whose CRC is computed with its own field zeroed.
+    let mut header = [0u8; SECTOR_SIZE as usize];
+    read_bytes(bd, lba * SECTOR_SIZE, &mut header)?;
+    let header_size = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+    if &header[0..8] != b"EFI PART" || !(92..=SECTOR_SIZE as usize).contains(&header_size) {
+        return Err("Invalid GPT header");
+    }
+    let header_crc = u32::from_le_bytes(header[16..20].try_into().unwrap());
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc
+        || u64::from_le_bytes(header[24..32].try_into().unwrap()) != lba
+    {
+        return Err("Invalid GPT header");
+    }
+    let entries_start = u64::from_le_bytes(header[72..80].try_into().unwrap())
+        .checked_mul(SECTOR_SIZE)
+        .ok_or("Invalid GPT header")?;
+    let entry_count = u32::from_le_bytes(header[80..84].try_into().unwrap()) as usize;
+    let entry_size = u32::from_le_bytes(header[84..88].try_into().unwrap()) as usize;
+    if !(128..=4096).contains(&entry_size) || entry_count > 1024 {
+        return Err("Invalid GPT header");
+    }
+
+    // 2. Check the partition entries.
+    let mut entries = alloc::vec![0u8; entry_count * entry_size];
+    read_bytes(bd, entries_start, &mut entries)?;
+    if crc32(&entries) != u32::from_le_bytes(header[88..92].try_into().unwrap()) {
+        return Err("Invalid GPT partition entries");
+    }
+    Ok((entries, entry_size))
+}
+
+/// Check if a ProkaFS super block is at a byte position of the disk.
+fn probe<B: BlockDevice>(bd: &mut B, pos: u64) -> Result<bool, &'static str> {
+    let mut magic = [0u8; 4];
+    match read_bytes(bd, pos, &mut magic) {
+        Ok(()) => Ok(u32::from_le_bytes(magic) == MAGIC),
+        // The partition table may point beyond the end of the disk.
+        Err(_) => Ok(false),
+    }
+}
+
+/// Read bytes at a byte position of the disk.
+fn read_bytes<B: BlockDevice>(bd: &mut B, pos: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+    let block_num =
+        u32::try_from(pos / BLOCK_SIZE as u64).map_err(|_| "Position beyond the disk")?;
+    bd.read_block(block_num, (pos % BLOCK_SIZE as u64) as u32, buf)
+}
@@ -0,0 +1,140 @@
+//! The block devices backed by memory, which work in `no_std`.
+//!
+//! - [`RamBlockDevice`] is a read-write device over a `Vec<u8>` or a `&mut [u8]`,
+//!   useful for RAM disks and tests;
+//! - [`RomBlockDevice`] is a read-only device over a `&'static [u8]`, useful to
+//!   mount an image embedded in the kernel with `include_bytes!`.
+
+use crate::{BLOCK_SIZE, BlockDevice, error};
+
+/// Get the byte range of a block device access, checking it's inside the device.
+fn byte_range(
+    len: usize,
+    block_num: u32,
+    offset: u32,
+    size: usize,
+) -> Result<core::ops::Range<usize>, &'static str> {
+    let start = block_num as usize * BLOCK_SIZE + offset as usize;
+    let end = start.checked_add(size).ok_or("Block out of range")?;
+    if end > len {
+        return Err("Block out of range");
+    }
+    Ok(start..end)
+}
+
+/// A read-write block device stored in memory.
+///
+/// # Example
+///
+/// ```rust
+/// use proka_fs::BlockDevice;
+/// use proka_fs::ram::RamBlockDevice;
+///
+/// // Over a vector, which owns the memory...
+/// let mut bd = RamBlockDevice::new(vec![0u8; 64 * 1024]);
+/// bd.write_block(1, 0, b"hello").unwrap();
+///
+/// // ...or over a borrowed buffer.
+/// let mut memory = [0u8; 4096];
+/// let mut bd = RamBlockDevice::new(&mut memory[..]);
+/// assert_eq!(bd.block_count(), Some(4));
+/// ```
+pub struct RamBlockDevice<T: AsRef<[u8]> + AsMut<[u8]>>(T);
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> RamBlockDevice<T> {
+    /// Create a block device over some memory.
+    pub fn new(memory: T) -> Self {
+        Self(memory)
+    }
+
+    /// Get the memory of the device.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> AsRef<[u8]> for RamBlockDevice<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> BlockDevice for RamBlockDevice<T> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        let memory = self.0.as_ref();
+        let range = byte_range(memory.len(), block_num, offset, buf.len())?;
+        buf.copy_from_slice(&memory[range]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let memory = self.0.as_mut();
+        let range = byte_range(memory.len(), block_num, offset, buf.len())?;
+        memory[range].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        let memory = self.0.as_mut();
+        let size = blocks.len() * BLOCK_SIZE;
+        let range = byte_range(memory.len(), blocks.start, 0, size)?;
+        memory[range].fill(0);
+        Ok(())
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        Some((self.0.as_ref().len() / BLOCK_SIZE) as u32)
+    }
+}
+
+/// A read-only block device stored in memory.
+///
+/// Every write fails, so the file system must be mounted with
+/// [`FileSystem::mount_read_only`](crate::FileSystem::mount_read_only).
+///
+/// # Example
+///
+/// ```ignore
+/// use proka_fs::FileSystem;
+/// use proka_fs::ram::RomBlockDevice;
+///
+/// static ROOT_IMAGE: &[u8] = include_bytes!("../initrd.img");
+///
+/// let mut fs = FileSystem::mount_read_only(RomBlockDevice::new(ROOT_IMAGE)).unwrap();
+/// let init = fs.lookup("/sbin/init").unwrap();
+/// ```
+pub struct RomBlockDevice<T: AsRef<[u8]> = &'static [u8]>(T);
+
+impl<T: AsRef<[u8]>> RomBlockDevice<T> {
+    /// Create a read-only block device over some memory.
+    pub const fn new(memory: T) -> Self {
+        Self(memory)
+    }
+}
+
+impl<T: AsRef<[u8]>> BlockDevice for RomBlockDevice<T> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        let memory = self.0.as_ref();
+        let range = byte_range(memory.len(), block_num, offset, buf.len())?;
+        buf.copy_from_slice(&memory[range]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, _: u32, _: u32, _: &[u8]) -> Result<(), &'static str> {
+        Err(error::READ_ONLY_DEVICE)
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        Some((self.0.as_ref().len() / BLOCK_SIZE) as u32)
+    }
+}
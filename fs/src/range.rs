@@ -0,0 +1,156 @@
+//! The block device adapter exposing a window of another block device.
+//!
+//! It's used to reach a partition inside a whole-disk image, without carving
+//! the partition out first.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use proka_fs::{FileSystem, init_block_device};
+//! use proka_fs::range::RangeBlockDevice;
+//!
+//! let disk = init_block_device("disk.img").unwrap();
+//!
+//! // The partition starts at 1 MiB, and is 64 MiB long.
+//! let partition = RangeBlockDevice::new(disk, 1024 * 1024, 64 * 1024 * 1024).unwrap();
+//! let mut fs = FileSystem::mount(partition).unwrap();
+//! ```
+
+use crate::{BLOCK_SIZE, BlockDevice};
+
+/// A block device exposing the bytes `start..start + len` of another block device.
+pub struct RangeBlockDevice<B: BlockDevice> {
+    /// The wrapped block device.
+    inner: B,
+
+    /// The start of the window in bytes.
+    start: u64,
+
+    /// The length of the window in bytes.
+    len: u64,
+}
+
+impl<B: BlockDevice> RangeBlockDevice<B> {
+    /// Create a window of a block device.
+    ///
+    /// # Parameters
+    ///
+    /// * `inner` - The block device to wrap.
+    /// * `start` - The start of the window in bytes.
+    /// * `len` - The length of the window in bytes.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(&'static str)` - If the window goes beyond the end of `inner`.
+    pub fn new(mut inner: B, start: u64, len: u64) -> Result<Self, &'static str> {
+        let end = start.checked_add(len).ok_or("Range out of device")?;
+        if let Some(count) = inner.block_count()
+            && end > count as u64 * BLOCK_SIZE as u64
+        {
+            return Err("Range out of device");
+        }
+        Ok(Self { inner, start, len })
+    }
+
+    /// Get the start of the window in bytes.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Get the length of the window in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Check if the window is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the wrapped block device.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Translate an access to the position in the wrapped device, checking the bounds.
+    fn translate(
+        &self,
+        block_num: u32,
+        offset: u32,
+        size: usize,
+    ) -> Result<(u32, u32), &'static str> {
+        let pos = block_num as u64 * BLOCK_SIZE as u64 + offset as u64;
+        if pos + size as u64 > self.len {
+            return Err("Block out of range");
+        }
+        let pos = self.start + pos;
+        Ok((
+            (pos / BLOCK_SIZE as u64) as u32,
+            (pos % BLOCK_SIZE as u64) as u32,
+        ))
+    }
+
+    /// Check if the window starts on a block boundary of the wrapped device.
+    fn is_aligned(&self) -> bool {
+        self.start.is_multiple_of(BLOCK_SIZE as u64)
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for RangeBlockDevice<B> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        let (block_num, offset) = self.translate(block_num, offset, buf.len())?;
+        self.inner.read_block(block_num, offset, buf)
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let (block_num, offset) = self.translate(block_num, offset, buf.len())?;
+        self.inner.write_block(block_num, offset, buf)
+    }
+
+    fn read_blocks(&mut self, block_num: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+        let (block_num, offset) = self.translate(block_num, 0, buf.len())?;
+        if self.is_aligned() {
+            self.inner.read_blocks(block_num, buf)
+        } else {
+            self.inner.read_block(block_num, offset, buf)
+        }
+    }
+
+    fn write_blocks(&mut self, block_num: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let (block_num, offset) = self.translate(block_num, 0, buf.len())?;
+        if self.is_aligned() {
+            self.inner.write_blocks(block_num, buf)
+        } else {
+            self.inner.write_block(block_num, offset, buf)
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.inner.flush()
+    }
+
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        let (start, _) = self.translate(blocks.start, 0, blocks.len() * BLOCK_SIZE)?;
+        if !self.is_aligned() {
+            // The blocks don't match the wrapped device's ones, discarding is only a hint.
+            return Ok(());
+        }
+        self.inner.discard(start..start + blocks.len() as u32)
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        Some((self.len / BLOCK_SIZE as u64) as u32)
+    }
+
+    fn block_size(&self) -> u32 {
+        self.inner.block_size()
+    }
+}
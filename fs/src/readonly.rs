@@ -0,0 +1,133 @@
+//! The read-only mount, which never writes to the block device.
+//!
+//! [`FileSystem::mount_read_only`] wraps the device in [`ReadOnly`], whose
+//! writes fail without reaching it, so a `FileSystem<ReadOnly<B>>` can't modify
+//! `B` whatever happens. Nothing is written on mount either: the state stays as
+//! it is, and the orphans are kept for the next read-write mount.
+//!
+//! It's meant for the devices which can't be written, such as an image embedded
+//! in the kernel or a write-protected medium, and for the tools inspecting a file
+//! system without changing it.
+//!
+//! # Example
+//!
+//! ```rust
+//! use proka_fs::FileSystem;
+//! use proka_fs::mkfs::{FormatOptions, format};
+//! use proka_fs::ram::{RamBlockDevice, RomBlockDevice};
+//!
+//! let mut image = vec![0u8; 1024 * 1024];
+//! # let options = FormatOptions {
+//! #     uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+//! #     ..FormatOptions::default()
+//! # };
+//! format(&mut RamBlockDevice::new(&mut image[..]), 1024 * 1024, &options).unwrap();
+//!
+//! let mut fs = FileSystem::mount_read_only(RomBlockDevice::new(&image[..])).unwrap();
+//! assert_eq!(fs.ls(0).unwrap().len(), 2);
+//! assert_eq!(fs.mkdir(0, "dir"), Err("Read-only file system"));
+//! ```
+
+use crate::{BlockDevice, FileSystem, MountOptions, error};
+
+/// A block device adapter which refuses every write.
+///
+/// The reads go to the wrapped device, and the writes and discards fail with
+/// `"Read-only device"` without reaching it.
+pub struct ReadOnly<B: BlockDevice>(B);
+
+impl<B: BlockDevice> ReadOnly<B> {
+    /// Wrap a block device.
+    pub const fn new(inner: B) -> Self {
+        Self(inner)
+    }
+
+    /// Get the wrapped block device, which can only be read through this adapter.
+    pub fn get_ref(&self) -> &B {
+        &self.0
+    }
+
+    /// Get the wrapped block device.
+    pub fn into_inner(self) -> B {
+        self.0
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for ReadOnly<B> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        self.0.read_block(block_num, offset, buf)
+    }
+
+    fn write_block(&mut self, _: u32, _: u32, _: &[u8]) -> Result<(), &'static str> {
+        Err(error::READ_ONLY_DEVICE)
+    }
+
+    fn read_blocks(&mut self, block_num: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+        self.0.read_blocks(block_num, buf)
+    }
+
+    fn write_blocks(&mut self, _: u32, _: &[u8]) -> Result<(), &'static str> {
+        Err(error::READ_ONLY_DEVICE)
+    }
+
+    fn read_blocks_vectored(
+        &mut self,
+        block_num: u32,
+        bufs: &mut [&mut [u8]],
+    ) -> Result<(), &'static str> {
+        self.0.read_blocks_vectored(block_num, bufs)
+    }
+
+    fn write_blocks_vectored(&mut self, _: u32, _: &[&[u8]]) -> Result<(), &'static str> {
+        Err(error::READ_ONLY_DEVICE)
+    }
+
+    fn discard(&mut self, _: core::ops::Range<u32>) -> Result<(), &'static str> {
+        Err(error::READ_ONLY_DEVICE)
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        self.0.block_count()
+    }
+
+    fn block_size(&self) -> u32 {
+        self.0.block_size()
+    }
+}
+
+impl<B: BlockDevice> FileSystem<ReadOnly<B>> {
+    /// Mount the file system read-only.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device driver, which is never written.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The mounted file system, whose changes all fail.
+    /// * `Err(&'static str)` - See [`FileSystem::mount`].
+    pub fn mount_read_only(bd: B) -> Result<Self, &'static str> {
+        Self::mount_read_only_with(bd, &MountOptions::default())
+    }
+
+    /// Mount the file system read-only with some options.
+    ///
+    /// # Parameters
+    ///
+    /// * `bd` - The block device driver, which is never written.
+    /// * `options` - The mount options, which are read-only whatever
+    ///   [`MountOptions::read_only`] is.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The mounted file system, whose changes all fail.
+    /// * `Err(&'static str)` - See [`FileSystem::mount_with`].
+    pub fn mount_read_only_with(bd: B, options: &MountOptions) -> Result<Self, &'static str> {
+        Self::mount_with(ReadOnly::new(bd), &options.read_only(true))
+    }
+}
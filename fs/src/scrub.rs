@@ -0,0 +1,263 @@
+//! The scrub, which reads every allocated block to find the silent corruptions.
+//!
+//! Unlike the [`check`](crate::check), which checks the structure of the file
+//! system, the scrub checks what the blocks hold against their checksums: the
+//! metadata with the `metadata_csum` feature, and the data with `data_csum`. The
+//! blocks without a checksum are read all the same, so the read errors of the
+//! device show up.
+
+use crate::checksum::ChecksumPolicy;
+use crate::definition::{FileType, Inode};
+use crate::options::ErrorBehavior;
+use crate::{BlockDevice, FileSystem, GenericFsData, error};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A block which doesn't match its checksum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    /// The block number.
+    pub block: u32,
+
+    /// The inode owning the block, `None` for the bitmap.
+    ///
+    /// For the inode table, it's the corrupted inode itself.
+    pub inode: Option<u32>,
+
+    /// A path of the inode, `None` if it's in no directory which could be read.
+    pub path: Option<String>,
+}
+
+impl core::fmt::Display for Corruption {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.inode, &self.path) {
+            (None, _) => write!(f, "block {} of the bitmap is corrupted", self.block),
+            (Some(inode), None) => {
+                write!(f, "block {} of inode {inode} is corrupted", self.block)
+            }
+            (Some(inode), Some(path)) => {
+                write!(
+                    f,
+                    "block {} of inode {inode} ({path}) is corrupted",
+                    self.block
+                )
+            }
+        }
+    }
+}
+
+/// The result of a scrub.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// The number of blocks read.
+    pub blocks_read: u64,
+
+    /// The corrupted blocks found.
+    pub corruptions: Vec<Corruption>,
+}
+
+impl ScrubReport {
+    /// Check if no corruption was found.
+    pub fn is_clean(&self) -> bool {
+        self.corruptions.is_empty()
+    }
+}
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Read every allocated block, and check the ones with checksums.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ScrubReport)` - The corrupted blocks found.
+    /// * `Err(&'static str)` - If the block device can't be read.
+    ///
+    /// # Note
+    ///
+    /// The file system isn't modified, and isn't made read-only by the corruptions
+    /// found, whatever [`FileSystem::checksum_policy`] is.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use proka_fs::FileSystem;
+    /// use proka_fs::definition::feature::{self, Features};
+    /// use proka_fs::mkfs::{FormatOptions, format};
+    /// use proka_fs::ram::RamBlockDevice;
+    ///
+    /// let mut bd = RamBlockDevice::new(vec![0u8; 1024 * 1024]);
+    /// let options = FormatOptions {
+    ///     features: Features::default().with(feature::DATA_CSUM),
+    /// #   uuid: Some("0123abcd-4567-89ab-cdef-0123456789ab".parse().unwrap()),
+    ///     ..FormatOptions::default()
+    /// };
+    /// format(&mut bd, 1024 * 1024, &options).unwrap();
+    ///
+    /// let mut fs = FileSystem::mount(bd).unwrap();
+    /// fs.mkfile(0, "file").unwrap();
+    /// let file = fs.lookup("/file").unwrap();
+    /// fs.write_at(file, 0, b"hello").unwrap();
+    /// assert!(fs.scrub().unwrap().is_clean());
+    /// ```
+    pub fn scrub(&mut self) -> Result<ScrubReport, &'static str> {
+        let mut report = ScrubReport::default();
+        let super_block = self.super_block;
+        let block_size = super_block.block_size as usize;
+        let mut buf = alloc::vec![0u8; block_size];
+
+        /* Stage 1: Check the bitmap */
+        for block in super_block.bitmap_start_block..super_block.total_block {
+            self.block_device.read_block(block, 0, &mut buf)?;
+            report.blocks_read += 1;
+            if !self.block_checksum_matches(block, &buf)? {
+                report.corruptions.push(Corruption {
+                    block,
+                    inode: None,
+                    path: None,
+                });
+            }
+        }
+
+        /* Stage 2: Check the inode table */
+        // The inodes which can't be decoded are left to the checker.
+        let mut inodes = BTreeMap::new();
+        let mut corrupted_inodes = Vec::new();
+        for block in 1..super_block.data_start_block {
+            self.block_device.read_block(block, 0, &mut buf)?;
+            report.blocks_read += 1;
+            for (i, chunk) in buf.chunks_exact(Inode::SIZE).enumerate() {
+                let inode_id = ((block - 1) as usize * (block_size / Inode::SIZE) + i) as u32;
+                let Some(inode) = Inode::from_bytes(chunk).filter(|inode| inode.is_used) else {
+                    continue;
+                };
+                if self.has_metadata_csum() && !Inode::checksum_matches(chunk) {
+                    corrupted_inodes.push((block, inode_id));
+                } else {
+                    inodes.insert(inode_id, inode);
+                }
+            }
+        }
+
+        /* Stage 3: Find a path of every inode */
+        // The directories which can't be read are reported below, so a failure
+        // here must not make the file system read-only, nor panic.
+        let policy = core::mem::replace(&mut self.checksum_policy, ChecksumPolicy::Enforce);
+        let errors = core::mem::replace(&mut self.errors, ErrorBehavior::Continue);
+        let paths = self.find_paths(&inodes);
+        self.checksum_policy = policy;
+        self.errors = errors;
+        let paths = paths?;
+        for (block, inode) in corrupted_inodes {
+            report.corruptions.push(Corruption {
+                block,
+                inode: Some(inode),
+                path: paths.get(&inode).cloned(),
+            });
+        }
+
+        /* Stage 4: Check the blocks of every file */
+        for (&inode_id, inode) in &inodes {
+            let checksummed = self.has_block_checksums(inode);
+            let mut data_blocks = BTreeSet::new();
+            for index in 0..inode.file_length.div_ceil(block_size as u64) {
+                let pointer = self.find_block(inode, index as u32)?;
+                let block = self.pointer_block(pointer);
+                // The holes and the broken pointers, which are left to the checker.
+                if !self.is_data_block(block) {
+                    continue;
+                }
+                data_blocks.insert(block);
+                // The unwritten blocks have no data to check.
+                if self.is_unwritten(pointer) {
+                    continue;
+                }
+                self.block_device.read_block(block, 0, &mut buf)?;
+                report.blocks_read += 1;
+                if checksummed && !self.block_checksum_matches(block, &buf)? {
+                    report.corruptions.push(Corruption {
+                        block,
+                        inode: Some(inode_id),
+                        path: paths.get(&inode_id).cloned(),
+                    });
+                }
+            }
+
+            // The pointer blocks and the extended attributes are metadata.
+            let mut other_blocks = self.file_blocks(inode)?;
+            other_blocks.push(inode.xattr_block);
+            for block in other_blocks {
+                if self.is_data_block(block) && !data_blocks.contains(&block) {
+                    self.block_device.read_block(block, 0, &mut buf)?;
+                    report.blocks_read += 1;
+                    if !self.block_checksum_matches(block, &buf)? {
+                        report.corruptions.push(Corruption {
+                            block,
+                            inode: Some(inode_id),
+                            path: paths.get(&inode_id).cloned(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Walk the directory tree to find a path of every inode.
+    ///
+    /// # Parameters
+    ///
+    /// * `inodes` - The used inodes, which the directories are read from.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(BTreeMap<u32, String>)` - The first path found of each inode.
+    /// * `Err(&'static str)` - If the block device can't be read.
+    fn find_paths(
+        &mut self,
+        inodes: &BTreeMap<u32, Inode>,
+    ) -> Result<BTreeMap<u32, String>, &'static str> {
+        let mut paths = BTreeMap::from([(0, String::from("/"))]);
+        let mut pending = alloc::vec![0u32];
+        while let Some(dir) = pending.pop() {
+            let Some(dir_inode) = inodes
+                .get(&dir)
+                .filter(|inode| inode.file_type == FileType::Directory)
+            else {
+                continue;
+            };
+            let entries = match self.read_dir_entries(dir_inode) {
+                Ok(entries) => entries,
+                Err(
+                    error::CHECKSUM_MISMATCH
+                    | error::CORRUPTED_BLOCK_POINTER
+                    | error::CORRUPTED_DIRECTORY
+                    | error::INVALID_DIR_ENTRY,
+                ) => {
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let dir_path = paths[&dir].clone();
+            for entry in entries {
+                let name = entry.name_bytes();
+                if name.is_empty()
+                    || name == b"."
+                    || name == b".."
+                    || paths.contains_key(&entry.inode)
+                {
+                    continue;
+                }
+                let name = String::from_utf8_lossy(name);
+                let path = if dir_path == "/" {
+                    alloc::format!("/{name}")
+                } else {
+                    alloc::format!("{dir_path}/{name}")
+                };
+                paths.insert(entry.inode, path);
+                pending.push(entry.inode);
+            }
+        }
+        Ok(paths)
+    }
+}
@@ -0,0 +1,277 @@
+//! The extended attributes (xattr) of the inodes.
+//!
+//! Small attributes are stored inline in the inode's spare bytes, and the others
+//! are stored in a dedicated attribute block referenced by `Inode::xattr_block`.
+//!
+//! The attribute block has a checksum with the `metadata_csum` feature, see
+//! [`crate::checksum`].
+//!
+//! Both areas use the same encoding, one attribute after another:
+//!
+//! | Offset | Size          | Description                         |
+//! |--------|---------------|-------------------------------------|
+//! | 0      | 1             | The namespace (0 ends the list)     |
+//! | 1      | 1             | The name length                     |
+//! | 2      | 2             | The value length (little endian)    |
+//! | 4      | name length   | The name, without namespace prefix  |
+//! | ...    | value length  | The value                           |
+
+use crate::definition::{Inode, feature};
+use crate::{BlockDevice, FileSystem, error};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The size of an attribute header.
+const HEADER_SIZE: usize = 4;
+
+/// The namespace of an extended attribute.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum XattrNamespace {
+    /// The attributes of the user (`user.`).
+    User = 1,
+
+    /// The attributes only visible to privileged processes (`trusted.`).
+    Trusted = 2,
+
+    /// The security labels and capabilities (`security.`).
+    Security = 3,
+
+    /// The attributes used by the kernel itself (`system.`).
+    System = 4,
+}
+
+impl XattrNamespace {
+    /// Get the prefix of the namespace, including the dot.
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::User => "user.",
+            Self::Trusted => "trusted.",
+            Self::Security => "security.",
+            Self::System => "system.",
+        }
+    }
+
+    /// Split a full attribute name into its namespace and its name.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use proka_fs::xattr::XattrNamespace;
+    /// let (namespace, name) = XattrNamespace::parse("security.selinux").unwrap();
+    /// assert_eq!(namespace, XattrNamespace::Security);
+    /// assert_eq!(name, "selinux");
+    /// ```
+    pub fn parse(full_name: &str) -> Option<(Self, &str)> {
+        [Self::User, Self::Trusted, Self::Security, Self::System]
+            .into_iter()
+            .find_map(|namespace| {
+                full_name
+                    .strip_prefix(namespace.prefix())
+                    .map(|name| (namespace, name))
+            })
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::User),
+            2 => Some(Self::Trusted),
+            3 => Some(Self::Security),
+            4 => Some(Self::System),
+            _ => None,
+        }
+    }
+}
+
+/// An extended attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Xattr {
+    namespace: XattrNamespace,
+    name: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Xattr {
+    /// The size of this attribute once encoded.
+    fn encoded_len(&self) -> usize {
+        HEADER_SIZE + self.name.len() + self.value.len()
+    }
+
+    /// Encode this attribute at the start of `buf`.
+    fn encode(&self, buf: &mut [u8]) {
+        let name_end = HEADER_SIZE + self.name.len();
+        buf[0] = self.namespace as u8;
+        buf[1] = self.name.len() as u8;
+        buf[2..4].copy_from_slice(&(self.value.len() as u16).to_le_bytes());
+        buf[HEADER_SIZE..name_end].copy_from_slice(&self.name);
+        buf[name_end..name_end + self.value.len()].copy_from_slice(&self.value);
+    }
+}
+
+/// Decode all attributes of an area, and append them to `out`.
+fn decode(area: &[u8], out: &mut Vec<Xattr>) -> Result<(), &'static str> {
+    let mut pos = 0;
+    while pos + HEADER_SIZE <= area.len() && area[pos] != 0 {
+        let namespace = XattrNamespace::from_u8(area[pos]).ok_or("Invalid attribute namespace")?;
+        let name_len = area[pos + 1] as usize;
+        let value_len = u16::from_le_bytes([area[pos + 2], area[pos + 3]]) as usize;
+        let name_start = pos + HEADER_SIZE;
+        let value_start = name_start + name_len;
+        let end = value_start + value_len;
+        if end > area.len() {
+            return Err("Corrupted attribute area");
+        }
+        out.push(Xattr {
+            namespace,
+            name: area[name_start..value_start].to_vec(),
+            value: area[value_start..end].to_vec(),
+        });
+        pos = end;
+    }
+    Ok(())
+}
+
+impl<B: BlockDevice> FileSystem<B> {
+    /// Set an extended attribute, replacing the old value if it exists.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode to attach the attribute to.
+    /// * `name` - The full name of the attribute, such as `user.package`.
+    /// * `value` - The value of the attribute.
+    pub fn setxattr(
+        &mut self,
+        inode_id: u32,
+        name: &str,
+        value: &[u8],
+    ) -> Result<(), &'static str> {
+        self.check_writable()?;
+        if !self.super_block.features().contains(feature::XATTR) {
+            return Err("Extended attributes are disabled");
+        }
+        let (namespace, name) = XattrNamespace::parse(name).ok_or("Unsupported namespace")?;
+        if name.is_empty() || name.len() > u8::MAX as usize {
+            return Err("Invalid attribute name");
+        }
+        let attr = Xattr {
+            namespace,
+            name: name.as_bytes().to_vec(),
+            value: value.to_vec(),
+        };
+        if attr.encoded_len() > self.super_block.block_size as usize {
+            return Err("Attribute too large");
+        }
+
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        let mut attrs = self.load_xattrs(&inode)?;
+        match attrs
+            .iter_mut()
+            .find(|a| a.namespace == attr.namespace && a.name == attr.name)
+        {
+            Some(old) => old.value = attr.value,
+            None => attrs.push(attr),
+        }
+        let result = self.store_xattrs(&mut inode, &attrs);
+        self.sync_write(result)
+    }
+
+    /// Get the value of an extended attribute.
+    ///
+    /// # Parameters
+    ///
+    /// * `inode_id` - The inode which owns the attribute.
+    /// * `name` - The full name of the attribute, such as `user.package`.
+    pub fn getxattr(&mut self, inode_id: u32, name: &str) -> Result<Vec<u8>, &'static str> {
+        let (namespace, name) = XattrNamespace::parse(name).ok_or("Unsupported namespace")?;
+        let inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        self.load_xattrs(&inode)?
+            .into_iter()
+            .find(|a| a.namespace == namespace && a.name == name.as_bytes())
+            .map(|a| a.value)
+            .ok_or("Attribute not found")
+    }
+
+    /// List the full names of all extended attributes of an inode.
+    pub fn listxattr(&mut self, inode_id: u32) -> Result<Vec<String>, &'static str> {
+        let inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        Ok(self
+            .load_xattrs(&inode)?
+            .into_iter()
+            .map(|a| {
+                let mut name = String::from(a.namespace.prefix());
+                name.push_str(&String::from_utf8_lossy(&a.name));
+                name
+            })
+            .collect())
+    }
+
+    /// Remove an extended attribute.
+    pub fn removexattr(&mut self, inode_id: u32, name: &str) -> Result<(), &'static str> {
+        self.check_writable()?;
+        let (namespace, name) = XattrNamespace::parse(name).ok_or("Unsupported namespace")?;
+        let mut inode = self.get_inode(inode_id).ok_or(error::INODE_NOT_FOUND)?;
+        let mut attrs = self.load_xattrs(&inode)?;
+        let index = attrs
+            .iter()
+            .position(|a| a.namespace == namespace && a.name == name.as_bytes())
+            .ok_or("Attribute not found")?;
+        attrs.remove(index);
+        let result = self.store_xattrs(&mut inode, &attrs);
+        self.sync_write(result)
+    }
+
+    /// Read all the attributes of an inode, inline ones first.
+    fn load_xattrs(&mut self, inode: &Inode) -> Result<Vec<Xattr>, &'static str> {
+        let mut attrs = Vec::new();
+        decode(&inode.inline_xattr, &mut attrs).or_else(|e| self.corruption_error(e))?;
+        if inode.xattr_block != 0 {
+            let xattr_block = self.check_block_pointer(inode.xattr_block)?;
+            let mut buf = alloc::vec![0u8; self.super_block.block_size as usize];
+            self.read_checksummed_block(xattr_block, &mut buf)?;
+            decode(&buf, &mut attrs).or_else(|e| self.corruption_error(e))?;
+        }
+        Ok(attrs)
+    }
+
+    /// Pack the attributes into the inode and its attribute block, then write them back.
+    ///
+    /// The attribute block is allocated or released as needed.
+    fn store_xattrs(&mut self, inode: &mut Inode, attrs: &[Xattr]) -> Result<(), &'static str> {
+        let mut inline = [0u8; crate::definition::INLINE_XATTR_SIZE];
+        let mut block = alloc::vec![0u8; self.super_block.block_size as usize];
+        let (mut inline_pos, mut block_pos) = (0, 0);
+
+        for attr in attrs {
+            let len = attr.encoded_len();
+            if inline_pos + len <= inline.len() {
+                attr.encode(&mut inline[inline_pos..]);
+                inline_pos += len;
+            } else if block_pos + len <= block.len() {
+                attr.encode(&mut block[block_pos..]);
+                block_pos += len;
+            } else {
+                return Err("No space for attribute");
+            }
+        }
+
+        let mut unused_block = 0;
+        if block_pos > 0 {
+            if inode.xattr_block == 0 {
+                inode.xattr_block = self.alloc_block()?;
+            }
+            self.write_checksummed_block(inode.xattr_block, &block)?;
+        } else {
+            unused_block = core::mem::take(&mut inode.xattr_block);
+        }
+
+        inode.inline_xattr = inline;
+        inode.ctime = (self.clock)();
+        self.write_inode(inode)?;
+
+        // Only free the block once the inode doesn't point to it anymore.
+        if unused_block != 0 {
+            self.free_block(unused_block)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! The tests of the backups of the super block.
+
+mod common;
+
+use common::{IMAGE_SIZE, format_options, fresh_image};
+use proka_fs::check::Problem;
+use proka_fs::definition::SuperBlock;
+use proka_fs::definition::feature::{self, Features};
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{ChecksumPolicy, FileSystem, GenericFsData, MountOptions};
+
+/// Get a block of an image.
+fn block(image: &[u8], block: u32) -> &[u8] {
+    &image[block as usize * 1024..][..1024]
+}
+
+/// Get the blocks of the backups of a fresh image.
+fn backup_blocks() -> Vec<u32> {
+    SuperBlock::new(IMAGE_SIZE as u64).backup_blocks()
+}
+
+#[test]
+fn format_writes_the_backups() {
+    let image = fresh_image();
+    let backups = backup_blocks();
+    assert_eq!(backups, [544, 1022]);
+    for &backup in &backups {
+        assert_eq!(block(&image, backup), block(&image, 0));
+    }
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert_eq!(fs.backup_used, None);
+    assert!(fs.check().unwrap().is_clean());
+    // The backups are never given to a file.
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, &vec![0x5A; 600 * 1024]).unwrap();
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn mount_falls_back_to_a_backup() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, b"hello").unwrap();
+    fs.unmount().unwrap();
+    image[..1024].fill(0);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert_eq!(fs.backup_used, Some(backup_blocks()[0]));
+    let file = fs.lookup("/file").unwrap();
+    let mut buf = [0u8; 5];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    // The primary super block is repaired on sync.
+    fs.sync().unwrap();
+    assert_eq!(fs.backup_used, None);
+    fs.unmount().unwrap();
+    assert_eq!(block(&image, 0), block(&image, backup_blocks()[0]));
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert_eq!(fs.backup_used, None);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn mount_uses_the_next_valid_backup() {
+    let mut image = fresh_image();
+    image[..1024].fill(0);
+    let first = backup_blocks()[0] as usize * 1024;
+    image[first..first + 4].fill(0);
+
+    let fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert_eq!(fs.backup_used, Some(backup_blocks()[1]));
+}
+
+#[test]
+fn mount_fails_without_a_valid_super_block() {
+    let mut image = fresh_image();
+    for backup in std::iter::once(0).chain(backup_blocks()) {
+        image[backup as usize * 1024..][..4].fill(0);
+    }
+    assert_eq!(
+        FileSystem::mount(RamBlockDevice::new(image)).err(),
+        Some("Invalid super block")
+    );
+}
+
+#[test]
+fn sync_updates_the_backups() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.super_block.set_label("renamed").unwrap();
+    fs.unmount().unwrap();
+
+    for backup in backup_blocks() {
+        let super_block = SuperBlock::from_bytes(block(&image, backup)).unwrap();
+        assert_eq!(super_block.label(), "renamed");
+    }
+}
+
+#[test]
+fn checker_reports_broken_backups() {
+    let [first, last] = backup_blocks()[..] else {
+        panic!("two backups expected");
+    };
+    let mut image = fresh_image();
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    image[super_block.bitmap_start_block as usize * 1024 + first as usize] = 0;
+    image[last as usize * 1024..][..1024].fill(0);
+
+    // Changing the bitmap by hand breaks its checksum as well.
+    let mut fs = FileSystem::mount_with(
+        RamBlockDevice::new(image),
+        &MountOptions::default().checksum_policy(ChecksumPolicy::ReadOnly),
+    )
+    .unwrap();
+    let report = fs.check().unwrap();
+    assert_eq!(
+        report.problems,
+        [
+            Problem::Corrupted {
+                block: super_block.bitmap_start_block
+            },
+            Problem::UnreservedBlock { block: first },
+            Problem::StaleBackup { block: last },
+            // The image is clean, so the counters aren't rebuilt from the bitmap.
+            Problem::WrongFreeBlocks {
+                stored: super_block.free_blocks,
+                actual: super_block.free_blocks + 1,
+            },
+        ]
+    );
+    assert!(!Problem::StaleBackup { block: last }.is_error());
+    assert!(!report.is_consistent());
+}
+
+#[test]
+fn backups_can_be_disabled() {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: Features::default().without(feature::BACKUP_SUPER),
+        ..format_options()
+    };
+    let super_block = format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+    assert!(super_block.backup_blocks().is_empty());
+    assert_eq!(
+        super_block.free_blocks,
+        SuperBlock::from_bytes(&fresh_image()).unwrap().free_blocks + 2
+    );
+
+    image[..4].fill(0);
+    assert_eq!(
+        FileSystem::mount(RamBlockDevice::new(image)).err(),
+        Some("Invalid super block")
+    );
+}
@@ -0,0 +1,210 @@
+//! The tests of the block cache.
+
+mod common;
+
+use common::{Fault, FaultyBlockDevice, fresh_image};
+use proka_fs::cache::CachedBlockDevice;
+use proka_fs::options::MountOptions;
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{BlockDevice, FileSystem};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// An access to the device, as logged by [`LoggingBlockDevice`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read(u32),
+    Write(u32),
+    Discard(u32),
+}
+
+/// An in-memory block device logging every access by block.
+struct LoggingBlockDevice {
+    memory: Vec<u8>,
+    block_size: usize,
+    log: Rc<RefCell<Vec<Access>>>,
+}
+
+impl LoggingBlockDevice {
+    /// Create a device of 16 blocks, and get its log.
+    fn new(block_size: usize) -> (Self, Rc<RefCell<Vec<Access>>>) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let bd = Self {
+            memory: vec![0; 16 * block_size],
+            block_size,
+            log: log.clone(),
+        };
+        (bd, log)
+    }
+}
+
+impl BlockDevice for LoggingBlockDevice {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        self.log.borrow_mut().push(Access::Read(block_num));
+        let start = block_num as usize * self.block_size + offset as usize;
+        buf.copy_from_slice(&self.memory[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        self.log.borrow_mut().push(Access::Write(block_num));
+        let start = block_num as usize * self.block_size + offset as usize;
+        self.memory[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn discard(&mut self, blocks: std::ops::Range<u32>) -> Result<(), &'static str> {
+        self.log.borrow_mut().push(Access::Discard(blocks.start));
+        Ok(())
+    }
+
+    fn block_size(&self) -> u32 {
+        self.block_size as u32
+    }
+}
+
+#[test]
+fn the_least_recently_used_block_is_evicted() {
+    let (bd, log) = LoggingBlockDevice::new(1024);
+    let mut cache = CachedBlockDevice::new(bd, 2);
+    let mut buf = [0u8; 4];
+    for block in [1, 2, 1, 3] {
+        cache.read_block(block, 0, &mut buf).unwrap();
+    }
+    assert_eq!(cache.cached_blocks(), 2);
+    // Block 1 is still cached, block 2 was evicted.
+    cache.read_block(1, 0, &mut buf).unwrap();
+    cache.read_block(2, 0, &mut buf).unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [1, 2, 3, 2].map(Access::Read),
+        "only the misses reach the device"
+    );
+}
+
+#[test]
+fn dirty_blocks_are_written_back_once_in_change_order() {
+    let (bd, log) = LoggingBlockDevice::new(1024);
+    let mut cache = CachedBlockDevice::new(bd, 8);
+    // Whole blocks aren't read first.
+    cache.write_block(5, 0, &[5; 1024]).unwrap();
+    cache.write_block(3, 0, &[3; 1024]).unwrap();
+    // Merged with the last change.
+    cache.write_block(3, 10, &[33; 4]).unwrap();
+    assert_eq!(cache.dirty_blocks(), 2);
+    assert!(log.borrow().is_empty());
+
+    // Block 5 changed again after block 3: its first change reaches the device first.
+    cache.write_block(5, 0, &[55; 4]).unwrap();
+    assert_eq!(*log.borrow(), [Access::Write(5)]);
+    assert_eq!(cache.dirty_blocks(), 2);
+
+    // The last changes are written back when the cache is dropped.
+    drop(cache);
+    assert_eq!(log.borrow()[1..], [Access::Write(3), Access::Write(5)]);
+}
+
+#[test]
+fn evicting_a_dirty_block_writes_the_older_changes_first() {
+    let (bd, log) = LoggingBlockDevice::new(1024);
+    let mut cache = CachedBlockDevice::new(bd, 2);
+    cache.write_block(7, 0, &[7; 1024]).unwrap();
+    cache.write_block(4, 0, &[4; 1024]).unwrap();
+    // Use block 7 again, so block 4 is evicted first.
+    cache.read_block(7, 0, &mut [0; 4]).unwrap();
+    cache.read_block(9, 0, &mut [0; 4]).unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [Access::Write(7), Access::Write(4), Access::Read(9)]
+    );
+    assert_eq!(cache.dirty_blocks(), 0);
+}
+
+#[test]
+fn discarded_blocks_are_dropped() {
+    let (bd, log) = LoggingBlockDevice::new(1024);
+    let mut cache = CachedBlockDevice::new(bd, 8);
+    cache.write_block(2, 0, &[2; 1024]).unwrap();
+    cache.write_block(6, 0, &[6; 1024]).unwrap();
+    // The other dirty blocks are written back first.
+    cache.discard(2..3).unwrap();
+    assert_eq!((cache.cached_blocks(), cache.dirty_blocks()), (1, 0));
+
+    cache.flush().unwrap();
+    cache.read_block(2, 0, &mut [0; 4]).unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [Access::Write(6), Access::Discard(2), Access::Read(2)]
+    );
+}
+
+#[test]
+fn the_device_block_size_is_used() {
+    let (bd, log) = LoggingBlockDevice::new(512);
+    let mut cache = CachedBlockDevice::new(bd, 8);
+    assert_eq!(cache.block_size(), 512);
+    // Across two blocks of the device.
+    cache.write_block(1, 500, &[9; 24]).unwrap();
+    let mut buf = [0u8; 24];
+    cache.read_block(1, 500, &mut buf).unwrap();
+    assert_eq!(buf, [9; 24]);
+    cache.flush().unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [
+            Access::Read(1),
+            Access::Read(2),
+            Access::Write(1),
+            Access::Write(2)
+        ]
+    );
+    let bd = cache.get_ref();
+    assert_eq!(bd.memory[1012..1036], [9; 24]);
+}
+
+/// Create, write and delete some files.
+fn workload<B: BlockDevice>(fs: &mut FileSystem<B>) -> Result<(), &'static str> {
+    fs.mkdir(0, "dir")?;
+    let dir = fs.lookup("/dir")?;
+    fs.mkfile(dir, "file")?;
+    let file = fs.lookup("/dir/file")?;
+    fs.write_at(file, 0, &[1; 20 * 1024])?;
+    fs.mkfile(0, "other")?;
+    fs.unlink(dir, "file")?;
+    fs.sync()
+}
+
+#[test]
+fn crashes_behind_a_small_cache_leave_a_consistent_file_system() {
+    let options = MountOptions::default().cache_blocks(4);
+
+    for count in 0.. {
+        let mut image = fresh_image();
+        let bd = FaultyBlockDevice::new(RamBlockDevice::new(&mut image[..]));
+        let mut fs = FileSystem::mount_cached(bd, &options).unwrap();
+        fs.sync().unwrap();
+        let faulty = fs.block_device.get_mut();
+        faulty.fail_after = Some(faulty.writes + count);
+        faulty.fault = Fault::Drop;
+        // Once the writes are dropped, what's read back may be stale and fail it.
+        let _ = workload(&mut fs);
+        let done = fs.block_device.get_ref().writes <= count;
+        std::mem::forget(fs);
+
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        let report = fs.check().unwrap();
+        assert!(
+            report.is_consistent(),
+            "after {count} writes: {:?}",
+            report.problems
+        );
+        if done {
+            break;
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! The tests of the checksums of the metadata.
+
+mod common;
+
+use common::{IMAGE_SIZE, format_options, fresh_image};
+use proka_fs::check::Problem;
+use proka_fs::crc32c::{crc32c, crc32c_append};
+use proka_fs::definition::feature::{self, Features};
+use proka_fs::definition::{Inode, SuperBlock};
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{BlockDevice, ChecksumPolicy, FileSystem, GenericFsData, MountOptions};
+
+/// Create an image with a file in a directory.
+fn populated_image() -> Vec<u8> {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    fs.mkfile(dir, "file").unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    fs.write_at(file, 0, b"hello").unwrap();
+    fs.unmount().unwrap();
+    image
+}
+
+/// Flip a bit of the image.
+fn flip(image: &mut [u8], pos: usize) {
+    image[pos] ^= 0x10;
+}
+
+#[test]
+fn crc32c_matches_the_reference_values() {
+    assert_eq!(crc32c(b""), 0);
+    assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    assert_eq!(crc32c(&[0; 32]), 0x8A91_36AA);
+    assert_eq!(crc32c(&[0xFF; 32]), 0x62A8_AB43);
+    assert_eq!(
+        crc32c_append(crc32c(b"1234"), b"56789"),
+        crc32c(b"123456789")
+    );
+}
+
+#[test]
+fn new_file_systems_are_clean() {
+    let image = populated_image();
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    assert!(super_block.features().contains(feature::METADATA_CSUM));
+    let table = super_block.checksum_table_blocks();
+    assert_eq!(table, 66..74);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert!(fs.check().unwrap().is_clean());
+    // The table is never given to a file.
+    fs.mkfile(0, "big").unwrap();
+    let big = fs.lookup("/big").unwrap();
+    fs.write_at(big, 0, &[0x5A; 64 * 1024]).unwrap();
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn corrupted_inodes_are_refused() {
+    let mut image = populated_image();
+    let file = {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        fs.lookup("/dir/file").unwrap()
+    };
+    // Somewhere in the middle of the direct blocks.
+    let pos = 1024 + file as usize * Inode::SIZE + 40;
+    flip(&mut image, pos);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert_eq!(fs.corrupted_block, None);
+    assert!(fs.stat(file).is_err());
+    assert_eq!(fs.corrupted_block, Some(1));
+    let report = fs.check().unwrap();
+    assert!(report.problems.contains(&Problem::Corrupted { block: 1 }));
+    assert!(!report.is_consistent());
+}
+
+#[test]
+fn corrupted_directories_are_refused() {
+    let mut image = populated_image();
+    // The block of "/dir", whose third entry is "file".
+    let entry = 2 * 256 + 4;
+    let block = (0..IMAGE_SIZE / 1024)
+        .find(|&block| image[block * 1024 + entry..].starts_with(b"file\0"))
+        .unwrap() as u32;
+    flip(&mut image, block as usize * 1024 + entry);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert_eq!(fs.lookup("/dir/file").err(), Some("Checksum mismatch"));
+    assert_eq!(fs.corrupted_block, Some(block));
+    assert!(fs.lookup("/dir").is_ok());
+    let report = fs.check().unwrap();
+    assert!(report.problems.contains(&Problem::Corrupted { block }));
+    assert!(!report.is_consistent());
+}
+
+#[test]
+fn corrupted_pointer_and_attribute_blocks_are_refused() {
+    let mut image = fresh_image();
+    let file = {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        fs.mkfile(0, "file").unwrap();
+        let file = fs.lookup("/file").unwrap();
+        // Large enough to need the indirect block.
+        fs.write_at(file, 0, &[0x5A; 14 * 1024]).unwrap();
+        fs.setxattr(file, "user.block", &[0xA5; 200]).unwrap();
+        fs.unmount().unwrap();
+        file
+    };
+    let inode = Inode::from_bytes(&image[1024 + file as usize * Inode::SIZE..]).unwrap();
+    let (indirect, xattr) = (inode.indirect_block, inode.xattr_block);
+    // A byte past the pointers and past the attribute, so both stay valid.
+    flip(&mut image, indirect as usize * 1024 + 1000);
+    flip(&mut image, xattr as usize * 1024 + 1000);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    let mut buf = [0u8; 1024];
+    assert_eq!(fs.read_at(file, 0, &mut buf), Ok(1024));
+    assert_eq!(
+        fs.read_at(file, 13 * 1024, &mut buf),
+        Err("Checksum mismatch")
+    );
+    assert_eq!(fs.corrupted_block, Some(indirect));
+    assert_eq!(fs.getxattr(file, "user.block"), Err("Checksum mismatch"));
+    assert_eq!(fs.corrupted_block, Some(xattr));
+    let blocks: Vec<u32> = fs
+        .scrub()
+        .unwrap()
+        .corruptions
+        .iter()
+        .map(|corruption| corruption.block)
+        .collect();
+    assert_eq!(blocks, [indirect, xattr]);
+}
+
+/// A block device counting the reads of a block.
+struct CountingBlockDevice<B: BlockDevice> {
+    inner: B,
+    block: u32,
+    reads: usize,
+}
+
+impl<B: BlockDevice> BlockDevice for CountingBlockDevice<B> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        if block_num == self.block {
+            self.reads += 1;
+        }
+        self.inner.read_block(block_num, offset, buf)
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        self.inner.write_block(block_num, offset, buf)
+    }
+
+    fn block_size(&self) -> u32 {
+        self.inner.block_size()
+    }
+}
+
+#[test]
+fn pointer_blocks_are_verified_once_per_read() {
+    let mut image = fresh_image();
+    let file = {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        fs.mkfile(0, "file").unwrap();
+        let file = fs.lookup("/file").unwrap();
+        fs.write_at(file, 0, &[0x5A; 100 * 1024]).unwrap();
+        fs.unmount().unwrap();
+        file
+    };
+    let inode = Inode::from_bytes(&image[1024 + file as usize * Inode::SIZE..]).unwrap();
+    let device = CountingBlockDevice {
+        inner: RamBlockDevice::new(image),
+        block: inode.indirect_block,
+        reads: 0,
+    };
+
+    let mut fs = FileSystem::mount(device).unwrap();
+    let mut buf = vec![0u8; 100 * 1024];
+    assert_eq!(fs.read_at(file, 0, &mut buf), Ok(buf.len()));
+    assert!(buf.iter().all(|&byte| byte == 0x5A));
+    assert_eq!(fs.block_device.reads, 1);
+}
+
+#[test]
+fn corrupted_bitmaps_are_refused_or_mounted_read_only() {
+    let mut image = populated_image();
+    let bitmap_start = SuperBlock::from_bytes(&image).unwrap().bitmap_start_block;
+    let mut unclean = image.clone();
+    let fs = FileSystem::mount(RamBlockDevice::new(&mut unclean[..])).unwrap();
+    std::mem::forget(fs);
+    // A free block at the end of the data area.
+    flip(&mut image, bitmap_start as usize * 1024 + 1000);
+    flip(&mut unclean, bitmap_start as usize * 1024 + 1000);
+
+    // The mount recounts the free blocks of a file system not cleanly unmounted.
+    assert_eq!(
+        FileSystem::mount(RamBlockDevice::new(unclean.clone())).err(),
+        Some("Checksum mismatch")
+    );
+    // A clean one is mounted without reading the bitmap, the first allocation
+    // finds it corrupted.
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image.clone())).unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    assert_eq!(fs.write_at(file, 4096, b"more"), Err("Checksum mismatch"));
+    assert_eq!(fs.corrupted_block, Some(bitmap_start));
+    std::mem::forget(fs);
+
+    let before = unclean.clone();
+    let mut fs = FileSystem::mount_with(
+        RamBlockDevice::new(&mut unclean[..]),
+        &MountOptions::default().checksum_policy(ChecksumPolicy::ReadOnly),
+    )
+    .unwrap();
+    assert!(fs.read_only);
+    let file = fs.lookup("/dir/file").unwrap();
+    let mut buf = [0u8; 5];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    assert_eq!(fs.mkfile(0, "new"), Err("Read-only file system"));
+    let report = fs.check().unwrap();
+    assert_eq!(
+        report.problems,
+        [
+            Problem::Corrupted {
+                block: bitmap_start
+            },
+            Problem::LeakedBlock { block: 1000 },
+        ]
+    );
+    fs.unmount().unwrap();
+    assert!(unclean == before);
+}
+
+#[test]
+fn corrupted_super_blocks_fall_back_to_a_backup() {
+    let mut image = populated_image();
+    // A byte of the label, which is valid whatever its value.
+    flip(&mut image, 70);
+
+    let fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert!(fs.backup_used.is_some());
+}
+
+#[test]
+fn corrupted_super_blocks_without_backups_are_refused_or_mounted_read_only() {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: Features::default().without(feature::BACKUP_SUPER),
+        ..format_options()
+    };
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    fs.unmount().unwrap();
+    flip(&mut image, 70);
+
+    assert_eq!(
+        FileSystem::mount(RamBlockDevice::new(&mut image[..])).err(),
+        Some("Invalid super block")
+    );
+
+    let before = image.clone();
+    let mut fs = FileSystem::mount_with(
+        RamBlockDevice::new(&mut image[..]),
+        &MountOptions::default().checksum_policy(ChecksumPolicy::ReadOnly),
+    )
+    .unwrap();
+    assert!(fs.read_only);
+    assert_eq!(fs.backup_used, None);
+    assert_eq!(fs.corrupted_block, Some(0));
+    assert!(fs.lookup("/file").is_ok());
+    assert_eq!(fs.mkfile(0, "new"), Err("Read-only file system"));
+    fs.unmount().unwrap();
+    assert!(image == before);
+
+    // Anything else than the checksum is still refused.
+    image[..4].fill(0);
+    assert_eq!(
+        FileSystem::mount_with(
+            RamBlockDevice::new(image),
+            &MountOptions::default().checksum_policy(ChecksumPolicy::ReadOnly),
+        )
+        .err(),
+        Some("Invalid super block")
+    );
+}
+
+#[test]
+fn checksums_can_be_disabled() {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: Features::default().without(feature::METADATA_CSUM),
+        ..format_options()
+    };
+    let super_block = format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+    assert!(super_block.checksum_table_blocks().is_empty());
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.unmount().unwrap();
+    // Nothing tells the change of the mode apart from a valid value.
+    flip(&mut image, 1024 + file as usize * Inode::SIZE + 180);
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert!(fs.stat(file).is_ok());
+    assert!(fs.check().unwrap().is_consistent());
+}
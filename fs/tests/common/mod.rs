@@ -0,0 +1,175 @@
+//! The helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use proka_fs::definition::SuperBlock;
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{BlockDevice, GenericFsData};
+
+/// The size of the test images.
+pub const IMAGE_SIZE: usize = 1024 * 1024;
+
+/// The size of a sector, the unit a write can't be torn inside.
+pub const SECTOR_SIZE: u64 = 512;
+
+/// Fill a buffer with random bytes.
+///
+/// The file system has no random source of its own without its `std` feature,
+/// but the tests always have the standard library.
+pub fn random(buf: &mut [u8]) {
+    use std::hash::{BuildHasher, Hasher};
+
+    let state = std::collections::hash_map::RandomState::new();
+    for (i, chunk) in buf.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+    }
+}
+
+/// Get the options of the test images, which work with or without `std`.
+pub fn format_options() -> FormatOptions {
+    FormatOptions {
+        random: Some(random),
+        ..FormatOptions::default()
+    }
+}
+
+/// Create a freshly formatted image.
+pub fn fresh_image() -> Vec<u8> {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &format_options(),
+    )
+    .unwrap();
+    image
+}
+
+/// Fill the free blocks of an unmounted image, like the old data a device keeps
+/// in the blocks it never discarded.
+pub fn fill_free_blocks(image: &mut [u8], byte: u8) {
+    let super_block = SuperBlock::from_bytes(image).unwrap();
+    let block_size = super_block.block_size as usize;
+    let bitmap = super_block.bitmap_start_block as usize * block_size;
+    for block in super_block.data_start_block as usize..super_block.bitmap_start_block as usize {
+        if image[bitmap + block] == 0 {
+            image[block * block_size..(block + 1) * block_size].fill(byte);
+        }
+    }
+}
+
+/// What happens to the writes once the fault is triggered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The writes fail.
+    Fail,
+
+    /// The first write is torn (only its first sectors reach the device),
+    /// and the next ones fail.
+    Tear,
+
+    /// The writes report success, but never reach the device (like a power loss
+    /// with a volatile write cache).
+    Drop,
+}
+
+/// A block device which starts misbehaving after some writes.
+///
+/// Each `write_block` and `discard` call counts as one write, the multi-block
+/// writes are split into single blocks, so every block is a possible crash point.
+pub struct FaultyBlockDevice<B: BlockDevice> {
+    /// The wrapped block device.
+    pub inner: B,
+
+    /// The number of writes done before the fault, or `None` to never fail.
+    pub fail_after: Option<u64>,
+
+    /// What happens once the fault is triggered.
+    pub fault: Fault,
+
+    /// The number of writes seen so far, including the faulty ones.
+    pub writes: u64,
+}
+
+impl<B: BlockDevice> FaultyBlockDevice<B> {
+    /// Wrap a block device, which never fails until `fail_after` is set.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            fail_after: None,
+            fault: Fault::Fail,
+            writes: 0,
+        }
+    }
+
+    /// Wrap a block device, which lets `count` writes through before the fault.
+    pub fn failing_after(inner: B, count: u64, fault: Fault) -> Self {
+        Self {
+            fail_after: Some(count),
+            fault,
+            ..Self::new(inner)
+        }
+    }
+
+    /// Count a write, and tell if it's the first faulty one.
+    ///
+    /// # Returns
+    ///
+    /// * `None` - If the write goes through.
+    /// * `Some(bool)` - If the write is faulty, and whether it's the first one.
+    fn next_write(&mut self) -> Option<bool> {
+        self.writes += 1;
+        let count = self.fail_after?;
+        (self.writes > count).then_some(self.writes == count + 1)
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for FaultyBlockDevice<B> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        self.inner.read_block(block_num, offset, buf)
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let Some(first) = self.next_write() else {
+            return self.inner.write_block(block_num, offset, buf);
+        };
+        match self.fault {
+            Fault::Fail => Err("Injected write failure"),
+            Fault::Tear if first => {
+                // Keep the sectors fully covered by the first half of the write.
+                let pos = block_num as u64 * self.block_size() as u64 + offset as u64;
+                let cut = (pos + buf.len() as u64 / 2) / SECTOR_SIZE * SECTOR_SIZE;
+                if cut > pos {
+                    self.inner
+                        .write_block(block_num, offset, &buf[..(cut - pos) as usize])?;
+                }
+                Err("Injected torn write")
+            }
+            Fault::Tear => Err("Injected write failure"),
+            Fault::Drop => Ok(()),
+        }
+    }
+
+    fn discard(&mut self, blocks: std::ops::Range<u32>) -> Result<(), &'static str> {
+        match self.next_write() {
+            None => self.inner.discard(blocks),
+            Some(_) if self.fault == Fault::Drop => Ok(()),
+            Some(_) => Err("Injected write failure"),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.inner.flush()
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        self.inner.block_count()
+    }
+}
@@ -0,0 +1,291 @@
+//! The corruption tests: damaged images must be rejected with errors, never
+//! read as invalid values or make the file system panic.
+//!
+//! The fuzz targets in `fuzz/` go much further, these only keep the basics covered.
+
+mod common;
+
+use common::{IMAGE_SIZE, format_options, fresh_image};
+use proka_fs::check::Problem;
+use proka_fs::definition::feature::{self, Features};
+use proka_fs::definition::{DirEntry, FileType, Inode, SuperBlock};
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::options::MountOptions;
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{FileSystem, GenericFsData};
+use proptest::prelude::*;
+
+/// The position of an inode in the image.
+fn inode_pos(inode_id: u32) -> usize {
+    1024 + inode_id as usize * Inode::SIZE
+}
+
+/// Create an image holding a few files and directories.
+fn populated_image() -> Vec<u8> {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    fs.mkfile(dir, "file").unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    fs.write_at(file, 0, &[0x5A; 14 * 1024]).unwrap();
+    fs.setxattr(file, "user.comment", &[0xA5; 200]).unwrap();
+    fs.mkfifo(0, "fifo").unwrap();
+    fs.unmount().unwrap();
+    image
+}
+
+/// Read everything reachable from the root, ignoring the errors.
+fn walk(fs: &mut FileSystem<RamBlockDevice<Vec<u8>>>) {
+    let mut pending = vec![0u32];
+    let mut buf = vec![0u8; 32 * 1024];
+    // The tree may have cycles.
+    for _ in 0..64 {
+        let Some(inode_id) = pending.pop() else {
+            break;
+        };
+        let _ = fs.stat(inode_id);
+        let _ = fs.listxattr(inode_id);
+        let _ = fs.read_at(inode_id, 0, &mut buf);
+        if let Ok(entries) = fs.ls(inode_id) {
+            pending.extend(
+                entries
+                    .iter()
+                    .filter(|entry| entry.name_bytes() != b"." && entry.name_bytes() != b"..")
+                    .map(|entry| entry.inode),
+            );
+        }
+    }
+}
+
+#[test]
+fn invalid_inode_values_are_rejected() {
+    // `file_type` is at 8, and `is_used` at 0.
+    for (pos, value) in [(8, 7), (8, 0xFF), (0, 2)] {
+        let mut image = populated_image();
+        let dir = {
+            let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+            fs.lookup("/dir").unwrap()
+        };
+        image[inode_pos(dir) + pos] = value;
+
+        let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+        assert!(fs.stat(dir).is_err());
+        let report = fs.check().unwrap();
+        assert!(report.problems.contains(&Problem::BadInode { inode: dir }));
+        assert!(!report.is_consistent());
+    }
+}
+
+#[test]
+fn invalid_super_blocks_are_rejected() {
+    let cases: [(usize, u32); 5] = [
+        // The magic, the block size, the bitmap, the data area and the total.
+        (0, 0),
+        (4, 4096),
+        (8, 0),
+        (12, 1),
+        (16, u32::MAX),
+    ];
+    // The backups must be damaged too, or the file system is mounted from them.
+    let copies: Vec<usize> = std::iter::once(0)
+        .chain(SuperBlock::new(IMAGE_SIZE as u64).backup_blocks())
+        .map(|block| block as usize * 1024)
+        .collect();
+    for (pos, value) in cases {
+        let mut image = fresh_image();
+        for copy in &copies {
+            image[copy + pos..copy + pos + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        assert!(
+            FileSystem::mount(RamBlockDevice::new(image)).is_err(),
+            "field at {pos} set to {value}"
+        );
+    }
+
+    // The image is cut short.
+    let mut image = fresh_image();
+    image.truncate(IMAGE_SIZE / 2);
+    assert!(FileSystem::mount(RamBlockDevice::new(image)).is_err());
+}
+
+#[test]
+fn oversized_directories_are_rejected() {
+    let mut image = populated_image();
+    // Encoded again, so the checksum of the inode still matches.
+    let pos = inode_pos(0);
+    let mut root = Inode::from_bytes(&image[pos..]).unwrap();
+    root.file_length = 64 << 20;
+    root.write_bytes(&mut image[pos..]);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert_eq!(fs.stat(0).unwrap().file_type, FileType::Directory);
+    assert!(fs.ls(0).is_err());
+    assert!(fs.lookup("/dir").is_err());
+    assert!(!fs.check().unwrap().is_consistent());
+}
+
+/// Rewrite an inode of an image, keeping its checksum valid.
+fn patch_inode(image: &mut [u8], inode_id: u32, patch: impl FnOnce(&mut Inode)) {
+    let pos = inode_pos(inode_id);
+    let mut inode = Inode::from_bytes(&image[pos..]).unwrap();
+    patch(&mut inode);
+    inode.write_bytes(&mut image[pos..]);
+}
+
+#[test]
+fn bad_block_pointers_are_rejected() {
+    let image = populated_image();
+    let (file, super_block) = {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(image.clone())).unwrap();
+        (fs.lookup("/dir/file").unwrap(), fs.super_block)
+    };
+    // The inode table, a backup of the super block, the checksum table, the
+    // bitmap, and past the end of the device.
+    let bad_blocks = [
+        1,
+        super_block.backup_blocks()[0],
+        super_block.checksum_table_blocks().start,
+        super_block.bitmap_start_block,
+        u32::MAX,
+    ];
+    for bad_block in bad_blocks {
+        let patches: [fn(&mut Inode, u32); 2] = [
+            |inode, block| inode.direct_blocks[3] = block,
+            |inode, block| inode.indirect_block = block,
+        ];
+        for patch in patches {
+            let mut image = image.clone();
+            patch_inode(&mut image, file, |inode| patch(inode, bad_block));
+            let before = image.clone();
+
+            let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+            let free_blocks = fs.statfs().free_blocks;
+            let mut buf = vec![0; 14 * 1024];
+            assert_eq!(
+                fs.read_at(file, 0, &mut buf),
+                Err("Corrupted block pointer")
+            );
+            assert_eq!(fs.write_at(file, 0, &buf), Err("Corrupted block pointer"));
+            assert_eq!(fs.truncate(file, 0), Err("Corrupted block pointer"));
+            let dir = fs.lookup("/dir").unwrap();
+            assert_eq!(fs.unlink(dir, "file"), Err("Corrupted block pointer"));
+            assert_eq!(fs.statfs().free_blocks, free_blocks);
+            let report = fs.check().unwrap();
+            assert!(!report.is_consistent());
+            assert!(!fs.read_only);
+            drop(fs);
+            // No block was allocated nor freed, nor written over through the bad pointer.
+            let bitmap = super_block.bitmap_start_block as usize * 1024;
+            assert!(image[bitmap..] == before[bitmap..]);
+        }
+    }
+}
+
+#[test]
+fn bad_directory_pointers_are_rejected() {
+    let mut image = populated_image();
+    let dir = {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        fs.lookup("/dir").unwrap()
+    };
+    patch_inode(&mut image, dir, |inode| inode.direct_blocks[0] = 1);
+
+    let options = MountOptions::parse("errors=remount-ro").unwrap();
+    let mut fs = FileSystem::mount_with(RamBlockDevice::new(image), &options).unwrap();
+    let report = fs.check().unwrap();
+    assert!(report.problems.contains(&Problem::BadBlockPointer {
+        inode: dir,
+        block: 1
+    }));
+    fs.scrub().unwrap();
+    assert!(!fs.read_only);
+    assert_eq!(
+        fs.lookup("/dir/file").err(),
+        Some("Corrupted block pointer")
+    );
+    assert!(fs.read_only);
+}
+
+#[test]
+fn invalid_metadata_follows_the_error_behavior() {
+    let options = MountOptions::parse("errors=remount-ro").unwrap();
+    let mut image = populated_image();
+    let (dir, file) = {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        (fs.lookup("/dir").unwrap(), fs.lookup("/dir/file").unwrap())
+    };
+
+    // An inode stored at the wrong place.
+    let mut bad_inode = image.clone();
+    patch_inode(&mut bad_inode, file, |inode| inode.inode_id = dir);
+    // A directory longer than the data area.
+    let mut bad_dir = image.clone();
+    patch_inode(&mut bad_dir, dir, |inode| inode.file_length = 64 << 20);
+    // An attribute in an unknown namespace.
+    let mut bad_xattr = image.clone();
+    patch_inode(&mut bad_xattr, file, |inode| inode.inline_xattr[0] = 9);
+
+    for (image, error) in [
+        (bad_inode, "Invalid inode"),
+        (bad_dir, "Corrupted directory"),
+        (bad_xattr, "Invalid attribute namespace"),
+    ] {
+        let mut fs = FileSystem::mount_with(RamBlockDevice::new(image), &options).unwrap();
+        assert!(!fs.read_only);
+        walk(&mut fs);
+        assert!(fs.read_only, "{error}");
+    }
+}
+
+#[test]
+fn invalid_dir_entries_follow_the_error_behavior() {
+    // Without checksums, nothing catches the entry before it's decoded.
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: Features::default().without(feature::METADATA_CSUM),
+        ..format_options()
+    };
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    fs.unmount().unwrap();
+    // The last byte of the name of the third entry of the root, "file".
+    let block = Inode::from_bytes(&image[inode_pos(0)..])
+        .unwrap()
+        .direct_blocks[0] as usize;
+    image[block * 1024 + 2 * DirEntry::SIZE + DirEntry::SIZE - 1] = b'x';
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image.clone())).unwrap();
+    assert_eq!(fs.ls(0).err(), Some("Invalid dir entry"));
+    assert!(!fs.read_only);
+
+    let options = MountOptions::parse("errors=remount-ro").unwrap();
+    let mut fs = FileSystem::mount_with(RamBlockDevice::new(image), &options).unwrap();
+    assert_eq!(fs.ls(0).err(), Some("Invalid dir entry"));
+    assert!(fs.read_only);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn corrupted_images_never_panic(
+        patches in prop::collection::vec((0..IMAGE_SIZE / 16, any::<u8>()), 1..32)
+    ) {
+        // The metadata and the first data blocks are at the start of the image.
+        let mut image = populated_image();
+        for (pos, value) in patches {
+            image[pos] = value;
+        }
+        if let Ok(mut fs) = FileSystem::mount(RamBlockDevice::new(image)) {
+            let _ = fs.check();
+            walk(&mut fs);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! The crash-consistency tests: a workload is interrupted at every write, and
+//! what reached the device must still be a consistent file system.
+
+mod common;
+
+use common::{Fault, FaultyBlockDevice, fill_free_blocks, fresh_image};
+use proka_fs::cache::CachedBlockDevice;
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{BlockDevice, FileSystem};
+
+/// Some bytes which differ from one block to the next.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Create, write and delete some files and directories.
+fn workload<B: BlockDevice>(fs: &mut FileSystem<B>) -> Result<(), &'static str> {
+    fs.mkdir(0, "dir")?;
+    let dir = fs.lookup("/dir")?;
+    fs.mkfile(dir, "big")?;
+    let big = fs.lookup("/dir/big")?;
+    // Large enough to need the indirect block.
+    fs.write_at(big, 0, &pattern(20 * 1024))?;
+
+    fs.mkfile(0, "small")?;
+    let small = fs.lookup("/small")?;
+    fs.write_at(small, 0, b"hello")?;
+    fs.setxattr(small, "user.comment", &pattern(300))?;
+    fs.mkdir(dir, "sub")?;
+    fs.mkfifo(dir, "fifo")?;
+
+    // Unlinked while open, then released on close.
+    fs.open_inode(big)?;
+    fs.unlink(dir, "big")?;
+    fs.write_at(big, 0, b"still open")?;
+    fs.close_inode(big)?;
+    fs.write_at(small, 3000, &pattern(3000))?;
+    fs.rmdir(dir, "sub")?;
+    // Reuses the freed slot, inode and blocks.
+    fs.mkfile(dir, "again")?;
+    let again = fs.lookup("/dir/again")?;
+    fs.write_at(again, 100, &pattern(5000))?;
+    fs.removexattr(small, "user.comment")?;
+    // Left in the orphan list until the unmount.
+    fs.open_inode(small)?;
+    fs.unlink(0, "small")?;
+    fs.sync()
+}
+
+/// Count the writes done by the workload and the unmount, after the mount.
+fn count_writes() -> u64 {
+    let mut image = fresh_image();
+    let mut bd = FaultyBlockDevice::new(RamBlockDevice::new(&mut image[..]));
+    let mut fs = FileSystem::mount(&mut bd).unwrap();
+    let mounted = fs.block_device.writes;
+    workload(&mut fs).unwrap();
+    fs.unmount().unwrap();
+    bd.writes - mounted
+}
+
+/// Interrupt the workload at every write, and check what reached the device.
+fn crash_at_every_write(fault: Fault) {
+    let total = count_writes();
+    assert!(total > 0);
+
+    for count in 0..=total {
+        let mut image = fresh_image();
+        {
+            let bd = FaultyBlockDevice::new(RamBlockDevice::new(&mut image[..]));
+            let mut fs = FileSystem::mount(bd).unwrap();
+            fs.block_device.fail_after = Some(fs.block_device.writes + count);
+            fs.block_device.fault = fault;
+            let result = workload(&mut fs).and_then(|()| fs.unmount());
+            if fault != Fault::Drop {
+                assert_eq!(result.is_ok(), count == total, "crash after {count} writes");
+            }
+        }
+
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        let report = fs.check().unwrap();
+        assert!(
+            report.is_consistent(),
+            "{fault:?} after {count} of {total} writes: {:?}",
+            report.problems
+        );
+
+        // The file system is still usable.
+        fs.mkfile(0, "after").unwrap();
+        let after = fs.lookup("/after").unwrap();
+        fs.write_at(after, 0, &pattern(2000)).unwrap();
+        let report = fs.check().unwrap();
+        assert!(
+            report.is_consistent(),
+            "{fault:?} after {count} of {total} writes, then used: {:?}",
+            report.problems
+        );
+    }
+}
+
+#[test]
+fn workload_leaves_a_clean_file_system() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.check().unwrap().is_clean());
+    workload(&mut fs).unwrap();
+
+    let report = fs.check().unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+    let mut buf = vec![0u8; 5100];
+    let again = fs.lookup("/dir/again").unwrap();
+    assert_eq!(fs.read_at(again, 0, &mut buf).unwrap(), 5100);
+    assert_eq!(&buf[..100], &[0; 100]);
+    assert_eq!(&buf[100..], &pattern(5000)[..]);
+    assert!(fs.lookup("/small").is_err());
+    assert!(fs.lookup("/dir/sub").is_err());
+}
+
+#[test]
+fn crash_with_failing_writes() {
+    crash_at_every_write(Fault::Fail);
+}
+
+#[test]
+fn crash_with_torn_writes() {
+    crash_at_every_write(Fault::Tear);
+}
+
+#[test]
+fn crash_with_dropped_writes() {
+    crash_at_every_write(Fault::Drop);
+}
+
+#[test]
+fn double_indirect_tables_are_zeroed_before_being_listed() {
+    let mut base = fresh_image();
+    // In the first blocks mapped by the first two tables of the double indirect block.
+    let first = (12 + 256) * 1024;
+    let second = first + 256 * 1024;
+    {
+        // The double indirect block is on the disk before the crash.
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut base[..])).unwrap();
+        fs.mkfile(0, "sparse").unwrap();
+        let file = fs.lookup("/sparse").unwrap();
+        fs.write_at(file, first, b"near").unwrap();
+        fs.unmount().unwrap();
+    }
+    // The old data of the free blocks mustn't show up as pointers.
+    fill_free_blocks(&mut base, 0xAB);
+
+    for count in 0.. {
+        let mut image = base.clone();
+        let done = {
+            let bd = FaultyBlockDevice::new(RamBlockDevice::new(&mut image[..]));
+            let mut fs = FileSystem::mount(bd).unwrap();
+            fs.block_device.fail_after = Some(fs.block_device.writes + count);
+            let file = fs.lookup("/sparse").unwrap();
+            fs.write_at(file, second, b"far").is_ok()
+        };
+
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        let report = fs.check().unwrap();
+        assert!(
+            report.is_consistent(),
+            "after {count} writes: {:?}",
+            report.problems
+        );
+        if done {
+            let mut buf = [0u8; 3];
+            let file = fs.lookup("/sparse").unwrap();
+            fs.read_at(file, second, &mut buf).unwrap();
+            assert_eq!(&buf, b"far");
+            break;
+        }
+    }
+}
+
+#[test]
+fn cached_discards_follow_the_writes_freeing_the_blocks() {
+    let mut base = fresh_image();
+    {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut base[..])).unwrap();
+        fs.mkdir(0, "dir").unwrap();
+        fs.unmount().unwrap();
+    }
+
+    for count in 0.. {
+        let mut image = base.clone();
+        let done = {
+            let bd = FaultyBlockDevice::new(RamBlockDevice::new(&mut image[..]));
+            let mut fs = FileSystem::mount(CachedBlockDevice::new(bd, 64)).unwrap();
+            fs.sync().unwrap();
+            let writes = fs.block_device.get_ref().writes;
+            fs.block_device.get_mut().fail_after = Some(writes + count);
+            // The block of the directory is freed and discarded.
+            fs.rmdir(0, "dir").and_then(|()| fs.sync()).is_ok()
+        };
+
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        let report = fs.check().unwrap();
+        assert!(
+            report.is_consistent(),
+            "after {count} writes: {:?}",
+            report.problems
+        );
+        if done {
+            assert!(fs.lookup("/dir").is_err());
+            break;
+        }
+    }
+}
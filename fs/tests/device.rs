@@ -0,0 +1,156 @@
+//! The tests of the flushes, the discards and the geometry of the block devices.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{BlockDevice, FileSystem, MountOptions};
+
+/// A block device with nothing but the required methods.
+struct MinimalBlockDevice(Vec<u8>);
+
+impl BlockDevice for MinimalBlockDevice {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        let start = block_num as usize * 1024 + offset as usize;
+        buf.copy_from_slice(&self.0[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        let start = block_num as usize * 1024 + offset as usize;
+        self.0[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+/// A block device counting the flushes and recording the discards.
+struct CountingBlockDevice {
+    inner: RamBlockDevice<Vec<u8>>,
+    flushes: usize,
+    discarded: Vec<u32>,
+}
+
+impl CountingBlockDevice {
+    fn new(image: Vec<u8>) -> Self {
+        Self {
+            inner: RamBlockDevice::new(image),
+            flushes: 0,
+            discarded: Vec::new(),
+        }
+    }
+}
+
+impl BlockDevice for CountingBlockDevice {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        self.inner.read_block(block_num, offset, buf)
+    }
+
+    fn write_block(&mut self, block_num: u32, offset: u32, buf: &[u8]) -> Result<(), &'static str> {
+        self.inner.write_block(block_num, offset, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), &'static str> {
+        self.flushes += 1;
+        Ok(())
+    }
+
+    fn discard(&mut self, blocks: core::ops::Range<u32>) -> Result<(), &'static str> {
+        self.discarded.extend(blocks.clone());
+        self.inner.discard(blocks)
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        self.inner.block_count()
+    }
+}
+
+#[test]
+fn default_methods() {
+    let mut bd = MinimalBlockDevice(vec![0; 4096]);
+    bd.write_block(1, 0, &[7; 1024]).unwrap();
+    assert_eq!(bd.flush(), Ok(()));
+    // The discard is only a hint, the default keeps the content.
+    assert_eq!(bd.discard(0..4), Ok(()));
+    let mut buf = [0; 1024];
+    bd.read_block(1, 0, &mut buf).unwrap();
+    assert_eq!(buf, [7; 1024]);
+    assert_eq!(bd.block_count(), None);
+    assert_eq!(bd.block_size(), 1024);
+}
+
+#[test]
+fn freed_blocks_are_discarded() {
+    let mut fs = FileSystem::mount(CountingBlockDevice::new(fresh_image())).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, &[7; 3000]).unwrap();
+    assert!(fs.block_device.discarded.is_empty());
+
+    let blocks = fs.stat(file).unwrap().blocks;
+    fs.unlink(0, "file").unwrap();
+    let mut discarded = fs.block_device.discarded.clone();
+    discarded.sort_unstable();
+    discarded.dedup();
+    assert_eq!(discarded.len() as u32, blocks);
+    let data_area = fs.super_block.data_start_block..fs.super_block.bitmap_start_block;
+    assert!(discarded.iter().all(|block| data_area.contains(block)));
+}
+
+#[test]
+fn sync_flushes_the_device() {
+    let mut fs = FileSystem::mount(CountingBlockDevice::new(fresh_image())).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    assert_eq!(fs.block_device.flushes, 0);
+    fs.sync().unwrap();
+    assert_eq!(fs.block_device.flushes, 1);
+}
+
+#[test]
+fn sync_mount_flushes_every_change() {
+    let options = MountOptions::parse("sync").unwrap();
+    let mut fs = FileSystem::mount_with(CountingBlockDevice::new(fresh_image()), &options).unwrap();
+    let flushes = fs.block_device.flushes;
+    fs.mkfile(0, "file").unwrap();
+    assert!(fs.block_device.flushes > flushes);
+
+    let flushes = fs.block_device.flushes;
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, b"hello").unwrap();
+    assert!(fs.block_device.flushes > flushes);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_device_flushes_and_discards() {
+    let path = std::env::temp_dir().join(format!("proka-fs-device-{}.img", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut bd = proka_fs::init_block_device(path.to_str().unwrap()).unwrap();
+    assert_eq!(bd.block_count(), Some(0));
+
+    bd.write_blocks(0, &[7; 4096]).unwrap();
+    bd.flush().unwrap();
+    assert_eq!(bd.block_count(), Some(4));
+
+    // A discarded block reads as zeroes, and the file keeps its size.
+    bd.discard(1..3).unwrap();
+    let mut buf = [0; 4096];
+    bd.read_blocks(0, &mut buf).unwrap();
+    assert_eq!(buf[..1024], [7; 1024]);
+    assert_eq!(buf[1024..3072], [0; 2048]);
+    assert_eq!(buf[3072..], [7; 1024]);
+    assert_eq!(bd.block_count(), Some(4));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 4096);
+
+    drop(bd);
+    std::fs::remove_file(&path).unwrap();
+}
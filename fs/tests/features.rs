@@ -0,0 +1,166 @@
+//! The tests of the revision and the feature masks of the super block.
+
+mod common;
+
+use common::{IMAGE_SIZE, format_options, fresh_image};
+use proka_fs::definition::feature::{self, Features};
+use proka_fs::definition::{REVISION, SuperBlock, UNWRITTEN_FLAG};
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{FileSystem, GenericFsData};
+
+/// Change the super block of an image.
+fn patch_super_block(image: &mut [u8], patch: impl FnOnce(&mut SuperBlock)) {
+    let mut super_block = SuperBlock::from_bytes(image).unwrap();
+    patch(&mut super_block);
+    super_block.write_bytes(image);
+}
+
+#[test]
+fn new_file_systems_use_the_default_features() {
+    let image = fresh_image();
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    assert_eq!(super_block.revision, REVISION);
+    assert_eq!(super_block.features(), Features::default());
+    assert!(super_block.features().contains(feature::XATTR));
+}
+
+#[test]
+fn unknown_incompatible_features_are_refused() {
+    let mut image = fresh_image();
+    patch_super_block(&mut image, |super_block| {
+        super_block.feature_incompat |= 1 << 31
+    });
+    assert_eq!(
+        FileSystem::mount(RamBlockDevice::new(image)).err(),
+        Some("Unsupported incompatible features")
+    );
+}
+
+#[test]
+fn unwritten_blocks_need_block_numbers_without_the_flag() {
+    let mut super_block = SuperBlock::from_bytes(&fresh_image()).unwrap();
+    super_block.set_features(Features::NONE);
+    super_block.total_block = UNWRITTEN_FLAG + 1;
+    super_block.bitmap_start_block =
+        super_block.total_block - super_block.total_block.div_ceil(1024);
+    assert_eq!(super_block.validate(), Ok(()));
+
+    super_block.set_features(Features::NONE.with(feature::UNWRITTEN));
+    assert_eq!(super_block.validate(), Err("Invalid super block layout"));
+
+    let options = FormatOptions {
+        features: Features::default().with(feature::UNWRITTEN),
+        ..format_options()
+    };
+    let result = format(
+        &mut RamBlockDevice::new(vec![0u8; IMAGE_SIZE]),
+        (UNWRITTEN_FLAG as u64 + 1) * 1024,
+        &options,
+    );
+    assert_eq!(
+        result.err(),
+        Some("The unwritten feature needs less than 2^31 blocks")
+    );
+}
+
+#[test]
+fn unknown_read_only_compatible_features_mount_read_only() {
+    let mut image = fresh_image();
+    patch_super_block(&mut image, |super_block| {
+        super_block.feature_ro_compat |= 1 << 31
+    });
+    let before = image.clone();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.read_only);
+    assert_eq!(fs.ls(0).unwrap().len(), 2);
+    assert_eq!(fs.mkdir(0, "dir"), Err("Read-only file system"));
+    assert_eq!(fs.mkfile(0, "file"), Err("Read-only file system"));
+    assert_eq!(fs.write_at(0, 0, b"data"), Err("Read-only file system"));
+    assert_eq!(fs.unlink(0, "."), Err("Read-only file system"));
+    assert_eq!(fs.setxattr(0, "user.a", b"b"), Err("Read-only file system"));
+    fs.unmount().unwrap();
+    assert!(image == before);
+}
+
+#[test]
+fn unknown_compatible_features_are_ignored() {
+    let mut image = fresh_image();
+    patch_super_block(&mut image, |super_block| {
+        super_block.feature_compat |= 1 << 31
+    });
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(!fs.read_only);
+    fs.mkdir(0, "dir").unwrap();
+    fs.unmount().unwrap();
+    // The unknown feature is kept.
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    assert_eq!(super_block.feature_compat & 1 << 31, 1 << 31);
+}
+
+#[test]
+fn revision_0_images_have_the_legacy_features() {
+    let mut image = fresh_image();
+    // The original format had nothing after the reserved blocks.
+    image[32..48].fill(0);
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    assert_eq!(super_block.revision, 0);
+    assert_eq!(super_block.features(), Features::LEGACY);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert!(!fs.read_only);
+    fs.setxattr(0, "user.a", b"b").unwrap();
+    assert_eq!(fs.getxattr(0, "user.a").unwrap(), b"b");
+}
+
+#[test]
+fn disabled_xattrs_are_refused() {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: Features::default().parse("^xattr").unwrap(),
+        ..format_options()
+    };
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    assert!(!fs.super_block.features().contains(feature::XATTR));
+    assert_eq!(
+        fs.setxattr(0, "user.a", b"b"),
+        Err("Extended attributes are disabled")
+    );
+}
+
+#[test]
+fn unknown_features_are_not_formatted() {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: Features {
+            incompat: 1 << 31,
+            ..Features::default()
+        },
+        ..format_options()
+    };
+    let result = format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    );
+    assert_eq!(result.err(), Some("Unsupported features"));
+}
+
+#[test]
+fn feature_lists_are_parsed() {
+    let features = Features::NONE.parse("xattr").unwrap();
+    assert!(features.contains(feature::XATTR));
+    assert_eq!(features.parse(" ^xattr ,").unwrap(), Features::NONE);
+    assert_eq!(Features::NONE.parse("").unwrap(), Features::NONE);
+    assert_eq!(Features::NONE.parse("nope"), Err("Unknown feature"));
+    assert_eq!(Features::SUPPORTED.unknown(), Features::NONE);
+}
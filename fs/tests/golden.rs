@@ -0,0 +1,192 @@
+//! The golden tests of the on-disk format: the images must be byte for byte
+//! the same on every architecture, whatever its endianness or alignment.
+//!
+//! If one of these fails, the on-disk format changed.
+
+use proka_fs::crc32c::{crc32c, crc32c_append};
+use proka_fs::definition::{DirEntry, FileType, Inode, SuperBlock};
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{FileSystem, GenericFsData};
+
+/// The size of the golden image.
+const IMAGE_SIZE: usize = 64 * 1024;
+
+/// Hash some bytes with 64-bit FNV-1a.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Create an image using every on-disk structure, without anything depending on
+/// the current time.
+fn golden_image() -> Vec<u8> {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        time: 0x0102_0304_0506_0708,
+        label: "golden".to_string(),
+        random: Some(|buf| buf.fill(0x5A)),
+        creator: "golden 1.0",
+        ..FormatOptions::default()
+    };
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.clock = || 0x1112_1314_1516_1718;
+    // The mount time was read from the system clock.
+    fs.super_block.last_mounted = 0x2122_2324_2526_2728;
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    fs.mkfile(dir, "file").unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    // Large enough to need the indirect block.
+    let data: Vec<u8> = (0..14 * 1024).map(|i| (i % 251) as u8).collect();
+    fs.write_at(file, 0, &data).unwrap();
+    fs.setxattr(file, "user.inline", b"value").unwrap();
+    fs.setxattr(file, "user.block", &[0xA5; 200]).unwrap();
+    fs.mkfifo(0, "fifo").unwrap();
+    fs.mksock(0, "sock").unwrap();
+    fs.unlink(0, "fifo").unwrap();
+    fs.unmount().unwrap();
+    image
+}
+
+#[test]
+fn super_block_is_little_endian() {
+    let image = golden_image();
+    #[rustfmt::skip]
+    let expected: [u8; 164] = [
+        0x53, 0x46, 0x4B, 0x50, // magic
+        0x00, 0x04, 0x00, 0x00, // block_size
+        0x3F, 0x00, 0x00, 0x00, // bitmap_start_block
+        0x05, 0x00, 0x00, 0x00, // data_start_block
+        0x40, 0x00, 0x00, 0x00, // total_block
+        0x24, 0x00, 0x00, 0x00, // free_blocks
+        0x0C, 0x00, 0x00, 0x00, // free_inodes
+        0x02, 0x00, 0x00, 0x00, // reserved_blocks
+        0x01, 0x00, 0x00, 0x00, // revision
+        0x03, 0x00, 0x00, 0x00, // feature_compat
+        0x01, 0x00, 0x00, 0x00, // feature_ro_compat
+        0x00, 0x00, 0x00, 0x00, // feature_incompat
+        0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x4A, 0x5A, // uuid (version 4)
+        0x9A, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, // uuid (variant 1)
+        b'g', b'o', b'l', b'd', b'e', b'n', 0, 0, // label
+        0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+        0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // created
+        0x28, 0x27, 0x26, 0x25, 0x24, 0x23, 0x22, 0x21, // last_mounted
+        0x01, 0x00, 0x00, 0x00, // mount_count
+        b'g', b'o', b'l', b'd', b'e', b'n', b' ', b'1', // creator
+        b'.', b'0', 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+        0x06, 0x00, 0x00, 0x00, // checksum_table
+        0x01, 0x00, 0x00, 0x00, // state
+        0x00, 0x00, 0x00, 0x00, // orphan_head
+        0xF2, 0xF4, 0x54, 0x4B, // checksum
+    ];
+    assert_eq!(image[..164], expected);
+    // The rest of the block is reserved for later fields.
+    assert!(image[164..1024].iter().all(|&byte| byte == 0));
+    // The backups are at the middle and at the end of the data area.
+    for block in [34, 62] {
+        assert_eq!(
+            image[block * 1024..][..1024],
+            image[..1024],
+            "block {block}"
+        );
+    }
+}
+
+#[test]
+fn image_matches_the_golden_hash() {
+    let image = golden_image();
+    assert_eq!(
+        fnv1a(&image),
+        0xFA0D_9BC3_3697_9FF4,
+        "{:#018x}",
+        fnv1a(&image)
+    );
+}
+
+#[test]
+fn inode_layout() {
+    let mut inode = Inode::new(0x0102_0304, FileType::Fifo);
+    inode.direct_blocks = core::array::from_fn(|i| 0x10 + i as u32);
+    inode.indirect_block = 0x2122_2324;
+    inode.double_indirect_block = 0x3132_3334;
+    inode.file_length = 0x0304_0506;
+    inode.xattr_block = 0x5152_5354;
+    inode.inline_xattr = [0x66; 96];
+    inode.mode = 0x7172;
+    inode.links_count = 0x7374;
+    inode.device = 0x7576_7778;
+    inode.block_count = 0x797A_7B7C;
+    inode.atime = 0x8182_8384_8586_8788;
+    inode.mtime = 0x9192_9394_9596_9798;
+    inode.ctime = 0xA1A2_A3A4_A5A6_A7A8;
+    inode.next_orphan = 0xB1B2_B3B4;
+    inode._reserved = [0xEE; 32];
+
+    let mut expected = vec![0u8; 256];
+    expected[0] = 1;
+    expected[4..8].copy_from_slice(&[0x04, 0x03, 0x02, 0x01]);
+    expected[8] = 3;
+    for i in 0..12 {
+        expected[12 + i * 4] = 0x10 + i as u8;
+    }
+    expected[60..64].copy_from_slice(&[0x24, 0x23, 0x22, 0x21]);
+    expected[64..68].copy_from_slice(&[0x34, 0x33, 0x32, 0x31]);
+    expected[72..80].copy_from_slice(&[0x06, 0x05, 0x04, 0x03, 0x00, 0x00, 0x00, 0x00]);
+    expected[80..84].copy_from_slice(&[0x54, 0x53, 0x52, 0x51]);
+    expected[84..180].fill(0x66);
+    expected[180..184].copy_from_slice(&[0x72, 0x71, 0x74, 0x73]);
+    expected[184..188].copy_from_slice(&[0x78, 0x77, 0x76, 0x75]);
+    expected[188..192].copy_from_slice(&[0x7C, 0x7B, 0x7A, 0x79]);
+    expected[192..200].copy_from_slice(&[0x88, 0x87, 0x86, 0x85, 0x84, 0x83, 0x82, 0x81]);
+    expected[200..208].copy_from_slice(&[0x98, 0x97, 0x96, 0x95, 0x94, 0x93, 0x92, 0x91]);
+    expected[208..216].copy_from_slice(&[0xA8, 0xA7, 0xA6, 0xA5, 0xA4, 0xA3, 0xA2, 0xA1]);
+    expected[220..224].copy_from_slice(&[0xB4, 0xB3, 0xB2, 0xB1]);
+    expected[224..256].fill(0xEE);
+    // The checksum covers everything but itself.
+    let checksum = crc32c_append(crc32c(&expected[..216]), &expected[220..]);
+    expected[216..220].copy_from_slice(&checksum.to_le_bytes());
+
+    assert_eq!(inode.to_bytes(), expected);
+    assert_eq!(Inode::from_bytes(&expected), Some(inode));
+    assert!(Inode::checksum_matches(&expected));
+    // The gaps between the fields are ignored, but not by the checksum.
+    expected[1..4].fill(0xFF);
+    expected[68..72].fill(0xFF);
+    assert_eq!(Inode::from_bytes(&expected), Some(inode));
+    assert!(!Inode::checksum_matches(&expected));
+}
+
+#[test]
+fn dir_entry_layout() {
+    let mut entry = DirEntry::empty();
+    entry.inode = 0x0A0B_0C0D;
+    entry.name[..5].copy_from_slice(b"hello");
+
+    let bytes = entry.to_bytes();
+    assert_eq!(bytes.len(), 256);
+    assert_eq!(bytes[..9], *b"\x0D\x0C\x0B\x0Ahello");
+    assert!(bytes[9..].iter().all(|&byte| byte == 0));
+    assert_eq!(DirEntry::from_bytes(&bytes), Some(entry));
+}
+
+#[test]
+fn super_block_round_trips() {
+    let super_block = SuperBlock::new(IMAGE_SIZE as u64);
+    let bytes = super_block.to_bytes();
+    assert_eq!(bytes.len(), SuperBlock::SIZE);
+    assert_eq!(SuperBlock::from_bytes(&bytes), Some(super_block));
+    assert_eq!(SuperBlock::from_bytes(&bytes[..147]), None);
+}
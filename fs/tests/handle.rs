@@ -0,0 +1,324 @@
+//! The tests of the open file handles.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::FileSystem;
+use proka_fs::definition::MAX_FILE_LENGTH;
+use proka_fs::error::{self, ErrorKind};
+use proka_fs::handle::{OpenOptions, SeekFrom};
+use proka_fs::ram::RamBlockDevice;
+
+/// Mount a fresh image.
+fn fresh_fs() -> FileSystem<RamBlockDevice<Vec<u8>>> {
+    FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap()
+}
+
+/// The options to create a file, or empty it.
+fn create() -> OpenOptions {
+    OpenOptions::new().write(true).create(true).truncate(true)
+}
+
+/// The options to read a file.
+fn read() -> OpenOptions {
+    OpenOptions::new().read(true)
+}
+
+#[test]
+fn open_options_follow_std() {
+    let mut fs = fresh_fs();
+    fs.mkdir(0, "dir").unwrap();
+    for options in [
+        OpenOptions::new(),
+        OpenOptions::new().read(true).create(true),
+        OpenOptions::new().read(true).truncate(true),
+        OpenOptions::new().append(true).truncate(true),
+    ] {
+        assert_eq!(
+            fs.open("/file", &options).err(),
+            Some("Invalid open options"),
+            "{options:?}"
+        );
+    }
+
+    assert_eq!(
+        fs.open("/file", &read()).err(),
+        Some("No such file or directory")
+    );
+    assert_eq!(
+        fs.open("/missing/file", &create()).err(),
+        Some("No such file or directory")
+    );
+    assert_eq!(fs.open("/dir", &read()).err(), Some("Is a directory"));
+
+    let create_new = OpenOptions::new().write(true).create_new(true);
+    let file = fs.open("/dir/file", &create_new).unwrap();
+    file.close(&mut fs).unwrap();
+    assert!(fs.lookup("/dir/file").is_ok());
+    assert_eq!(fs.open("/dir/file", &create_new).err(), Some("File exists"));
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn handles_read_and_write_at_their_position() {
+    let mut fs = fresh_fs();
+    let mut file = fs.open("/file", &create().read(true)).unwrap();
+    assert_eq!(file.write(&mut fs, b"hello world").unwrap(), 11);
+    assert_eq!(file.position(), 11);
+
+    assert_eq!(file.seek(&mut fs, SeekFrom::Current(-5)).unwrap(), 6);
+    let mut buf = [0u8; 16];
+    assert_eq!(file.read(&mut fs, &mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"world");
+    assert_eq!(file.read(&mut fs, &mut buf).unwrap(), 0);
+
+    // Past the end, the gap reads as zeroes.
+    assert_eq!(file.seek(&mut fs, SeekFrom::End(3)).unwrap(), 14);
+    file.write(&mut fs, b"!").unwrap();
+    file.seek(&mut fs, SeekFrom::Start(9)).unwrap();
+    assert_eq!(file.read(&mut fs, &mut buf).unwrap(), 6);
+    assert_eq!(&buf[..6], b"ld\0\0\0!");
+
+    assert_eq!(
+        file.seek(&mut fs, SeekFrom::Current(-16)),
+        Err("Invalid seek")
+    );
+    assert_eq!(file.position(), 15);
+    assert_eq!(file.metadata(&mut fs).unwrap().size, 15);
+    file.close(&mut fs).unwrap();
+
+    // The read-only and write-only handles.
+    let mut file = fs.open("/file", &read()).unwrap();
+    assert_eq!(file.write(&mut fs, b"x"), Err("File not open for writing"));
+    file.close(&mut fs).unwrap();
+    let mut file = fs.open("/file", &OpenOptions::new().write(true)).unwrap();
+    assert_eq!(
+        file.read(&mut fs, &mut buf),
+        Err("File not open for reading")
+    );
+    file.close(&mut fs).unwrap();
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn append_and_truncate_on_open() {
+    let mut fs = fresh_fs();
+    let free_blocks = fs.super_block.free_blocks;
+    let mut file = fs.open("/log", &create()).unwrap();
+    file.write(&mut fs, &[0x5A; 20 * 1024]).unwrap();
+    file.close(&mut fs).unwrap();
+
+    let mut file = fs.open("/log", &OpenOptions::new().append(true)).unwrap();
+    // Whatever the position is.
+    file.seek(&mut fs, SeekFrom::Start(0)).unwrap();
+    file.write(&mut fs, b"end").unwrap();
+    assert_eq!(file.position(), 20 * 1024 + 3);
+    file.close(&mut fs).unwrap();
+    assert_eq!(fs.stat_path("/log").unwrap().size, 20 * 1024 + 3);
+
+    // The blocks are freed, the indirect one too.
+    let file = fs.open("/log", &create()).unwrap();
+    let inode_id = file.inode_id();
+    file.close(&mut fs).unwrap();
+    let metadata = fs.stat(inode_id).unwrap();
+    assert_eq!((metadata.size, metadata.blocks), (0, 0));
+    assert_eq!(fs.super_block.free_blocks, free_blocks);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn read_only_file_systems_refuse_writable_handles() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let mut file = fs.open("/file", &create()).unwrap();
+    file.write(&mut fs, b"data").unwrap();
+    file.close(&mut fs).unwrap();
+    fs.unmount().unwrap();
+
+    let mut fs = FileSystem::mount_read_only(RamBlockDevice::new(&mut image[..])).unwrap();
+    for options in [create(), OpenOptions::new().append(true)] {
+        assert_eq!(
+            fs.open("/file", &options).err(),
+            Some("Read-only file system")
+        );
+    }
+    let mut buf = [0u8; 4];
+    let mut file = fs.open("/file", &read()).unwrap();
+    file.read(&mut fs, &mut buf).unwrap();
+    assert_eq!(&buf, b"data");
+    file.close(&mut fs).unwrap();
+}
+
+#[test]
+fn handles_are_released_when_closed_or_unmounted() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let free_blocks = fs.super_block.free_blocks;
+    let file = fs.open("/file", &create()).unwrap();
+    let inode_id = file.inode_id();
+    file.close(&mut fs).unwrap();
+    assert!(!fs.is_open(inode_id));
+    assert_eq!(fs.close_inode(inode_id), Err("Inode not open"));
+
+    // A handle dropped without closing keeps an unlinked file until the unmount.
+    let mut file = fs.open("/file", &read().write(true)).unwrap();
+    file.write(&mut fs, &[7; 3000]).unwrap();
+    drop(file);
+    assert!(fs.is_open(inode_id));
+    fs.unlink(0, "file").unwrap();
+    assert!(fs.stat(inode_id).is_ok());
+    fs.unmount().unwrap();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.stat(inode_id).is_err());
+    assert_eq!(fs.super_block.free_blocks, free_blocks);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn seeks_stay_within_the_max_length() {
+    let mut fs = fresh_fs();
+    let mut file = fs.open("/file", &create()).unwrap();
+    file.write(&mut fs, b"data").unwrap();
+    assert_eq!(
+        file.seek(&mut fs, SeekFrom::Start(MAX_FILE_LENGTH))
+            .unwrap(),
+        MAX_FILE_LENGTH
+    );
+    for pos in [
+        SeekFrom::Start(MAX_FILE_LENGTH + 1),
+        SeekFrom::Start(u64::MAX),
+        SeekFrom::Current(1),
+        SeekFrom::Current(i64::MAX),
+        SeekFrom::End(i64::MIN),
+        SeekFrom::End(i64::MAX),
+    ] {
+        assert_eq!(file.seek(&mut fs, pos), Err(error::INVALID_SEEK), "{pos:?}");
+        assert_eq!(file.position(), MAX_FILE_LENGTH);
+    }
+    assert_eq!(
+        file.seek(&mut fs, SeekFrom::Current(-(MAX_FILE_LENGTH as i64))),
+        Ok(0)
+    );
+    assert_eq!(
+        file.seek(&mut fs, SeekFrom::End(MAX_FILE_LENGTH as i64 - 4)),
+        Ok(MAX_FILE_LENGTH)
+    );
+    file.close(&mut fs).unwrap();
+}
+
+#[test]
+fn several_handles_stay_open_at_once() {
+    let mut fs = fresh_fs();
+    let mut first = fs.open("/first", &create().read(true)).unwrap();
+    let mut second = fs.open("/second", &create().read(true)).unwrap();
+    first.write(&mut fs, b"/first").unwrap();
+    second.write(&mut fs, b"/second").unwrap();
+    // The file system stays usable between the operations of the handles.
+    fs.unlink(0, "first").unwrap();
+    second.write(&mut fs, b"!").unwrap();
+
+    let mut buf = [0u8; 8];
+    first.seek(&mut fs, SeekFrom::Start(0)).unwrap();
+    assert_eq!(first.read(&mut fs, &mut buf).unwrap(), 6);
+    assert_eq!(&buf[..6], b"/first");
+    second.seek(&mut fs, SeekFrom::Start(0)).unwrap();
+    assert_eq!(second.read(&mut fs, &mut buf).unwrap(), 8);
+    assert_eq!(&buf, b"/second!");
+
+    let first_id = first.inode_id();
+    first.close(&mut fs).unwrap();
+    second.close(&mut fs).unwrap();
+    assert!(fs.stat(first_id).is_err());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn errors_have_a_kind() {
+    let mut fs = fresh_fs();
+    fs.mkdir(0, "dir").unwrap();
+    let mut file = fs.open("/file", &create()).unwrap();
+    let cases = [
+        (fs.open("/missing", &read()).err(), ErrorKind::NotFound),
+        (
+            fs.open("/file", &create().create_new(true)).err(),
+            ErrorKind::AlreadyExists,
+        ),
+        (fs.open("/dir", &read()).err(), ErrorKind::IsADirectory),
+        (
+            file.seek(&mut fs, SeekFrom::Current(-1)).err(),
+            ErrorKind::InvalidInput,
+        ),
+    ];
+    for (error, kind) in cases {
+        assert_eq!(error.map(error::kind), Some(kind), "{error:?}");
+    }
+    file.close(&mut fs).unwrap();
+    fs.read_only = true;
+    let error = fs.open("/file", &create()).err();
+    assert_eq!(error, Some(error::READ_ONLY_FS));
+    assert_eq!(error::kind(error.unwrap()), ErrorKind::ReadOnly);
+    assert_eq!(error::kind("File not open for writing"), ErrorKind::Other);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn handles_implement_std_io() {
+    use std::io::{self, Read, Seek, Write};
+
+    let mut fs = fresh_fs();
+    let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    let mut handle = fs.open("/data", &create().read(true)).unwrap();
+    let mut file = handle.io(&mut fs);
+    file.write_all(&data).unwrap();
+    io::Write::flush(&mut file).unwrap();
+
+    io::Seek::seek(&mut file, io::SeekFrom::Start(1000)).unwrap();
+    let mut rest = Vec::new();
+    file.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, data[1000..]);
+    assert_eq!(file.stream_position().unwrap(), 5000);
+
+    let error = io::Seek::seek(&mut file, io::SeekFrom::End(-6000)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    let error = io::Seek::seek(&mut file, io::SeekFrom::Start(u64::MAX)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    io::Seek::seek(&mut file, io::SeekFrom::Start(MAX_FILE_LENGTH)).unwrap();
+    let error = io::Write::write(&mut file, b"x").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::FileTooLarge);
+    // The position is kept by the handle.
+    assert_eq!(handle.position(), MAX_FILE_LENGTH);
+    handle.close(&mut fs).unwrap();
+
+    let mut handle = fs.open("/data", &read()).unwrap();
+    let mut file = handle.io(&mut fs);
+    let error = io::Write::write(&mut file, b"x").unwrap_err();
+    assert_eq!(error.to_string(), "File not open for writing");
+    let mut reader = io::BufReader::new(file);
+    let mut first = [0u8; 3];
+    reader.read_exact(&mut first).unwrap();
+    assert_eq!(first, [0, 1, 2]);
+    drop(reader);
+    handle.close(&mut fs).unwrap();
+}
+
+#[cfg(feature = "embedded-io")]
+#[test]
+fn handles_implement_embedded_io() {
+    use embedded_io::{Error, ErrorKind, Read, Seek, Write};
+
+    let mut fs = fresh_fs();
+    let mut handle = fs.open("/data", &create().read(true)).unwrap();
+    let mut file = handle.io(&mut fs);
+    Write::write_all(&mut file, b"hello world").unwrap();
+    Write::flush(&mut file).unwrap();
+    Seek::seek(&mut file, embedded_io::SeekFrom::Start(6)).unwrap();
+    let mut buf = [0u8; 5];
+    Read::read_exact(&mut file, &mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    let error = Seek::seek(&mut file, embedded_io::SeekFrom::Current(-12)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert_eq!(error.0, "Invalid seek");
+    handle.close(&mut fs).unwrap();
+}
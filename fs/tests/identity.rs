@@ -0,0 +1,162 @@
+//! The tests of the UUID, the label and the other identity fields of the super block.
+
+mod common;
+
+use common::{IMAGE_SIZE, format_options, fresh_image};
+use proka_fs::definition::{SuperBlock, Uuid};
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::partition::{PKFS_MBR_TYPE, find_by_label, find_by_uuid};
+use proka_fs::ram::{RamBlockDevice, RomBlockDevice};
+use proka_fs::{FileSystem, GenericFsData};
+
+/// Format an image with some options.
+fn format_image(options: &FormatOptions) -> Result<Vec<u8>, &'static str> {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        options,
+    )?;
+    Ok(image)
+}
+
+#[test]
+fn format_sets_the_identity() {
+    let options = FormatOptions {
+        time: 1234,
+        label: "root".to_string(),
+        random: Some(|buf| buf.fill(0xFF)),
+        creator: "test 1.0",
+        ..format_options()
+    };
+    let image = format_image(&options).unwrap();
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    assert_eq!(super_block.label(), "root");
+    assert_eq!(super_block.creator(), "test 1.0");
+    assert_eq!(super_block.created, 1234);
+    assert_eq!(super_block.mount_count, 0);
+    assert_eq!(
+        super_block.uuid.to_string(),
+        "ffffffff-ffff-4fff-bfff-ffffffffffff"
+    );
+}
+
+#[test]
+fn no_random_source() {
+    let options = FormatOptions {
+        random: None,
+        ..FormatOptions::default()
+    };
+    assert_eq!(format_image(&options).err(), Some("No random source"));
+    let options = FormatOptions {
+        uuid: Some(Uuid::NIL),
+        ..options
+    };
+    assert!(format_image(&options).is_ok());
+}
+
+#[test]
+fn random_uuids_differ() {
+    let first = SuperBlock::from_bytes(&fresh_image()).unwrap().uuid;
+    let second = SuperBlock::from_bytes(&fresh_image()).unwrap().uuid;
+    assert!(!first.is_nil());
+    assert_ne!(first, second);
+}
+
+#[test]
+fn given_uuids_are_kept() {
+    let uuid: Uuid = "0123ABCD-4567-89ab-cdef-0123456789AB".parse().unwrap();
+    let options = FormatOptions {
+        uuid: Some(uuid),
+        ..format_options()
+    };
+    let image = format_image(&options).unwrap();
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    assert_eq!(super_block.uuid, uuid);
+    assert_eq!(
+        super_block.uuid.to_string(),
+        "0123abcd-4567-89ab-cdef-0123456789ab"
+    );
+}
+
+#[test]
+fn invalid_uuids_and_labels_are_refused() {
+    for uuid in [
+        "",
+        "0123abcd-4567-89ab-cdef-0123456789a",
+        "0123abcd-4567-89ab-cdef-0123456789abc",
+        "0123abcd+4567-89ab-cdef-0123456789ab",
+        "0123abcd-4567-89ab-cdef-0123456789ag",
+    ] {
+        assert_eq!(uuid.parse::<Uuid>(), Err("Invalid UUID"), "{uuid}");
+    }
+
+    let options = FormatOptions {
+        label: "l".repeat(33),
+        ..format_options()
+    };
+    assert_eq!(format_image(&options).err(), Some("Invalid label"));
+    let options = FormatOptions {
+        label: "l".repeat(32),
+        ..format_options()
+    };
+    assert!(format_image(&options).is_ok());
+}
+
+#[test]
+fn mounts_are_counted() {
+    let mut image = fresh_image();
+    for count in 1..=3 {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        fs.sync().unwrap();
+        assert_eq!(fs.super_block.mount_count, count);
+    }
+    let super_block = SuperBlock::from_bytes(&image).unwrap();
+    assert_eq!(super_block.mount_count, 3);
+    // Without `std`, the default clock always gives 0.
+    if cfg!(feature = "std") {
+        assert!(super_block.last_mounted > 0);
+    }
+}
+
+/// Create a disk with an MBR holding two ProkaFS partitions of 1 MiB.
+fn disk(first: &FormatOptions, second: &FormatOptions) -> Vec<u8> {
+    let mut disk = vec![0u8; 3 * IMAGE_SIZE];
+    for (i, options) in [first, second].into_iter().enumerate() {
+        let start = (i + 1) * IMAGE_SIZE;
+        let entry = &mut disk[446 + i * 16..462 + i * 16];
+        entry[4] = PKFS_MBR_TYPE;
+        entry[8..12].copy_from_slice(&(start as u32 / 512).to_le_bytes());
+        entry[12..16].copy_from_slice(&(IMAGE_SIZE as u32 / 512).to_le_bytes());
+        disk[start..start + IMAGE_SIZE].copy_from_slice(&format_image(options).unwrap());
+    }
+    disk[510] = 0x55;
+    disk[511] = 0xAA;
+    disk
+}
+
+#[test]
+fn partitions_are_found_by_uuid_and_label() {
+    let boot = FormatOptions {
+        label: "boot".to_string(),
+        ..format_options()
+    };
+    let root = FormatOptions {
+        label: "root".to_string(),
+        ..format_options()
+    };
+    let disk = disk(&boot, &root);
+    let root_uuid = SuperBlock::from_bytes(&disk[2 * IMAGE_SIZE..])
+        .unwrap()
+        .uuid;
+
+    let bd = find_by_label(RomBlockDevice::new(&disk[..]), "root").unwrap();
+    assert_eq!(bd.start(), 2 * IMAGE_SIZE as u64);
+    let bd = find_by_uuid(RomBlockDevice::new(&disk[..]), root_uuid).unwrap();
+    assert_eq!(bd.start(), 2 * IMAGE_SIZE as u64);
+    let fs = FileSystem::mount(find_by_label(RamBlockDevice::new(disk.clone()), "boot").unwrap());
+    assert_eq!(fs.unwrap().super_block.label(), "boot");
+
+    assert!(find_by_label(RomBlockDevice::new(&disk[..]), "home").is_err());
+    assert!(find_by_uuid(RomBlockDevice::new(&disk[..]), Uuid::NIL).is_err());
+}
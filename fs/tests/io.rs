@@ -0,0 +1,162 @@
+//! The tests of the file reads and writes, and of the multi-block device I/O.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::definition::{DIRECT_BLOCKS, MAX_FILE_LENGTH};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{BlockDevice, FileSystem};
+
+/// Mount a fresh image with an empty file, and get the file.
+fn fs_with_file() -> (FileSystem<RamBlockDevice<Vec<u8>>>, u32) {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    (fs, file)
+}
+
+/// Some data which differs in every block.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn data_spans_the_pointer_blocks() {
+    let (mut fs, file) = fs_with_file();
+    // From the direct blocks, through the indirect block, into the double indirect one.
+    let offset = (DIRECT_BLOCKS as u64 - 1) * 1024 + 500;
+    let data = pattern(300 * 1024);
+    assert_eq!(fs.write_at(file, offset, &data), Ok(data.len()));
+    assert_eq!(fs.stat(file).unwrap().size, offset + data.len() as u64);
+
+    let mut buf = vec![0; data.len()];
+    assert_eq!(fs.read_at(file, offset, &mut buf), Ok(data.len()));
+    assert_eq!(buf, data);
+
+    // Unaligned reads across the blocks, and the hole before the data.
+    let mut buf = vec![0; 5000];
+    fs.read_at(file, offset + 777, &mut buf).unwrap();
+    assert_eq!(buf, data[777..5777]);
+    let mut buf = vec![1; 2000];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(buf, vec![0; 2000]);
+}
+
+#[test]
+fn reads_stop_at_the_end() {
+    let (mut fs, file) = fs_with_file();
+    fs.write_at(file, 0, b"hello").unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(fs.read_at(file, 2, &mut buf), Ok(3));
+    assert_eq!(&buf[..3], b"llo");
+    assert_eq!(fs.read_at(file, 5, &mut buf), Ok(0));
+    assert_eq!(fs.read_at(file, MAX_FILE_LENGTH, &mut buf), Ok(0));
+    assert_eq!(
+        fs.read_at(file, MAX_FILE_LENGTH + 1, &mut buf),
+        Err("File too large")
+    );
+    assert_eq!(fs.read_at(file, u64::MAX, &mut buf), Err("File too large"));
+}
+
+#[test]
+fn writes_past_the_max_length_fail() {
+    let (mut fs, file) = fs_with_file();
+    fs.write_at(file, 0, b"hello").unwrap();
+    let free_blocks = fs.statfs().free_blocks;
+
+    // The block index of 1 << 42 doesn't fit in 32 bits, and used to wrap to block 0.
+    assert_eq!(fs.write_at(file, 1 << 42, b"x"), Err("File too large"));
+    // The end of the write overflows.
+    assert_eq!(fs.write_at(file, u64::MAX, b"x"), Err("File too large"));
+    assert_eq!(
+        fs.write_at(file, MAX_FILE_LENGTH, b"x"),
+        Err("File too large")
+    );
+
+    assert_eq!(fs.statfs().free_blocks, free_blocks);
+    assert_eq!(fs.stat(file).unwrap().size, 5);
+    let mut buf = [0; 5];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+#[test]
+fn directories_are_not_written_as_data() {
+    let (mut fs, _) = fs_with_file();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    assert_eq!(fs.write_at(dir, 0, b"garbage"), Err("Is a directory"));
+    assert_eq!(fs.write_at(0, 0, b"garbage"), Err("Is a directory"));
+
+    assert_eq!(fs.ls(dir).unwrap().len(), 2);
+    assert!(fs.lookup("/file").is_ok());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn files_reach_the_max_length() {
+    let (mut fs, file) = fs_with_file();
+    assert_eq!(fs.write_at(file, MAX_FILE_LENGTH - 1, b"x"), Ok(1));
+    assert_eq!(fs.stat(file).unwrap().size, MAX_FILE_LENGTH);
+    let mut buf = [0; 16];
+    assert_eq!(fs.read_at(file, MAX_FILE_LENGTH - 2, &mut buf), Ok(2));
+    assert_eq!(&buf[..2], b"\0x");
+
+    assert_eq!(
+        fs.truncate(file, MAX_FILE_LENGTH + 1),
+        Err("File too large")
+    );
+    assert_eq!(fs.fallocate(file, u64::MAX, 2), Err("File too large"));
+    fs.truncate(file, 0).unwrap();
+    assert_eq!(fs.stat(file).unwrap().blocks, 0);
+}
+
+#[test]
+fn vectored_defaults() {
+    let mut bd = RamBlockDevice::new(vec![0u8; 8 * 1024]);
+    let data = pattern(3 * 1024);
+    bd.write_blocks_vectored(2, &[&data[..1024], &data[1024..]])
+        .unwrap();
+
+    let mut buf = vec![0; 3 * 1024];
+    bd.read_blocks(2, &mut buf).unwrap();
+    assert_eq!(buf, data);
+
+    // Split differently from the writes.
+    let (mut a, mut b) = (vec![0; 2048], vec![0; 1024]);
+    bd.read_blocks_vectored(2, &mut [&mut a, &mut b]).unwrap();
+    assert_eq!(a, data[..2048]);
+    assert_eq!(b, data[2048..]);
+
+    let mut block = [0; 1024];
+    bd.read_block(1, 0, &mut block).unwrap();
+    assert_eq!(block, [0; 1024]);
+    bd.read_block(5, 0, &mut block).unwrap();
+    assert_eq!(block, [0; 1024]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_device_multi_block_io() {
+    let path = std::env::temp_dir().join(format!("proka-fs-io-{}.img", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut bd = proka_fs::init_block_device(path.to_str().unwrap()).unwrap();
+    let data = pattern(4 * 1024);
+
+    bd.write_blocks(0, &data).unwrap();
+    bd.write_blocks_vectored(4, &[&data[2048..], &data[..2048]])
+        .unwrap();
+    let mut buf = vec![0; 8 * 1024];
+    bd.read_blocks(0, &mut buf).unwrap();
+    assert_eq!(buf[..4096], data);
+    assert_eq!(buf[4096..6144], data[2048..]);
+    assert_eq!(buf[6144..], data[..2048]);
+
+    let (mut a, mut b) = (vec![0; 1024], vec![0; 3072]);
+    bd.read_blocks_vectored(1, &mut [&mut a, &mut b]).unwrap();
+    assert_eq!(a, data[1024..2048]);
+    assert_eq!(b, buf[2048..5120]);
+
+    drop(bd);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,63 @@
+//! The tests of the metadata of the files.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::FileSystem;
+use proka_fs::definition::FileType;
+use proka_fs::ram::RamBlockDevice;
+
+#[test]
+fn stat_reports_the_inode() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    fs.clock = || 1_700_000_000;
+    fs.mkdir(0, "etc").unwrap();
+    let etc = fs.lookup("/etc").unwrap();
+    fs.mkfile(etc, "passwd").unwrap();
+    let passwd = fs.lookup("/etc/passwd").unwrap();
+    fs.write_at(passwd, 0, b"root:x:0:0::/root:/bin/sh\n")
+        .unwrap();
+
+    let metadata = fs.stat_path("/etc/passwd").unwrap();
+    assert_eq!(metadata, fs.stat(passwd).unwrap());
+    assert!(metadata.is_file());
+    assert_eq!(metadata.inode_id, passwd);
+    assert_eq!((metadata.size, metadata.blocks), (26, 1));
+    assert_eq!((metadata.links, metadata.mode), (1, 0o644));
+    assert_eq!(metadata.modified, 1_700_000_000);
+    assert_eq!(metadata.changed, 1_700_000_000);
+
+    let metadata = fs.stat(etc).unwrap();
+    assert!(metadata.is_dir());
+    assert_eq!(metadata.file_type, FileType::Directory);
+    assert_eq!((metadata.links, metadata.mode), (2, 0o755));
+    // The root directory is linked by the '..' of its subdirectory.
+    assert_eq!(fs.stat(0).unwrap().links, 3);
+}
+
+#[test]
+fn blocks_count_the_pointer_blocks() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    // 13 data blocks, the last one is mapped by the indirect block.
+    fs.write_at(file, 0, &[1; 13 * 1024]).unwrap();
+    let free_blocks = fs.statfs().free_blocks;
+    assert_eq!(fs.stat(file).unwrap().blocks, 14);
+
+    // The extended attribute block isn't counted.
+    fs.setxattr(file, "user.big", &[2; 500]).unwrap();
+    assert_eq!(fs.statfs().free_blocks, free_blocks - 1);
+    assert_eq!(fs.stat(file).unwrap().blocks, 14);
+}
+
+#[test]
+fn stat_fails_on_missing_files() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    assert_eq!(fs.stat(5).err(), Some("Inode not found"));
+    assert_eq!(fs.stat(u32::MAX).err(), Some("Inode not found"));
+    assert_eq!(
+        fs.stat_path("/missing").err(),
+        Some("No such file or directory")
+    );
+}
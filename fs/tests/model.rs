@@ -0,0 +1,263 @@
+//! The model-based property tests: random operation sequences run against the
+//! file system and a simple in-memory model, which must always agree.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::FileSystem;
+use proka_fs::definition::FileType;
+use proka_fs::ram::RamBlockDevice;
+use proptest::prelude::*;
+use proptest::sample::Index;
+use std::collections::BTreeMap;
+
+/// The names used by the operations, a few of them are invalid.
+fn names() -> Vec<String> {
+    let mut names: Vec<String> = ["a", "b", "hello.txt", ".", "..", "", "x/y"]
+        .map(String::from)
+        .to_vec();
+    // The longest valid name, and a name one byte too long.
+    names.push("l".repeat(251));
+    names.push("t".repeat(252));
+    names
+}
+
+/// A file in the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Dir,
+    File(Vec<u8>),
+    Fifo,
+}
+
+/// The reference model: every path, from "/" on.
+struct Model {
+    nodes: BTreeMap<String, Node>,
+}
+
+impl Model {
+    fn new() -> Self {
+        Self {
+            nodes: BTreeMap::from([("/".to_string(), Node::Dir)]),
+        }
+    }
+
+    /// Get the child paths of a directory, with their names.
+    fn children(&self, dir: &str) -> Vec<(String, String)> {
+        self.nodes
+            .keys()
+            .filter(|path| path.as_str() != "/" && parent(path) == dir)
+            .map(|path| (path.clone(), path.rsplit('/').next().unwrap().to_string()))
+            .collect()
+    }
+
+    /// Pick a path among the ones matching a filter.
+    fn pick(&self, index: &Index, filter: impl Fn(&str, &Node) -> bool) -> Option<String> {
+        let paths: Vec<&String> = self
+            .nodes
+            .iter()
+            .filter(|(path, node)| filter(path, node))
+            .map(|(path, _)| path)
+            .collect();
+        (!paths.is_empty()).then(|| index.get(&paths).to_string())
+    }
+
+    /// Check if a new name is accepted in a directory.
+    fn can_create(&self, dir: &str, name: &str) -> bool {
+        let valid = !name.is_empty()
+            && name != "."
+            && name != ".."
+            && !name.contains('/')
+            && name.len() <= 251;
+        valid && !self.nodes.contains_key(&join(dir, name))
+    }
+}
+
+/// Get the parent of a path.
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) => "/",
+        Some(i) => &path[..i],
+        None => "/",
+    }
+}
+
+/// Join a directory path and a name.
+fn join(dir: &str, name: &str) -> String {
+    if dir == "/" {
+        format!("/{name}")
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+/// An operation on the file system.
+#[derive(Debug, Clone)]
+enum Op {
+    Mkdir(Index, usize),
+    Mkfile(Index, usize),
+    Mkfifo(Index, usize),
+    Write(Index, u64, usize, u8),
+    Unlink(Index),
+    Rmdir(Index),
+    Remount,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let name = 0..names().len();
+    prop_oneof![
+        3 => (any::<Index>(), name.clone()).prop_map(|(dir, name)| Op::Mkdir(dir, name)),
+        4 => (any::<Index>(), name.clone()).prop_map(|(dir, name)| Op::Mkfile(dir, name)),
+        1 => (any::<Index>(), name).prop_map(|(dir, name)| Op::Mkfifo(dir, name)),
+        // Up to the double indirect blocks, which start at 268 KiB.
+        4 => (any::<Index>(), prop_oneof![0..4096u64, 0..300 * 1024u64], 0..8 * 1024usize, any::<u8>())
+            .prop_map(|(file, offset, len, seed)| Op::Write(file, offset, len, seed)),
+        2 => any::<Index>().prop_map(Op::Unlink),
+        2 => any::<Index>().prop_map(Op::Rmdir),
+        1 => Just(Op::Remount),
+    ]
+}
+
+/// Some bytes depending on a seed.
+fn data(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+        .collect()
+}
+
+/// Apply an operation to both the file system and the model.
+fn apply(fs: &mut FileSystem<RamBlockDevice<Vec<u8>>>, model: &mut Model, op: &Op) {
+    match *op {
+        Op::Mkdir(ref dir, name) | Op::Mkfile(ref dir, name) | Op::Mkfifo(ref dir, name) => {
+            let dir = model.pick(dir, |_, node| *node == Node::Dir).unwrap();
+            let name = &names()[name];
+            let dir_id = fs.lookup(&dir).unwrap();
+            let (result, node) = match op {
+                Op::Mkdir(..) => (fs.mkdir(dir_id, name), Node::Dir),
+                Op::Mkfile(..) => (fs.mkfile(dir_id, name), Node::File(Vec::new())),
+                _ => (fs.mkfifo(dir_id, name), Node::Fifo),
+            };
+            if model.can_create(&dir, name) {
+                result.unwrap();
+                model.nodes.insert(join(&dir, name), node);
+            } else {
+                assert!(result.is_err(), "{op:?} in {dir} should fail");
+            }
+        }
+        Op::Write(ref file, offset, len, seed) => {
+            let Some(path) = model.pick(file, |_, node| matches!(node, Node::File(_))) else {
+                return;
+            };
+            let data = data(len, seed);
+            let id = fs.lookup(&path).unwrap();
+            assert_eq!(fs.write_at(id, offset, &data).unwrap(), len);
+
+            let Some(Node::File(content)) = model.nodes.get_mut(&path) else {
+                unreachable!()
+            };
+            let end = offset as usize + len;
+            if content.len() < end && len > 0 {
+                content.resize(end, 0);
+            }
+            if len > 0 {
+                content[offset as usize..end].copy_from_slice(&data);
+            }
+        }
+        Op::Unlink(ref entry) | Op::Rmdir(ref entry) => {
+            let Some(path) = model.pick(entry, |path, _| path != "/") else {
+                return;
+            };
+            let dir = parent(&path).to_string();
+            let name = path.rsplit('/').next().unwrap();
+            let dir_id = fs.lookup(&dir).unwrap();
+            let is_dir = model.nodes[&path] == Node::Dir;
+            let (result, allowed) = match op {
+                Op::Unlink(_) => (fs.unlink(dir_id, name), !is_dir),
+                _ => (
+                    fs.rmdir(dir_id, name),
+                    is_dir && model.children(&path).is_empty(),
+                ),
+            };
+            if allowed {
+                result.unwrap();
+                model.nodes.remove(&path);
+            } else {
+                assert!(result.is_err(), "{op:?} on {path} should fail");
+            }
+        }
+        Op::Remount => {
+            fs.sync().unwrap();
+            let bd = std::mem::replace(&mut fs.block_device, RamBlockDevice::new(Vec::new()));
+            *fs = FileSystem::mount(bd).unwrap();
+        }
+    }
+}
+
+/// Check the file system matches the model.
+fn compare(fs: &mut FileSystem<RamBlockDevice<Vec<u8>>>, model: &Model) {
+    for (path, node) in &model.nodes {
+        let id = fs.lookup(path).unwrap();
+        let metadata = fs.stat(id).unwrap();
+        match node {
+            Node::Dir => {
+                let mut names: Vec<String> = fs
+                    .ls(id)
+                    .unwrap()
+                    .iter()
+                    .map(|entry| String::from_utf8(entry.name_bytes().to_vec()).unwrap())
+                    .filter(|name| name != "." && name != "..")
+                    .collect();
+                names.sort();
+                let mut expected: Vec<String> = model
+                    .children(path)
+                    .into_iter()
+                    .map(|(_, name)| name)
+                    .collect();
+                expected.sort();
+                assert_eq!(names, expected, "entries of {path}");
+
+                let subdirs = model
+                    .children(path)
+                    .iter()
+                    .filter(|(child, _)| model.nodes[child] == Node::Dir)
+                    .count();
+                assert_eq!(metadata.file_type, FileType::Directory, "type of {path}");
+                assert_eq!(metadata.links as usize, 2 + subdirs, "links of {path}");
+            }
+            Node::File(content) => {
+                assert_eq!(metadata.file_type, FileType::Regular, "type of {path}");
+                assert_eq!(metadata.size, content.len() as u64, "size of {path}");
+                assert_eq!(metadata.links, 1, "links of {path}");
+                let mut buf = vec![0u8; content.len() + 10];
+                assert_eq!(fs.read_at(id, 0, &mut buf).unwrap(), content.len());
+                assert!(buf[..content.len()] == content[..], "content of {path}");
+            }
+            Node::Fifo => {
+                assert_eq!(metadata.file_type, FileType::Fifo, "type of {path}");
+                assert_eq!(metadata.size, 0, "size of {path}");
+            }
+        }
+    }
+
+    assert!(fs.lookup("/missing").is_err());
+    let report = fs.check().unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn file_system_matches_model(ops in prop::collection::vec(op(), 1..40)) {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+        let mut model = Model::new();
+        for op in &ops {
+            apply(&mut fs, &mut model, op);
+            compare(&mut fs, &model);
+        }
+
+        // Everything must survive a remount.
+        apply(&mut fs, &mut model, &Op::Remount);
+        compare(&mut fs, &model);
+    }
+}
@@ -0,0 +1,47 @@
+//! The tests of the special file nodes: named pipes and Unix domain sockets.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::FileSystem;
+use proka_fs::definition::FileType;
+use proka_fs::ram::RamBlockDevice;
+
+#[test]
+fn fifos_and_sockets_are_persisted() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkdir(0, "run").unwrap();
+    let run = fs.lookup("/run").unwrap();
+    fs.mkfifo(run, "initctl").unwrap();
+    fs.mksock(run, "log.sock").unwrap();
+    fs.unmount().unwrap();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let fifo = fs.stat_path("/run/initctl").unwrap();
+    assert_eq!(
+        (fifo.file_type, fifo.size, fifo.blocks),
+        (FileType::Fifo, 0, 0)
+    );
+    let socket = fs.stat_path("/run/log.sock").unwrap();
+    assert_eq!(socket.file_type, FileType::Socket);
+    assert!(!socket.is_file() && !socket.is_dir());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn nodes_follow_the_rules_of_files() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    fs.mkfifo(0, "pipe").unwrap();
+    assert_eq!(fs.mksock(0, "pipe"), Err("File exists"));
+    assert_eq!(fs.mkfifo(0, ""), Err("Invalid name"));
+    assert_eq!(fs.mksock(0, &"s".repeat(300)), Err("Name too long"));
+
+    let pipe = fs.lookup("/pipe").unwrap();
+    assert_eq!(fs.mkfifo(pipe, "child"), Err("Not a directory"));
+    let free_inodes = fs.statfs().free_inodes;
+    fs.unlink(0, "pipe").unwrap();
+    assert_eq!(fs.statfs().free_inodes, free_inodes + 1);
+    assert_eq!(fs.lookup("/pipe"), Err("No such file or directory"));
+    assert!(fs.check().unwrap().is_clean());
+}
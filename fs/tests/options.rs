@@ -0,0 +1,215 @@
+//! The tests of the mount options.
+
+mod common;
+
+use common::{IMAGE_SIZE, fresh_image};
+use proka_fs::options::{
+    AtimePolicy, DEFAULT_CACHE_BLOCKS, ErrorBehavior, JournalMode, MountOptions,
+};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{ChecksumPolicy, FileSystem};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The time given by [`clock`].
+static NOW: AtomicU64 = AtomicU64::new(0);
+
+/// A clock which can be moved by the tests.
+fn clock() -> u64 {
+    NOW.load(Ordering::Relaxed)
+}
+
+/// Create an image with a file in a directory.
+fn populated_image() -> Vec<u8> {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    fs.mkfile(dir, "file").unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    fs.write_at(file, 0, b"hello").unwrap();
+    fs.unmount().unwrap();
+    image
+}
+
+/// Corrupt the block of "/dir", whose third entry is "file".
+fn corrupt_dir(image: &mut [u8]) {
+    let entry = 2 * 256 + 4;
+    let block = (0..IMAGE_SIZE / 1024)
+        .find(|&block| image[block * 1024 + entry..].starts_with(b"file\0"))
+        .unwrap();
+    image[block * 1024 + entry] ^= 0x10;
+}
+
+#[test]
+fn option_strings_are_parsed() {
+    assert_eq!(MountOptions::parse("").unwrap(), MountOptions::default());
+    assert_eq!(
+        MountOptions::parse("ro,strictatime,cache=128,errors=panic,csum=ro,journal=none,sync")
+            .unwrap(),
+        MountOptions::default()
+            .read_only(true)
+            .atime(AtimePolicy::Strict)
+            .cache_blocks(128)
+            .errors(ErrorBehavior::Panic)
+            .checksum_policy(ChecksumPolicy::ReadOnly)
+            .sync(true)
+    );
+    // The later options win, and the empty ones are ignored.
+    assert_eq!(
+        MountOptions::parse("ro,,noatime,rw,sync,async,").unwrap(),
+        MountOptions::default().atime(AtimePolicy::NoAtime)
+    );
+
+    for (options, error) in [
+        ("noexec", "Unknown mount option"),
+        ("ro=1", "Unknown mount option"),
+        ("cache", "Unknown mount option"),
+        ("cache=-1", "Invalid mount option value"),
+        ("errors=ignore", "Invalid mount option value"),
+        ("csum=off", "Invalid mount option value"),
+        ("journal=full", "Invalid mount option value"),
+    ] {
+        assert_eq!(MountOptions::parse(options), Err(error), "{options}");
+    }
+}
+
+#[test]
+fn read_only_mounts_write_nothing() {
+    let mut image = populated_image();
+    let before = image.clone();
+    let options = MountOptions::parse("ro,strictatime").unwrap();
+    let mut fs = FileSystem::mount_with(RamBlockDevice::new(&mut image[..]), &options).unwrap();
+    assert!(fs.read_only);
+    let file = fs.lookup("/dir/file").unwrap();
+    let mut buf = [0u8; 5];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(fs.mkdir(0, "new"), Err("Read-only file system"));
+    fs.unmount().unwrap();
+    assert!(image == before);
+}
+
+#[test]
+fn access_times_follow_the_policy() {
+    const DAY: u64 = 24 * 60 * 60;
+    let image = populated_image();
+    let read_times = |atime: AtimePolicy| {
+        let options = MountOptions::default().atime(atime);
+        let mut fs = FileSystem::mount_with(RamBlockDevice::new(image.clone()), &options).unwrap();
+        fs.clock = clock;
+        let file = fs.lookup("/dir/file").unwrap();
+        let created = fs.stat(file).unwrap().accessed;
+        [10, 20, DAY + 20].map(|delay| {
+            NOW.store(created + delay, Ordering::Relaxed);
+            let mut buf = [0u8; 5];
+            fs.read_at(file, 0, &mut buf).unwrap();
+            fs.stat(file).unwrap().accessed - created
+        })
+    };
+
+    assert_eq!(read_times(AtimePolicy::NoAtime), [0, 0, 0]);
+    // Once after the creation, then once a day.
+    assert_eq!(read_times(AtimePolicy::Relatime), [10, 10, DAY + 20]);
+    assert_eq!(read_times(AtimePolicy::Strict), [10, 20, DAY + 20]);
+}
+
+#[test]
+fn listing_a_directory_updates_its_access_time() {
+    let mut image = populated_image();
+    let options = MountOptions::default().atime(AtimePolicy::Strict);
+    let mut fs = FileSystem::mount_with(RamBlockDevice::new(&mut image[..]), &options).unwrap();
+    fs.clock = || u64::MAX;
+    let dir = fs.lookup("/dir").unwrap();
+    // Looking up a path doesn't.
+    fs.lookup("/dir/file").unwrap();
+    assert_ne!(fs.stat(dir).unwrap().accessed, u64::MAX);
+    fs.ls(dir).unwrap();
+    assert_eq!(fs.stat(dir).unwrap().accessed, u64::MAX);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn errors_can_make_the_file_system_read_only() {
+    let mut image = populated_image();
+    corrupt_dir(&mut image);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image.clone())).unwrap();
+    assert_eq!(fs.lookup("/dir/file").err(), Some("Checksum mismatch"));
+    assert!(!fs.read_only);
+
+    let options = MountOptions::parse("errors=remount-ro").unwrap();
+    let mut fs = FileSystem::mount_with(RamBlockDevice::new(&mut image[..]), &options).unwrap();
+    assert_eq!(fs.lookup("/dir/file").err(), Some("Checksum mismatch"));
+    assert!(fs.read_only);
+    assert_eq!(fs.mkfile(0, "new"), Err("Read-only file system"));
+    // The scrub reports the corruption without failing.
+    assert!(!fs.scrub().unwrap().is_clean());
+    fs.unmount().unwrap();
+    // The file system is left not clean for the checker.
+    let fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(!fs.was_clean);
+}
+
+#[test]
+#[should_panic(expected = "Checksum mismatch")]
+fn errors_can_panic() {
+    let mut image = populated_image();
+    corrupt_dir(&mut image);
+    let options = MountOptions::parse("errors=panic").unwrap();
+    let mut fs = FileSystem::mount_with(RamBlockDevice::new(image), &options).unwrap();
+    // The scrub never panics.
+    assert!(!fs.scrub().unwrap().is_clean());
+    let _ = fs.lookup("/dir/file");
+}
+
+#[test]
+fn journals_are_refused() {
+    let image = fresh_image();
+    for journal in [
+        JournalMode::Ordered,
+        JournalMode::Writeback,
+        JournalMode::Data,
+    ] {
+        let options = MountOptions::default().journal(journal);
+        assert_eq!(
+            FileSystem::mount_with(RamBlockDevice::new(image.clone()), &options).err(),
+            Some("Unsupported journal mode")
+        );
+    }
+}
+
+#[test]
+fn caches_need_a_cached_mount() {
+    let mut image = fresh_image();
+    let options = MountOptions::parse("cache=16").unwrap();
+    assert_eq!(
+        FileSystem::mount_with(RamBlockDevice::new(&mut image[..]), &options).err(),
+        Some("Unsupported block cache")
+    );
+
+    // The scrub reads more blocks than any cache holds.
+    for (options, capacity) in [
+        (options, 16),
+        (MountOptions::default(), DEFAULT_CACHE_BLOCKS),
+    ] {
+        let mut fs =
+            FileSystem::mount_cached(RamBlockDevice::new(&mut image[..]), &options).unwrap();
+        fs.scrub().unwrap();
+        assert_eq!(fs.block_device.cached_blocks(), capacity);
+    }
+}
+
+#[test]
+fn sync_writes_survive_a_crash_behind_a_cache() {
+    for sync in [false, true] {
+        let mut image = fresh_image();
+        let options = MountOptions::default().cache_blocks(1024).sync(sync);
+        let mut fs =
+            FileSystem::mount_cached(RamBlockDevice::new(&mut image[..]), &options).unwrap();
+        fs.mkfile(0, "file").unwrap();
+        // The cache is lost with everything it holds.
+        std::mem::forget(fs);
+
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        assert_eq!(fs.lookup("/file").is_ok(), sync, "sync: {sync}");
+    }
+}
@@ -0,0 +1,131 @@
+//! The tests of the clean-unmount state and of the orphan list.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::definition::SuperBlock;
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{FileSystem, GenericFsData};
+
+/// Read the super block of an image.
+fn super_block(image: &[u8]) -> SuperBlock {
+    SuperBlock::from_bytes(image).unwrap()
+}
+
+#[test]
+fn the_state_tells_clean_unmounts() {
+    let mut image = fresh_image();
+    assert!(super_block(&image).is_clean());
+
+    let fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.was_clean);
+    fs.unmount().unwrap();
+    assert!(super_block(&image).is_clean());
+
+    // Dropping the file system unmounts it too.
+    let fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    drop(fs);
+    assert!(super_block(&image).is_clean());
+
+    // A crash leaves it not clean on the device.
+    let fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    std::mem::forget(fs);
+    assert!(!super_block(&image).is_clean());
+    let fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(!fs.was_clean);
+}
+
+#[test]
+fn open_files_outlive_their_unlink() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let free_inodes = fs.super_block.free_inodes;
+    fs.mkfile(0, "tmp").unwrap();
+    let tmp = fs.lookup("/tmp").unwrap();
+    fs.write_at(tmp, 0, &[0x5A; 4096]).unwrap();
+    fs.open_inode(tmp).unwrap();
+    fs.open_inode(tmp).unwrap();
+
+    fs.unlink(0, "tmp").unwrap();
+    assert!(fs.lookup("/tmp").is_err());
+    assert_eq!(fs.super_block.orphan_head, tmp);
+    let mut buf = [0u8; 16];
+    fs.read_at(tmp, 100, &mut buf).unwrap();
+    assert_eq!(buf, [0x5A; 16]);
+    // The orphans are expected in no directory.
+    assert!(fs.check().unwrap().is_clean());
+
+    // Released by the last close.
+    fs.close_inode(tmp).unwrap();
+    assert!(fs.stat(tmp).is_ok());
+    fs.close_inode(tmp).unwrap();
+    assert!(fs.stat(tmp).is_err());
+    assert_eq!(fs.super_block.orphan_head, 0);
+    assert_eq!(fs.super_block.free_inodes, free_inodes);
+    assert_eq!(fs.close_inode(tmp), Err("Inode not open"));
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn orphans_are_released_in_any_order() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let mut files = Vec::new();
+    for name in ["a", "b", "c"] {
+        fs.mkfile(0, name).unwrap();
+        let file = fs.lookup(&format!("/{name}")).unwrap();
+        fs.open_inode(file).unwrap();
+        fs.unlink(0, name).unwrap();
+        files.push(file);
+    }
+
+    // From the middle of the list, then its head and its tail.
+    for file in [files[1], files[2], files[0]] {
+        fs.close_inode(file).unwrap();
+        assert!(fs.stat(file).is_err());
+        assert!(fs.check().unwrap().is_clean());
+    }
+    assert_eq!(fs.super_block.orphan_head, 0);
+}
+
+#[test]
+fn orphans_are_released_after_a_crash() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let free = (fs.super_block.free_blocks, fs.super_block.free_inodes);
+    fs.mkfile(0, "tmp").unwrap();
+    let tmp = fs.lookup("/tmp").unwrap();
+    fs.write_at(tmp, 0, &[0x5A; 20 * 1024]).unwrap();
+    fs.open_inode(tmp).unwrap();
+    fs.unlink(0, "tmp").unwrap();
+    std::mem::forget(fs);
+    assert_eq!(super_block(&image).orphan_head, tmp);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(!fs.was_clean);
+    assert!(fs.stat(tmp).is_err());
+    assert_eq!(fs.super_block.orphan_head, 0);
+    assert_eq!(
+        (fs.super_block.free_blocks, fs.super_block.free_inodes),
+        free
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn unmount_releases_the_open_orphans() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "tmp").unwrap();
+    let tmp = fs.lookup("/tmp").unwrap();
+    fs.open_inode(tmp).unwrap();
+    fs.unlink(0, "tmp").unwrap();
+    fs.unmount().unwrap();
+
+    let super_block = super_block(&image);
+    assert!(super_block.is_clean());
+    assert_eq!(super_block.orphan_head, 0);
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.stat(tmp).is_err());
+    assert!(fs.check().unwrap().is_clean());
+}
@@ -0,0 +1,177 @@
+//! The tests of the copy-on-write overlay.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::overlay::{MemoryDelta, OverlayBlockDevice};
+use proka_fs::ram::{RamBlockDevice, RomBlockDevice};
+use proka_fs::{BlockDevice, FileSystem};
+
+/// A base of 8 blocks, where each block is filled with its number.
+fn base() -> Vec<u8> {
+    (0..8).flat_map(|block| [block as u8; 1024]).collect()
+}
+
+#[test]
+fn writes_go_to_the_delta() {
+    let base = base();
+    let mut bd = OverlayBlockDevice::new(RomBlockDevice::new(&base[..]));
+    assert_eq!(bd.block_count(), Some(8));
+    bd.write_block(2, 0, &[0xaa; 1024]).unwrap();
+    bd.write_block(3, 100, b"hello").unwrap();
+    assert_eq!(bd.changed_blocks(), 2);
+
+    let mut buf = [0; 1024];
+    bd.read_block(2, 0, &mut buf).unwrap();
+    assert_eq!(buf, [0xaa; 1024]);
+    // The rest of a partly written block comes from the base.
+    bd.read_block(3, 0, &mut buf).unwrap();
+    assert_eq!(buf[..100], [3; 100]);
+    assert_eq!(&buf[100..105], b"hello");
+    assert_eq!(buf[105..], [3; 919]);
+    bd.read_block(4, 0, &mut buf).unwrap();
+    assert_eq!(buf, [4; 1024]);
+}
+
+#[test]
+fn reads_across_both_devices() {
+    let base = base();
+    let mut bd = OverlayBlockDevice::new(RomBlockDevice::new(&base[..]));
+    bd.write_block(5, 0, &[0xaa; 1024]).unwrap();
+
+    // From the end of block 4, through the changed block 5, into block 6.
+    let mut buf = [0; 1224];
+    bd.read_block(4, 924, &mut buf).unwrap();
+    assert_eq!(buf[..100], [4; 100]);
+    assert_eq!(buf[100..1124], [0xaa; 1024]);
+    assert_eq!(buf[1124..], [6; 100]);
+
+    // And the same for a write.
+    bd.write_block(0, 1000, &[0xbb; 48]).unwrap();
+    let mut buf = [0; 2048];
+    bd.read_blocks(0, &mut buf).unwrap();
+    assert_eq!(buf[..1000], [0; 1000]);
+    assert_eq!(buf[1000..1048], [0xbb; 48]);
+    assert_eq!(buf[1048..], [1; 1000]);
+    assert_eq!(bd.changed_blocks(), 3);
+}
+
+#[test]
+fn commit_writes_the_base() {
+    let mut bd = OverlayBlockDevice::new(RamBlockDevice::new(base()));
+    bd.write_block(1, 0, &[0xaa; 1024]).unwrap();
+    bd.write_block(6, 512, &[0xbb; 10]).unwrap();
+    bd.commit().unwrap();
+    assert_eq!(bd.changed_blocks(), 0);
+
+    let (base, _) = bd.into_parts();
+    let memory = base.into_inner();
+    assert_eq!(memory[1024..2048], [0xaa; 1024]);
+    assert_eq!(memory[6 * 1024..6 * 1024 + 512], [6; 512]);
+    assert_eq!(memory[6 * 1024 + 512..6 * 1024 + 522], [0xbb; 10]);
+    let mut expected = self::base();
+    expected[1024..2048].fill(0xaa);
+    expected[6 * 1024 + 512..6 * 1024 + 522].fill(0xbb);
+    assert_eq!(memory, expected);
+}
+
+#[test]
+fn rollback_restores_the_base() {
+    let base = base();
+    let mut bd = OverlayBlockDevice::new(RomBlockDevice::new(&base[..]));
+    bd.write_block(1, 0, &[0xaa; 1024]).unwrap();
+    bd.write_block(7, 0, &[0xbb; 1024]).unwrap();
+    bd.rollback().unwrap();
+    assert_eq!(bd.changed_blocks(), 0);
+
+    let mut buf = vec![0; 8 * 1024];
+    bd.read_blocks(0, &mut buf).unwrap();
+    assert_eq!(buf, base);
+
+    // A partial write after the rollback copies the base, not the old delta.
+    bd.write_block(1, 0, b"x").unwrap();
+    let mut block = [0; 1024];
+    bd.read_block(1, 0, &mut block).unwrap();
+    assert_eq!(block[0], b'x');
+    assert_eq!(block[1..], [1; 1023]);
+}
+
+#[test]
+fn discard_reverts_to_the_base() {
+    let base = base();
+    let mut bd = OverlayBlockDevice::new(RomBlockDevice::new(&base[..]));
+    bd.write_block(2, 0, &[0xaa; 3072]).unwrap();
+    bd.discard(3..4).unwrap();
+    bd.discard(6..8).unwrap();
+    assert_eq!(bd.changed_blocks(), 2);
+
+    let mut buf = [0; 3072];
+    bd.read_blocks(2, &mut buf).unwrap();
+    assert_eq!(buf[..1024], [0xaa; 1024]);
+    assert_eq!(buf[1024..2048], [3; 1024]);
+    assert_eq!(buf[2048..], [0xaa; 1024]);
+}
+
+#[test]
+fn sidecar_delta() {
+    let base = base();
+    let delta = RamBlockDevice::new(vec![0u8; 8 * 1024]);
+    let mut bd = OverlayBlockDevice::with_delta(RomBlockDevice::new(&base[..]), delta);
+    bd.write_block(4, 10, b"hello").unwrap();
+
+    let (_, delta) = bd.into_parts();
+    let memory = delta.into_inner();
+    assert_eq!(memory[4 * 1024..4 * 1024 + 10], [4; 10]);
+    assert_eq!(&memory[4 * 1024 + 10..4 * 1024 + 15], b"hello");
+    assert_eq!(memory[..4 * 1024], [0; 4 * 1024]);
+}
+
+#[test]
+fn memory_delta_is_sparse() {
+    let mut delta = MemoryDelta::new();
+    let mut buf = [1; 2048];
+    delta.read_blocks(100, &mut buf).unwrap();
+    assert_eq!(buf, [0; 2048]);
+
+    delta.write_block(100, 1020, &[7; 8]).unwrap();
+    delta.read_blocks(100, &mut buf).unwrap();
+    assert_eq!(buf[..1020], [0; 1020]);
+    assert_eq!(buf[1020..1028], [7; 8]);
+    assert_eq!(buf[1028..], [0; 1020]);
+
+    delta.discard(100..101).unwrap();
+    delta.read_blocks(100, &mut buf).unwrap();
+    assert_eq!(buf[..1024], [0; 1024]);
+    assert_eq!(buf[1024..1028], [7; 4]);
+}
+
+#[test]
+fn file_system_over_a_pristine_image() {
+    let mut image = fresh_image();
+    let pristine = image.clone();
+    let mut bd = OverlayBlockDevice::new(RamBlockDevice::new(&mut image[..]));
+    let mut fs = FileSystem::mount(&mut bd).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, b"hello").unwrap();
+    fs.unmount().unwrap();
+    assert!(bd.changed_blocks() > 0);
+
+    // Nothing reached the base before the commit.
+    bd.rollback().unwrap();
+    let mut fs = FileSystem::mount(&mut bd).unwrap();
+    assert!(fs.lookup("/file").is_err());
+    fs.mkfile(0, "file").unwrap();
+    fs.unmount().unwrap();
+    let (base, _) = bd.into_parts();
+    assert_eq!(base.as_ref(), &pristine[..]);
+
+    let mut bd = OverlayBlockDevice::new(base);
+    let mut fs = FileSystem::mount(&mut bd).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    fs.unmount().unwrap();
+    bd.commit().unwrap();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.lookup("/file").is_ok());
+    assert!(fs.check().unwrap().is_consistent());
+}
@@ -0,0 +1,175 @@
+//! The tests of the MBR and GPT partition tables.
+
+mod common;
+
+use common::{IMAGE_SIZE, fresh_image};
+use proka_fs::FileSystem;
+use proka_fs::crc32::crc32;
+use proka_fs::partition::{
+    PKFS_GPT_TYPE, PKFS_MBR_TYPE, PartitionType, find_pkfs, read_partitions,
+};
+use proka_fs::ram::{RamBlockDevice, RomBlockDevice};
+
+/// The size of a sector.
+const SECTOR: usize = 512;
+
+/// The number of entries of the GPT fixtures, which fill 32 sectors.
+const GPT_ENTRIES: usize = 128;
+
+/// The "Linux filesystem data" type GUID, in its on-disk byte order.
+const LINUX_DATA: [u8; 16] = [
+    0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47, 0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4,
+];
+
+/// Create a disk of 4 MiB with an MBR, holding a ProkaFS partition at 1 MiB
+/// and a Linux one at 2 MiB with a ProkaFS image.
+fn mbr_disk() -> Vec<u8> {
+    let mut disk = vec![0u8; 4 * IMAGE_SIZE];
+    for (i, partition_type) in [PKFS_MBR_TYPE, 0x83].into_iter().enumerate() {
+        let start = (i + 1) * IMAGE_SIZE;
+        let entry = &mut disk[446 + i * 16..462 + i * 16];
+        entry[4] = partition_type;
+        entry[8..12].copy_from_slice(&((start / SECTOR) as u32).to_le_bytes());
+        entry[12..16].copy_from_slice(&((IMAGE_SIZE / SECTOR) as u32).to_le_bytes());
+        disk[start..start + IMAGE_SIZE].copy_from_slice(&fresh_image());
+    }
+    disk[510] = 0x55;
+    disk[511] = 0xAA;
+    disk
+}
+
+/// Write a GPT header, and its CRC.
+fn write_gpt_header(disk: &mut [u8], lba: u64, alternate: u64, entries_lba: u64) {
+    let entries_start = entries_lba as usize * SECTOR;
+    let entries_crc = crc32(&disk[entries_start..entries_start + GPT_ENTRIES * 128]);
+    let last_lba = (disk.len() / SECTOR) as u64 - 1;
+
+    let header = &mut disk[lba as usize * SECTOR..lba as usize * SECTOR + 92];
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+    header[12..16].copy_from_slice(&92u32.to_le_bytes());
+    header[24..32].copy_from_slice(&lba.to_le_bytes());
+    header[32..40].copy_from_slice(&alternate.to_le_bytes());
+    header[40..48].copy_from_slice(&34u64.to_le_bytes());
+    header[48..56].copy_from_slice(&(last_lba - 33).to_le_bytes());
+    header[56..72].fill(0x42);
+    header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+    header[80..84].copy_from_slice(&(GPT_ENTRIES as u32).to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+    let header_crc = crc32(header);
+    header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+}
+
+/// Create a disk of 4 MiB with a GPT and its backup, holding a ProkaFS
+/// partition at 1 MiB and a Linux one at 2 MiB with a ProkaFS image.
+fn gpt_disk() -> Vec<u8> {
+    let mut disk = vec![0u8; 4 * IMAGE_SIZE];
+    let last_lba = (disk.len() / SECTOR) as u64 - 1;
+
+    // The protective MBR covers the whole disk.
+    let entry = &mut disk[446..462];
+    entry[4] = 0xEE;
+    entry[8..12].copy_from_slice(&1u32.to_le_bytes());
+    entry[12..16].copy_from_slice(&(last_lba as u32).to_le_bytes());
+    disk[510] = 0x55;
+    disk[511] = 0xAA;
+
+    // The entries, at the same place in both tables.
+    let backup_entries_lba = last_lba - 32;
+    for entries_lba in [2, backup_entries_lba] {
+        for (i, type_guid) in [PKFS_GPT_TYPE, LINUX_DATA].into_iter().enumerate() {
+            let first = ((i + 1) * IMAGE_SIZE / SECTOR) as u64;
+            let last = first + (IMAGE_SIZE / SECTOR) as u64 - 1;
+            let entry_start = entries_lba as usize * SECTOR + i * 128;
+            let entry = &mut disk[entry_start..entry_start + 128];
+            entry[0..16].copy_from_slice(&type_guid);
+            entry[16..32].fill(i as u8 + 1);
+            entry[32..40].copy_from_slice(&first.to_le_bytes());
+            entry[40..48].copy_from_slice(&last.to_le_bytes());
+        }
+    }
+    for i in 1..3 {
+        let start = i * IMAGE_SIZE;
+        disk[start..start + IMAGE_SIZE].copy_from_slice(&fresh_image());
+    }
+    write_gpt_header(&mut disk, 1, last_lba, 2);
+    write_gpt_header(&mut disk, last_lba, 1, backup_entries_lba);
+    disk
+}
+
+#[test]
+fn mbr_partitions() {
+    let disk = mbr_disk();
+    let partitions = read_partitions(&mut RomBlockDevice::new(&disk[..])).unwrap();
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(partitions[0].number, 1);
+    assert_eq!(
+        partitions[0].partition_type,
+        PartitionType::Mbr(PKFS_MBR_TYPE)
+    );
+    assert_eq!(partitions[0].start, IMAGE_SIZE as u64);
+    assert_eq!(partitions[0].len, IMAGE_SIZE as u64);
+    // Found by its super block.
+    assert_eq!(partitions[1].partition_type, PartitionType::Mbr(0x83));
+    assert!(partitions.iter().all(|partition| partition.is_pkfs));
+
+    let mut fs = FileSystem::mount(find_pkfs(RamBlockDevice::new(disk)).unwrap()).unwrap();
+    assert!(fs.check().unwrap().is_consistent());
+}
+
+#[test]
+fn no_partition_table() {
+    let image = fresh_image();
+    let partitions = read_partitions(&mut RomBlockDevice::new(&image[..])).unwrap();
+    assert!(partitions.is_empty());
+    assert!(find_pkfs(RomBlockDevice::new(&image[..])).is_err());
+}
+
+#[test]
+fn gpt_partitions() {
+    let disk = gpt_disk();
+    let partitions = read_partitions(&mut RomBlockDevice::new(&disk[..])).unwrap();
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(
+        partitions[0].partition_type,
+        PartitionType::Gpt(PKFS_GPT_TYPE)
+    );
+    assert_eq!(partitions[0].start, IMAGE_SIZE as u64);
+    assert_eq!(partitions[0].len, IMAGE_SIZE as u64);
+    assert_eq!(partitions[1].number, 2);
+    assert_eq!(partitions[1].partition_type, PartitionType::Gpt(LINUX_DATA));
+    assert_eq!(partitions[1].start, 2 * IMAGE_SIZE as u64);
+    assert!(partitions.iter().all(|partition| partition.is_pkfs));
+
+    let mut fs = FileSystem::mount(find_pkfs(RamBlockDevice::new(disk)).unwrap()).unwrap();
+    assert!(fs.check().unwrap().is_consistent());
+}
+
+#[test]
+fn gpt_falls_back_to_the_backup() {
+    let expected = read_partitions(&mut RomBlockDevice::new(&gpt_disk()[..])).unwrap();
+
+    // A broken primary header.
+    let mut disk = gpt_disk();
+    disk[SECTOR + 56] ^= 1;
+    let partitions = read_partitions(&mut RomBlockDevice::new(&disk[..])).unwrap();
+    assert_eq!(partitions, expected);
+
+    // Broken primary entries.
+    let mut disk = gpt_disk();
+    disk[2 * SECTOR + 40] ^= 1;
+    let partitions = read_partitions(&mut RomBlockDevice::new(&disk[..])).unwrap();
+    assert_eq!(partitions, expected);
+
+    // Both tables broken.
+    let last = disk.len() - SECTOR;
+    disk[last + 56] ^= 1;
+    let partitions = read_partitions(&mut RomBlockDevice::new(&disk[..]));
+    assert_eq!(partitions, Err("Invalid GPT partition entries"));
+    let mut disk = gpt_disk();
+    disk[SECTOR] = b'X';
+    disk[last] = b'X';
+    let partitions = read_partitions(&mut RomBlockDevice::new(&disk[..]));
+    assert_eq!(partitions, Err("Invalid GPT header"));
+}
@@ -0,0 +1,96 @@
+//! The tests of the block devices backed by memory.
+
+mod common;
+
+use common::{IMAGE_SIZE, fresh_image};
+use proka_fs::ram::{RamBlockDevice, RomBlockDevice};
+use proka_fs::{BlockDevice, FileSystem};
+
+#[test]
+fn ram_over_a_vector() {
+    let mut bd = RamBlockDevice::new(vec![0u8; 4096]);
+    assert_eq!(bd.block_count(), Some(4));
+    assert_eq!(bd.block_size(), 1024);
+
+    bd.write_block(1, 1000, b"hello").unwrap();
+    let mut buf = [0; 5];
+    bd.read_block(1, 1000, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    assert_eq!(&bd.as_ref()[2024..2029], b"hello");
+    assert_eq!(&bd.into_inner()[2024..2029], b"hello");
+}
+
+#[test]
+fn ram_over_a_buffer() {
+    let mut memory = [0u8; 4096];
+    let mut bd = RamBlockDevice::new(&mut memory[..]);
+    bd.write_blocks(2, &[7; 2048]).unwrap();
+    bd.discard(3..4).unwrap();
+    assert_eq!(memory[..2048], [0; 2048]);
+    assert_eq!(memory[2048..3072], [7; 1024]);
+    assert_eq!(memory[3072..], [0; 1024]);
+}
+
+#[test]
+fn ram_accesses_are_bounded() {
+    let mut bd = RamBlockDevice::new(vec![0u8; 4096]);
+    let mut buf = [0; 16];
+    assert_eq!(bd.read_block(4, 0, &mut buf), Err("Block out of range"));
+    assert_eq!(bd.read_block(3, 1020, &mut buf), Err("Block out of range"));
+    assert_eq!(bd.write_block(4, 0, &buf), Err("Block out of range"));
+    assert_eq!(
+        bd.write_block(u32::MAX, u32::MAX, &buf),
+        Err("Block out of range")
+    );
+    assert_eq!(bd.discard(3..5), Err("Block out of range"));
+    assert_eq!(bd.into_inner(), vec![0; 4096]);
+}
+
+#[test]
+fn ram_holds_a_file_system() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, b"hello").unwrap();
+    fs.unmount().unwrap();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    let mut buf = [0; 5];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+#[test]
+fn rom_is_read_only() {
+    let mut bd = RomBlockDevice::new(vec![7u8; 4096]);
+    assert_eq!(bd.block_count(), Some(4));
+    let mut buf = [0; 16];
+    bd.read_block(3, 1008, &mut buf).unwrap();
+    assert_eq!(buf, [7; 16]);
+    assert_eq!(bd.read_block(3, 1009, &mut buf), Err("Block out of range"));
+    assert_eq!(bd.write_block(0, 0, &buf), Err("Read-only device"));
+}
+
+#[test]
+fn rom_mounts_an_embedded_image() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkdir(0, "sbin").unwrap();
+    let sbin = fs.lookup("/sbin").unwrap();
+    fs.mkfile(sbin, "init").unwrap();
+    let init = fs.lookup("/sbin/init").unwrap();
+    fs.write_at(init, 0, b"#!init").unwrap();
+    // Like an image embedded with `include_bytes!`.
+    fs.unmount().unwrap();
+    let image: &'static [u8] = image.leak();
+    assert_eq!(image.len(), IMAGE_SIZE);
+
+    let mut fs = FileSystem::mount_read_only(RomBlockDevice::new(image)).unwrap();
+    let init = fs.lookup("/sbin/init").unwrap();
+    let mut buf = [0; 6];
+    fs.read_at(init, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"#!init");
+    assert!(fs.mkfile(0, "file").is_err());
+    assert!(fs.unmount().is_ok());
+}
@@ -0,0 +1,107 @@
+//! The tests of the block device window.
+
+mod common;
+
+use common::{IMAGE_SIZE, format_options};
+use proka_fs::mkfs::format;
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::range::RangeBlockDevice;
+use proka_fs::{BlockDevice, FileSystem};
+
+#[test]
+fn window_bounds() {
+    let bd = RamBlockDevice::new(vec![0u8; 8192]);
+    let window = RangeBlockDevice::new(bd, 1024, 4096).unwrap();
+    assert_eq!(window.start(), 1024);
+    assert_eq!(window.len(), 4096);
+    assert!(!window.is_empty());
+
+    let bd = RamBlockDevice::new(vec![0u8; 8192]);
+    let window = RangeBlockDevice::new(bd, 4096, 4097);
+    assert_eq!(window.err(), Some("Range out of device"));
+    let bd = RamBlockDevice::new(vec![0u8; 8192]);
+    let window = RangeBlockDevice::new(bd, u64::MAX, 1);
+    assert_eq!(window.err(), Some("Range out of device"));
+}
+
+#[test]
+fn accesses_stay_inside() {
+    let bd = RamBlockDevice::new(vec![0u8; 8192]);
+    let mut window = RangeBlockDevice::new(bd, 2048, 4096).unwrap();
+    assert_eq!(window.block_count(), Some(4));
+
+    window.write_block(0, 0, &[1; 1024]).unwrap();
+    window.write_blocks(2, &[3; 2048]).unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(window.write_block(4, 0, &buf), Err("Block out of range"));
+    assert_eq!(
+        window.read_block(3, 1020, &mut buf),
+        Err("Block out of range")
+    );
+    assert_eq!(
+        window.write_blocks(3, &[0; 2048]),
+        Err("Block out of range")
+    );
+    assert_eq!(window.discard(3..5), Err("Block out of range"));
+
+    window.discard(3..4).unwrap();
+    let memory = window.into_inner().into_inner();
+    assert_eq!(memory[..2048], [0; 2048]);
+    assert_eq!(memory[2048..3072], [1; 1024]);
+    assert_eq!(memory[3072..4096], [0; 1024]);
+    assert_eq!(memory[4096..5120], [3; 1024]);
+    assert_eq!(memory[5120..], [0; 3072]);
+}
+
+#[test]
+fn unaligned_window() {
+    let bd = RamBlockDevice::new(vec![0u8; 8192]);
+    let mut window = RangeBlockDevice::new(bd, 512, 4096).unwrap();
+    window.write_blocks(0, &[5; 2048]).unwrap();
+    window.write_block(3, 1023, &[6]).unwrap();
+    let mut buf = [0; 2048];
+    window.read_blocks(0, &mut buf).unwrap();
+    assert_eq!(buf, [5; 2048]);
+    // Not a whole block of the wrapped device, so nothing is discarded.
+    window.discard(0..1).unwrap();
+
+    let memory = window.into_inner().into_inner();
+    assert_eq!(memory[..512], [0; 512]);
+    assert_eq!(memory[512..2560], [5; 2048]);
+    assert_eq!(memory[4607], 6);
+    assert_eq!(memory[4608..], [0; 3584]);
+}
+
+#[test]
+fn file_system_inside_a_disk() {
+    // A partition at 1 MiB, between other data.
+    let start = 1024 * 1024;
+    let mut disk = vec![0xaau8; 3 * IMAGE_SIZE];
+    let mut window =
+        RangeBlockDevice::new(RamBlockDevice::new(&mut disk[..]), start, IMAGE_SIZE as u64)
+            .unwrap();
+    format(&mut window, IMAGE_SIZE as u64, &format_options()).unwrap();
+
+    let mut fs = FileSystem::mount(window).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, &[7; 5000]).unwrap();
+    fs.unmount().unwrap();
+
+    let start = start as usize;
+    assert!(disk[..start].iter().all(|&byte| byte == 0xaa));
+    assert!(disk[start + IMAGE_SIZE..].iter().all(|&byte| byte == 0xaa));
+
+    let window = RangeBlockDevice::new(
+        RamBlockDevice::new(&mut disk[..]),
+        start as u64,
+        IMAGE_SIZE as u64,
+    )
+    .unwrap();
+    let mut fs = FileSystem::mount(window).unwrap();
+    assert!(fs.check().unwrap().is_consistent());
+    let file = fs.lookup("/file").unwrap();
+    let mut buf = vec![0; 5000];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(buf, vec![7; 5000]);
+}
@@ -0,0 +1,97 @@
+//! The tests of the read-only mount.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::ram::{RamBlockDevice, RomBlockDevice};
+use proka_fs::{BlockDevice, FileSystem};
+
+/// A block device which panics on any write, to be sure nothing is written.
+struct PanickingBlockDevice<'a>(RomBlockDevice<&'a [u8]>);
+
+impl BlockDevice for PanickingBlockDevice<'_> {
+    fn read_block(
+        &mut self,
+        block_num: u32,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<(), &'static str> {
+        self.0.read_block(block_num, offset, buf)
+    }
+
+    fn write_block(&mut self, block_num: u32, _: u32, _: &[u8]) -> Result<(), &'static str> {
+        panic!("write to block {block_num}");
+    }
+
+    fn discard(&mut self, blocks: std::ops::Range<u32>) -> Result<(), &'static str> {
+        panic!("discard of blocks {blocks:?}");
+    }
+
+    fn block_count(&mut self) -> Option<u32> {
+        self.0.block_count()
+    }
+}
+
+/// Create an image with a file, and an orphan left by a crash.
+fn crashed_image() -> Vec<u8> {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, b"hello").unwrap();
+    fs.mkfile(0, "tmp").unwrap();
+    let tmp = fs.lookup("/tmp").unwrap();
+    fs.open_inode(tmp).unwrap();
+    fs.unlink(0, "tmp").unwrap();
+    fs.sync().unwrap();
+    std::mem::forget(fs);
+    image
+}
+
+#[test]
+fn read_only_mounts_never_write() {
+    let image = crashed_image();
+    let mut fs =
+        FileSystem::mount_read_only(PanickingBlockDevice(RomBlockDevice::new(&image[..]))).unwrap();
+    assert!(fs.read_only);
+    assert!(!fs.was_clean);
+    // The orphan is kept for the next read-write mount.
+    assert_ne!(fs.super_block.orphan_head, 0);
+    assert!(fs.check().unwrap().is_clean());
+
+    let file = fs.lookup("/file").unwrap();
+    let mut buf = [0u8; 5];
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    assert_eq!(fs.mkfile(0, "new"), Err("Read-only file system"));
+    assert_eq!(fs.write_at(file, 0, b"bye"), Err("Read-only file system"));
+    assert_eq!(fs.unlink(0, "file"), Err("Read-only file system"));
+    fs.super_block.set_label("renamed").unwrap();
+    fs.sync().unwrap();
+    fs.unmount().unwrap();
+}
+
+#[test]
+fn writes_never_reach_the_device() {
+    let mut image = fresh_image();
+    let before = image.clone();
+    let mut fs = FileSystem::mount_read_only(RamBlockDevice::new(&mut image[..])).unwrap();
+    // Even when the flag is cleared by mistake.
+    fs.read_only = false;
+    assert_eq!(fs.mkdir(0, "dir"), Err("Read-only device"));
+    assert_eq!(fs.sync(), Err("Read-only device"));
+    drop(fs);
+    assert!(image == before);
+}
+
+#[test]
+fn read_only_devices_need_a_read_only_mount() {
+    let image = fresh_image();
+    assert_eq!(
+        FileSystem::mount(RomBlockDevice::new(&image[..])).err(),
+        Some("Read-only device")
+    );
+    let mut fs = FileSystem::mount_read_only(RomBlockDevice::new(&image[..])).unwrap();
+    assert!(fs.was_clean);
+    assert!(fs.check().unwrap().is_clean());
+}
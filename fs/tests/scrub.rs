@@ -0,0 +1,181 @@
+//! The tests of the checksums of the data and of the scrub.
+
+mod common;
+
+use common::{IMAGE_SIZE, format_options, fresh_image};
+use proka_fs::definition::feature::{self, Features};
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::scrub::Corruption;
+use proka_fs::{ChecksumPolicy, FileSystem, MountOptions};
+
+/// The content of the file, which is easy to find in the image.
+const CONTENT: &[u8; 16] = b"scrub me please!";
+
+/// Create an image with the `data_csum` feature.
+fn data_csum_image() -> Vec<u8> {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: Features::default().with(feature::DATA_CSUM),
+        ..format_options()
+    };
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+    image
+}
+
+/// Add a file in a directory, returning its inode.
+fn populate(image: &mut [u8]) -> u32 {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    fs.mkfile(dir, "file").unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    fs.write_at(file, 0, &CONTENT.repeat(100)).unwrap();
+    fs.unmount().unwrap();
+    file
+}
+
+/// Find the block of the image starting with some bytes.
+fn find_block(image: &[u8], start: &[u8]) -> u32 {
+    image
+        .chunks_exact(1024)
+        .position(|block| block.starts_with(start))
+        .unwrap() as u32
+}
+
+#[test]
+fn data_corruption_is_detected() {
+    let mut image = data_csum_image();
+    let file = populate(&mut image);
+    let block = find_block(&image, CONTENT);
+    image[block as usize * 1024 + 100] ^= 0x10;
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let mut buf = [0u8; 16];
+    assert_eq!(fs.read_at(file, 0, &mut buf), Err("Checksum mismatch"));
+    assert_eq!(fs.corrupted_block, Some(block));
+    // Through the reads of whole blocks too.
+    fs.corrupted_block = None;
+    assert_eq!(
+        fs.read_at(file, 0, &mut [0u8; 2048]),
+        Err("Checksum mismatch")
+    );
+    assert_eq!(fs.corrupted_block, Some(block));
+    assert!(!fs.read_only);
+    fs.unmount().unwrap();
+
+    let mut fs = FileSystem::mount_with(
+        RamBlockDevice::new(&mut image[..]),
+        &MountOptions::default().checksum_policy(ChecksumPolicy::ReadOnly),
+    )
+    .unwrap();
+    fs.read_at(file, 0, &mut buf).unwrap();
+    assert_eq!(&buf, CONTENT);
+    assert!(fs.read_only);
+}
+
+#[test]
+fn data_writes_keep_the_checksums() {
+    let mut image = data_csum_image();
+    let file = populate(&mut image);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    // Partial blocks, whole blocks and a hole.
+    fs.write_at(file, 5, b"changed").unwrap();
+    fs.write_at(file, 4096, &[0x5A; 2048]).unwrap();
+    fs.write_at(file, 10_000, b"end").unwrap();
+    let mut buf = [0u8; 7];
+    fs.read_at(file, 5, &mut buf).unwrap();
+    assert_eq!(&buf, b"changed");
+    assert!(fs.scrub().unwrap().is_clean());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn scrub_reports_the_corrupted_blocks() {
+    let mut image = data_csum_image();
+    let file = populate(&mut image);
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let report = fs.scrub().unwrap();
+    assert!(report.is_clean());
+    assert!(report.blocks_read > 0);
+    fs.unmount().unwrap();
+
+    let block = find_block(&image, CONTENT);
+    image[block as usize * 1024 + 100] ^= 0x10;
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let report = fs.scrub().unwrap();
+    assert_eq!(
+        report.corruptions,
+        [Corruption {
+            block,
+            inode: Some(file),
+            path: Some("/dir/file".to_string()),
+        }]
+    );
+    assert_eq!(
+        report.corruptions[0].to_string(),
+        format!("block {block} of inode {file} (/dir/file) is corrupted")
+    );
+    // The scrub changes nothing.
+    assert!(!fs.read_only);
+}
+
+#[test]
+fn scrub_reports_corrupted_directories() {
+    let mut image = fresh_image();
+    let file = populate(&mut image);
+    let block = find_block(&image, CONTENT);
+    image[block as usize * 1024 + 100] ^= 0x10;
+
+    // The data isn't checked without data_csum, but the directories are.
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.scrub().unwrap().is_clean());
+    let mut buf = [0u8; 16];
+    fs.read_at(file, 0, &mut buf).unwrap();
+
+    let dir = fs.lookup("/dir").unwrap();
+    fs.unmount().unwrap();
+    // The block of "/dir", whose third entry is "file".
+    let dir_block = image
+        .chunks_exact(1024)
+        .position(|block| block[2 * 256 + 4..].starts_with(b"file\0"))
+        .unwrap();
+    image[dir_block * 1024 + 20] ^= 0x10;
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let report = fs.scrub().unwrap();
+    assert_eq!(
+        report.corruptions,
+        [Corruption {
+            block: dir_block as u32,
+            inode: Some(dir),
+            path: Some("/dir".to_string()),
+        }]
+    );
+}
+
+#[test]
+fn data_csum_needs_metadata_csum() {
+    let options = FormatOptions {
+        features: Features::default()
+            .without(feature::METADATA_CSUM)
+            .with(feature::DATA_CSUM),
+        ..format_options()
+    };
+    let mut image = vec![0u8; IMAGE_SIZE];
+    assert_eq!(
+        format(
+            &mut RamBlockDevice::new(&mut image[..]),
+            IMAGE_SIZE as u64,
+            &options
+        )
+        .err(),
+        Some("The data_csum feature needs metadata_csum")
+    );
+}
@@ -0,0 +1,318 @@
+//! The tests of the changes of the space allocated to files.
+
+mod common;
+
+use common::{IMAGE_SIZE, fill_free_blocks, format_options, fresh_image};
+use proka_fs::definition::SuperBlock;
+use proka_fs::definition::feature::{self, Feature, Features};
+use proka_fs::handle::OpenOptions;
+use proka_fs::mkfs::{FormatOptions, format};
+use proka_fs::ram::RamBlockDevice;
+use proka_fs::{BlockDevice, FileSystem, GenericFsData};
+
+/// Create an image with the `data_csum` feature.
+fn data_csum_image() -> Vec<u8> {
+    image_with(&[feature::DATA_CSUM])
+}
+
+/// Create an image with some features on top of the default ones.
+fn image_with(features: &[Feature]) -> Vec<u8> {
+    let mut image = vec![0u8; IMAGE_SIZE];
+    let options = FormatOptions {
+        features: features
+            .iter()
+            .fold(Features::default(), |all, &feature| all.with(feature)),
+        ..format_options()
+    };
+    format(
+        &mut RamBlockDevice::new(&mut image[..]),
+        IMAGE_SIZE as u64,
+        &options,
+    )
+    .unwrap();
+    image
+}
+
+/// Create a file filled with a pattern, returning its inode and content.
+fn filled_file(fs: &mut FileSystem<RamBlockDevice<Vec<u8>>>, len: usize) -> (u32, Vec<u8>) {
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    let data: Vec<u8> = (0..len).map(|i| (i % 251 + 1) as u8).collect();
+    fs.write_at(file, 0, &data).unwrap();
+    (file, data)
+}
+
+/// Read a whole file.
+fn read_file<B: BlockDevice>(fs: &mut FileSystem<B>, file: u32) -> Vec<u8> {
+    let mut buf = vec![0u8; fs.stat(file).unwrap().size as usize];
+    assert_eq!(fs.read_at(file, 0, &mut buf).unwrap(), buf.len());
+    buf
+}
+
+#[test]
+fn truncate_frees_the_tail_and_extends_with_a_hole() {
+    for image in [fresh_image(), data_csum_image()] {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+        let free_blocks = fs.super_block.free_blocks;
+        // Up to the double indirect blocks.
+        let (file, data) = filled_file(&mut fs, 300 * 1024);
+
+        fs.truncate(file, 5000).unwrap();
+        let metadata = fs.stat(file).unwrap();
+        assert_eq!((metadata.size, metadata.blocks), (5000, 5));
+        assert_eq!(fs.super_block.free_blocks, free_blocks - 5);
+        assert!(fs.check().unwrap().is_clean());
+
+        // The old tail isn't visible again.
+        fs.truncate(file, 8000).unwrap();
+        let mut expected = data[..5000].to_vec();
+        expected.resize(8000, 0);
+        assert_eq!(read_file(&mut fs, file), expected);
+        assert_eq!(fs.stat(file).unwrap().blocks, 5);
+
+        fs.truncate(file, 0).unwrap();
+        assert_eq!(fs.stat(file).unwrap().blocks, 0);
+        assert_eq!(fs.super_block.free_blocks, free_blocks);
+        assert!(fs.check().unwrap().is_clean());
+        assert!(fs.scrub().unwrap().is_clean());
+    }
+}
+
+#[test]
+fn truncate_refuses_directories_and_huge_lengths() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    assert_eq!(fs.truncate(dir, 0), Err("Is a directory"));
+    assert_eq!(fs.fallocate(dir, 0, 1), Err("Is a directory"));
+    assert_eq!(fs.punch_hole(dir, 0, 1), Err("Is a directory"));
+
+    let (file, _) = filled_file(&mut fs, 10);
+    assert_eq!(fs.truncate(file, u64::MAX), Err("File too large"));
+    assert_eq!(fs.fallocate(file, u64::MAX, 1), Err("File too large"));
+    assert_eq!(fs.stat(file).unwrap().size, 10);
+}
+
+/// Create the images fallocate works differently on: with and without the
+/// unwritten blocks and the data checksums.
+fn fallocate_images() -> [Vec<u8>; 4] {
+    [
+        fresh_image(),
+        data_csum_image(),
+        image_with(&[feature::UNWRITTEN]),
+        image_with(&[feature::UNWRITTEN, feature::DATA_CSUM]),
+    ]
+}
+
+#[test]
+fn fallocate_allocates_contiguous_blocks() {
+    for mut image in fallocate_images() {
+        // The old data of the free blocks must never be read back.
+        fill_free_blocks(&mut image, 0xAB);
+        let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+        let free_blocks = fs.super_block.free_blocks;
+        let features = fs.super_block.features();
+        let (file, data) = filled_file(&mut fs, 1500);
+
+        fs.fallocate(file, 1000, 40 * 1024).unwrap();
+        // The feature is only enabled when formatting.
+        assert_eq!(fs.super_block.features(), features);
+        let metadata = fs.stat(file).unwrap();
+        // 41 data blocks and the indirect one.
+        assert_eq!((metadata.size, metadata.blocks), (1000 + 40 * 1024, 42));
+        assert_eq!(fs.super_block.free_blocks, free_blocks - 42);
+        let mut expected = data.clone();
+        expected.resize(metadata.size as usize, 0);
+        assert_eq!(read_file(&mut fs, file), expected);
+        assert!(fs.check().unwrap().is_clean());
+        assert!(fs.scrub().unwrap().is_clean());
+
+        // A range already allocated changes nothing, and doesn't shrink the file.
+        fs.fallocate(file, 0, 2048).unwrap();
+        let after = fs.stat(file).unwrap();
+        assert_eq!((after.size, after.blocks), (metadata.size, metadata.blocks));
+        assert_eq!(fs.super_block.free_blocks, free_blocks - 42);
+
+        // Not enough space: nothing is allocated.
+        let free_blocks = fs.super_block.free_blocks;
+        let len = (free_blocks as u64 + 1) * 1024;
+        assert_eq!(
+            fs.fallocate(file, metadata.size, len),
+            Err("No free block available")
+        );
+        assert_eq!(fs.super_block.free_blocks, free_blocks);
+        assert_eq!(fs.stat(file).unwrap().size, metadata.size);
+        assert!(fs.check().unwrap().is_clean());
+    }
+}
+
+#[test]
+fn empty_ranges_are_not_allocated() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    let free_blocks = fs.super_block.free_blocks;
+    let (file, _) = filled_file(&mut fs, 10);
+    let metadata = fs.stat(file).unwrap();
+
+    // Neither the block of an unaligned offset, nor a length past the end.
+    fs.fallocate(file, 1100, 0).unwrap();
+    fs.fallocate(file, 5000, 0).unwrap();
+    let after = fs.stat(file).unwrap();
+    assert_eq!((after.size, after.blocks), (metadata.size, metadata.blocks));
+    assert_eq!(fs.super_block.free_blocks, free_blocks - 1);
+}
+
+#[test]
+fn writes_to_fallocated_blocks_zero_the_rest() {
+    for mut image in fallocate_images() {
+        fill_free_blocks(&mut image, 0xAB);
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        fs.mkfile(0, "file").unwrap();
+        let file = fs.lookup("/file").unwrap();
+        // Through the direct, indirect and double indirect blocks.
+        let len = 300 * 1024;
+        fs.fallocate(file, 0, len).unwrap();
+        let blocks = fs.stat(file).unwrap().blocks;
+        let features = fs.super_block.features();
+
+        let mut expected = vec![0u8; len as usize];
+        for offset in [100, 5 * 1024, 20 * 1024 + 1000, 290 * 1024 + 1] {
+            fs.write_at(file, offset, b"inside").unwrap();
+            expected[offset as usize..offset as usize + 6].copy_from_slice(b"inside");
+        }
+        // Whole blocks, in a run of unwritten ones.
+        let whole = [0x5A; 3 * 1024];
+        fs.write_at(file, 280 * 1024, &whole).unwrap();
+        expected[280 * 1024..283 * 1024].copy_from_slice(&whole);
+
+        assert_eq!(fs.stat(file).unwrap().blocks, blocks);
+        assert_eq!(read_file(&mut fs, file), expected);
+        assert!(fs.check().unwrap().is_clean());
+        assert!(fs.scrub().unwrap().is_clean());
+
+        // The data and the flags which are left survive a remount.
+        fs.unmount().unwrap();
+        let super_block = SuperBlock::from_bytes(&image).unwrap();
+        assert_eq!(super_block.features(), features);
+        let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+        assert_eq!(read_file(&mut fs, file), expected);
+
+        // The unwritten blocks are freed like the others.
+        let free_blocks = fs.super_block.free_blocks;
+        fs.punch_hole(file, 10 * 1024, 10 * 1024).unwrap();
+        assert_eq!(fs.super_block.free_blocks, free_blocks + 10);
+        expected[10 * 1024..20 * 1024].fill(0);
+        assert_eq!(read_file(&mut fs, file), expected);
+        // The edge still reads as zeroes once the file grows again.
+        fs.truncate(file, 1000).unwrap();
+        fs.truncate(file, 2048).unwrap();
+        expected.truncate(1000);
+        expected.resize(2048, 0);
+        assert_eq!(read_file(&mut fs, file), expected);
+        fs.truncate(file, 0).unwrap();
+        assert_eq!(fs.stat(file).unwrap().blocks, 0);
+        assert!(fs.check().unwrap().is_clean());
+        assert!(fs.scrub().unwrap().is_clean());
+    }
+}
+
+#[test]
+fn fallocate_prefers_a_contiguous_run() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    // Leave a free block between the blocks of other files.
+    for name in ["a", "b", "c"] {
+        fs.mkfile(0, name).unwrap();
+        let file = fs.lookup(&format!("/{name}")).unwrap();
+        fs.write_at(file, 0, &[1; 1024]).unwrap();
+    }
+    fs.unlink(0, "b").unwrap();
+
+    fs.mkfile(0, "big").unwrap();
+    let file = fs.lookup("/big").unwrap();
+    fs.fallocate(file, 0, 8 * 1024).unwrap();
+    for i in 0..8u64 {
+        fs.write_at(file, i * 1024, format!("block {i}").as_bytes())
+            .unwrap();
+    }
+    assert!(fs.check().unwrap().is_clean());
+    fs.unmount().unwrap();
+
+    let blocks: Vec<usize> = (0..8)
+        .map(|i| {
+            let marker = format!("block {i}");
+            image
+                .chunks_exact(1024)
+                .position(|block| block.starts_with(marker.as_bytes()))
+                .unwrap()
+        })
+        .collect();
+    assert!(blocks.windows(2).all(|pair| pair[1] == pair[0] + 1));
+}
+
+#[test]
+fn punch_hole_frees_whole_blocks_and_zeroes_the_edges() {
+    for image in [fresh_image(), data_csum_image()] {
+        let mut fs = FileSystem::mount(RamBlockDevice::new(image)).unwrap();
+        let (file, data) = filled_file(&mut fs, 20 * 1024);
+        let blocks = fs.stat(file).unwrap().blocks;
+        let free_blocks = fs.super_block.free_blocks;
+
+        // Blocks 3 to 13 are freed, 2 and 14 are partly zeroed.
+        fs.punch_hole(file, 3000, 12 * 1024).unwrap();
+        let mut expected = data.clone();
+        expected[3000..3000 + 12 * 1024].fill(0);
+        assert_eq!(read_file(&mut fs, file), expected);
+        let metadata = fs.stat(file).unwrap();
+        assert_eq!((metadata.size, metadata.blocks), (20 * 1024, blocks - 11));
+        assert_eq!(fs.super_block.free_blocks, free_blocks + 11);
+        assert!(fs.check().unwrap().is_clean());
+        assert!(fs.scrub().unwrap().is_clean());
+
+        // Up to and past the end, the indirect block is freed with its blocks.
+        fs.punch_hole(file, 10 * 1024, u64::MAX).unwrap();
+        expected[10 * 1024..].fill(0);
+        assert_eq!(read_file(&mut fs, file), expected);
+        assert_eq!(fs.stat(file).unwrap().blocks, 3);
+
+        // The holes can be written again.
+        fs.write_at(file, 5000, b"back").unwrap();
+        expected[5000..5004].copy_from_slice(b"back");
+        assert_eq!(read_file(&mut fs, file), expected);
+        assert!(fs.check().unwrap().is_clean());
+        assert!(fs.scrub().unwrap().is_clean());
+    }
+}
+
+#[test]
+fn handles_can_set_the_length() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    let options = OpenOptions::new().write(true).create(true);
+    let mut file = fs.open("/file", &options).unwrap();
+    file.write(&mut fs, b"hello world").unwrap();
+    file.set_len(&mut fs, 5).unwrap();
+    assert_eq!(file.position(), 11);
+    assert_eq!(file.metadata(&mut fs).unwrap().size, 5);
+    file.close(&mut fs).unwrap();
+
+    let file = fs.open("/file", &OpenOptions::new().read(true)).unwrap();
+    assert_eq!(file.set_len(&mut fs, 0), Err("File not open for writing"));
+    file.close(&mut fs).unwrap();
+}
+
+#[test]
+fn read_only_file_systems_refuse_space_changes() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    fs.write_at(file, 0, &[1; 3000]).unwrap();
+    fs.unmount().unwrap();
+    let before = image.clone();
+
+    let mut fs = FileSystem::mount_read_only(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert_eq!(fs.truncate(file, 0), Err("Read-only file system"));
+    assert_eq!(fs.fallocate(file, 0, 8192), Err("Read-only file system"));
+    assert_eq!(fs.punch_hole(file, 0, 3000), Err("Read-only file system"));
+    fs.unmount().unwrap();
+    assert!(image == before);
+}
@@ -0,0 +1,82 @@
+//! The tests of the free space and inode accounting.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::FileSystem;
+use proka_fs::check::Problem;
+use proka_fs::ram::RamBlockDevice;
+
+#[test]
+fn statfs_follows_the_allocations() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    let before = fs.statfs();
+    assert_eq!(before.block_size, 1024);
+    assert_eq!(before.max_name_len, 251);
+    assert_eq!(
+        before.available_blocks,
+        before.free_blocks - fs.super_block.reserved_blocks
+    );
+    assert!(before.free_blocks < before.total_blocks);
+    assert!(before.free_inodes < before.total_inodes);
+
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    fs.mkfile(dir, "file").unwrap();
+    let file = fs.lookup("/dir/file").unwrap();
+    fs.write_at(file, 0, &[7; 3000]).unwrap();
+    let after = fs.statfs();
+    // The directory has a block, the file has three.
+    assert_eq!(after.free_blocks, before.free_blocks - 4);
+    assert_eq!(after.free_inodes, before.free_inodes - 2);
+
+    fs.unlink(dir, "file").unwrap();
+    fs.rmdir(0, "dir").unwrap();
+    assert_eq!(fs.statfs(), before);
+}
+
+#[test]
+fn stale_counters_are_rebuilt() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let expected = fs.statfs();
+    assert!(!fs.rebuild_counters().unwrap());
+
+    fs.super_block.free_blocks = 3;
+    fs.super_block.free_inodes = 0;
+    assert!(fs.rebuild_counters().unwrap());
+    assert_eq!(fs.statfs(), expected);
+
+    // The counters written on sync are wrong, the mount recounts them.
+    fs.super_block.free_blocks += 10;
+    fs.sync().unwrap();
+    std::mem::forget(fs);
+    let fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert_eq!(fs.statfs(), expected);
+}
+
+#[test]
+fn clean_counters_are_trusted_and_checked() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    let expected = fs.statfs();
+    fs.super_block.free_inodes += 2;
+    fs.unmount().unwrap();
+
+    // The mount of a clean file system doesn't recount, the check reports it.
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert!(fs.was_clean);
+    assert_eq!(fs.statfs().free_inodes, expected.free_inodes + 2);
+    let report = fs.check().unwrap();
+    assert_eq!(
+        report.problems,
+        [Problem::WrongFreeInodes {
+            stored: expected.free_inodes + 2,
+            actual: expected.free_inodes,
+        }]
+    );
+    assert!(report.is_consistent());
+    assert!(fs.rebuild_counters().unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
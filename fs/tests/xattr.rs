@@ -0,0 +1,84 @@
+//! The tests of the extended attributes.
+
+mod common;
+
+use common::fresh_image;
+use proka_fs::FileSystem;
+use proka_fs::ram::RamBlockDevice;
+
+#[test]
+fn attributes_are_set_listed_and_removed() {
+    let mut image = fresh_image();
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    fs.mkfile(0, "bin").unwrap();
+    let file = fs.lookup("/bin").unwrap();
+    fs.setxattr(file, "user.package", b"coreutils").unwrap();
+    fs.setxattr(file, "security.capability", &[1, 2, 3])
+        .unwrap();
+    // Setting it again replaces the value.
+    fs.setxattr(file, "user.package", b"busybox").unwrap();
+    fs.unmount().unwrap();
+
+    let mut fs = FileSystem::mount(RamBlockDevice::new(&mut image[..])).unwrap();
+    assert_eq!(fs.getxattr(file, "user.package").unwrap(), b"busybox");
+    assert_eq!(
+        fs.listxattr(file).unwrap(),
+        ["user.package", "security.capability"]
+    );
+    // The namespaces are apart.
+    assert_eq!(
+        fs.getxattr(file, "trusted.package"),
+        Err("Attribute not found")
+    );
+
+    fs.removexattr(file, "user.package").unwrap();
+    assert_eq!(fs.listxattr(file).unwrap(), ["security.capability"]);
+    assert_eq!(
+        fs.removexattr(file, "user.package"),
+        Err("Attribute not found")
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn large_attributes_use_a_block() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    fs.mkdir(0, "dir").unwrap();
+    let dir = fs.lookup("/dir").unwrap();
+    let free_blocks = fs.statfs().free_blocks;
+
+    // Too large to be inline.
+    let label = vec![b'x'; 200];
+    fs.setxattr(dir, "security.selinux", &label).unwrap();
+    assert_eq!(fs.statfs().free_blocks, free_blocks - 1);
+    fs.setxattr(dir, "user.small", b"inline").unwrap();
+    assert_eq!(fs.getxattr(dir, "security.selinux").unwrap(), label);
+    assert_eq!(fs.getxattr(dir, "user.small").unwrap(), b"inline");
+    assert!(fs.check().unwrap().is_clean());
+
+    // The block is freed with the last attribute in it.
+    fs.removexattr(dir, "security.selinux").unwrap();
+    assert_eq!(fs.statfs().free_blocks, free_blocks);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn invalid_attributes_are_refused() {
+    let mut fs = FileSystem::mount(RamBlockDevice::new(fresh_image())).unwrap();
+    fs.mkfile(0, "file").unwrap();
+    let file = fs.lookup("/file").unwrap();
+    assert_eq!(
+        fs.setxattr(file, "os2.name", b""),
+        Err("Unsupported namespace")
+    );
+    assert_eq!(
+        fs.setxattr(file, "user.", b""),
+        Err("Invalid attribute name")
+    );
+    assert_eq!(
+        fs.setxattr(file, "user.big", &[0; 2048]),
+        Err("Attribute too large")
+    );
+    assert_eq!(fs.setxattr(1000, "user.name", b""), Err("Inode not found"));
+    assert!(fs.listxattr(file).unwrap().is_empty());
+}
@@ -0,0 +1,79 @@
+//! The tool to check the proka file system.
+use clap::Parser;
+use colored::Colorize;
+use pkfs_utils::open_partition;
+use proka_fs::{ChecksumPolicy, FileSystem, MountOptions};
+
+// Define CLI args
+#[derive(Parser)]
+#[command(about = "The ProkaFS checker")]
+struct Args {
+    /// The path to the file to check.
+    #[arg(required = true)]
+    path: String,
+
+    /// The number of the partition to check, from the MBR or GPT partition table of the file.
+    #[arg(long)]
+    partition: Option<u32>,
+}
+
+fn main() {
+    let result = || {
+        println!("ckpkfs {}", "v0.1.0".cyan().bold());
+
+        /* Prework: Open the file system */
+        let args = Args::parse();
+        let bd = open_partition(&args.path, args.partition, 0, None)?;
+        // Nothing is written, and the corrupted metadata is reported by the check
+        // instead of failing the mount.
+        let mut fs = FileSystem::mount_read_only_with(
+            bd,
+            &MountOptions::default().checksum_policy(ChecksumPolicy::ReadOnly),
+        )?;
+        if let Some(block) = fs.backup_used {
+            println!(
+                "ckpkfs: [WARN] The super block is invalid, using its backup at block {}",
+                block
+            );
+        }
+        if !fs.was_clean {
+            println!("ckpkfs: [WARN] The file system was not cleanly unmounted");
+        }
+        let super_block = &fs.super_block;
+        println!(
+            "ckpkfs: [INFO] UUID {}, label \"{}\", created by \"{}\"",
+            super_block.uuid,
+            super_block.label(),
+            super_block.creator()
+        );
+
+        /* Check the file system */
+        println!("ckpkfs: [INFO] Checking the file system...");
+        let report = fs.check()?;
+        for problem in &report.problems {
+            if problem.is_error() {
+                println!("ckpkfs: [ERROR] {}", problem);
+            } else {
+                println!("ckpkfs: [WARN] {}", problem);
+            }
+        }
+
+        let errors = report.errors().count();
+        println!(
+            "ckpkfs: [INFO] {} errors, {} warnings.",
+            errors,
+            report.problems.len() - errors
+        );
+        if !report.is_consistent() {
+            return Err("The file system is corrupted".to_string());
+        }
+        println!("ckpkfs: [INFO] Done.");
+        Ok::<(), String>(())
+    };
+
+    if let Err(e) = result() {
+        eprintln!("ckpkfs: [ERROR] {}", e);
+        eprintln!("ckpkfs: [ERROR] Terminated.");
+        std::process::exit(1);
+    }
+}
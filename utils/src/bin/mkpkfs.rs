@@ -1,12 +1,12 @@
 //! The tool to create the proka file system.
 use clap::Parser;
 use colored::Colorize;
-use proka_fs::bitmap::BlockBitmap;
-use proka_fs::definition::{DirEntry, Inode, SuperBlock};
-use proka_fs::{
-    Bitmap, BlockDevice, FileBlockDevice, GenericFsData, convert_name, get_device_size,
-    init_block_device,
-};
+use pkfs_utils::{open_partition, populate};
+use proka_fs::FileSystem;
+use proka_fs::definition::Uuid;
+use proka_fs::definition::feature::{self, Features};
+use proka_fs::mkfs::{FormatOptions, format};
+use std::path::PathBuf;
 
 // Define CLI args
 #[derive(Parser)]
@@ -15,6 +15,36 @@ struct Args {
     /// The path to the file to create.
     #[arg(required = true)]
     path: String,
+
+    /// The byte offset of the partition inside the file.
+    #[arg(long, default_value_t = 0, conflicts_with = "partition")]
+    offset: u64,
+
+    /// The size of the partition in bytes (default: up to the end of the file).
+    #[arg(long, conflicts_with = "partition")]
+    size: Option<u64>,
+
+    /// The number of the partition to format, from the MBR or GPT partition table of the file.
+    #[arg(long)]
+    partition: Option<u32>,
+
+    /// The features to enable, separated by commas, or to disable with a `^` prefix
+    /// (e.g. `unwritten,^xattr`).
+    #[arg(short = 'O', long)]
+    features: Option<String>,
+
+    /// The label of the file system, up to 32 bytes.
+    #[arg(short = 'L', long, default_value = "")]
+    label: String,
+
+    /// The UUID of the file system (default: a random one).
+    #[arg(short = 'U', long)]
+    uuid: Option<Uuid>,
+
+    /// A directory to copy into the new file system, with its subdirectories, files,
+    /// FIFOs and sockets.
+    #[arg(short = 'd', long)]
+    root: Option<PathBuf>,
 }
 
 fn main() {
@@ -35,98 +65,70 @@ fn main() {
         /* Prework: Initialize the program */
         // Parse the CLI args.
         let args = Args::parse();
-        // Open the file.
-        let mut bd = init_block_device(&args.path)?;
-        /* Stage 1: Initialize the super block */
-        println!("mkpkfs: [INFO] Initialize the super block...");
-        let mut super_block = SuperBlock::new(get_device_size(&args.path)?);
-        sync(&mut bd, &mut super_block)?;
-        let data_start_block = 65792; // 65536 + 256
-
-        // Check is the size is > 64MB
-        if get_device_size(&args.path)? < 64 * 1024 * 1024 {
-            return Err("The partition size must be larger than 64MB".to_string());
-        }
-
-        /* Stage 2: Initialize the root inode */
-        println!("mkpkfs: [INFO] Initialize the root inode...");
-        let root_inode = Inode {
-            is_used: true,
-            inode_id: 0,
-            file_type: proka_fs::definition::FileType::Directory,
-            head_block: data_start_block,
-            file_length: 2 * core::mem::size_of::<DirEntry>() as u64,
-            _reserved: [0; 7],
+        // Open the file, and select the partition inside it.
+        let mut bd = open_partition(&args.path, args.partition, args.offset, args.size)?;
+        let partition_size = bd.len();
+        let features = match &args.features {
+            Some(list) => Features::default().parse(list)?,
+            None => Features::default(),
         };
-        bd.write_block(1, 0, root_inode.as_bytes())?;
-        sync(&mut bd, &mut super_block)?;
-
-        /* Stage 3: Init the block bitmap */
-        println!("mkpkfs: [INFO] Initialize the block bitmap...");
-
-        // 3.1: Initialize the block bitmap
-        // This bitmap is 0 for all, but except 2 places:
-        //
-        // 1. Super Block (const, 0)
-        // 2. Block bitmap itself (From `total_block - size_of::<BlockBitmap>()` to `total_block`)
-        let mut block_bitmap: Vec<u8> = Vec::new();
 
-        // 3.2: Get the bitmap start block and total block
-        let bitmap_start_block = super_block.bitmap_start_block;
-        let total_block = super_block.total_block;
+        /* Create the file system */
+        println!("mkpkfs: [INFO] Creating the file system...");
+        let options = FormatOptions {
+            time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            features,
+            label: args.label.clone(),
+            uuid: args.uuid,
+            creator: concat!("mkpkfs ", env!("CARGO_PKG_VERSION")),
+            ..FormatOptions::default()
+        };
+        let super_block = format(&mut bd, partition_size, &options)?;
 
-        for _ in 0..total_block {
-            block_bitmap.push(0);   // Extend vec storage
+        println!(
+            "mkpkfs: [INFO] {} blocks of {} bytes, {} inodes, {} data blocks",
+            super_block.total_block,
+            super_block.block_size,
+            super_block.inode_count(),
+            super_block.data_block_count()
+        );
+        println!(
+            "mkpkfs: [INFO] UUID {}, label \"{}\"",
+            super_block.uuid,
+            super_block.label()
+        );
+        let backup_blocks = super_block.backup_blocks();
+        if !backup_blocks.is_empty() {
+            let blocks: Vec<String> = backup_blocks.iter().map(u32::to_string).collect();
+            println!(
+                "mkpkfs: [INFO] Super block backups at blocks {}",
+                blocks.join(", ")
+            );
         }
-
-        // 3.3: Set the index 0 to 1 (Superblock used)
-        block_bitmap.set(0, true);
-
-        // 3.4: Set each bitmap block's block to 1
-        for i in bitmap_start_block..total_block {
-            block_bitmap.set(i as usize, true);
+        let names: Vec<&str> = feature::ALL
+            .iter()
+            .filter(|feature| features.contains(**feature))
+            .map(|feature| feature.name)
+            .collect();
+        if names.is_empty() {
+            println!("mkpkfs: [INFO] Features: none");
+        } else {
+            println!("mkpkfs: [INFO] Features: {}", names.join(", "));
         }
 
-        // 3.5: Convert to &mut [u8]
-        let block_bitmap = BlockBitmap::new(&mut block_bitmap);
-
-
-        // 3.6: Write it to the block device
-        bd.write_block(bitmap_start_block, 0, block_bitmap.as_bytes())?;
-
-        /* Stage 4: Initialize the root directory's basic information */
-        // In this stage, we will create the root directory's basic information.
-        // There are 2 dir entry we MUST define:
-        // - "."
-        // - ".."
-        // These entries are pointed at the same directory: root.
-        println!("mkpkfs: [INFO] Initialize the root directory's basic information...");
-
-        // 4.1: Define the name of the "." and ".." entries.
-        let name_dot = convert_name(b".");
-        let name_parent = convert_name(b"..");
-
-        // 4.2: Define the dir entry of the "." and ".." entries.
-        let entry_dot = DirEntry {
-            inode: 0,
-            name: name_dot,
-        };
-        let entry_parent = DirEntry {
-            inode: 0,
-            name: name_parent,
-        };
-
-        // 4.3: Write the "." and ".." entries to the root directory.
-        //
-        // # Note:
-        // - The data block starts at block 1024, which is a constant currently.
-        bd.write_block(data_start_block, 0, entry_dot.as_bytes())?;
-        bd.write_block(
-            data_start_block,
-            core::mem::size_of::<DirEntry>() as u32,
-            entry_parent.as_bytes(),
-        )?;
-        Ok(())
+        /* Copy the files */
+        if let Some(root) = &args.root {
+            println!("mkpkfs: [INFO] Copying {}...", root.display());
+            let mut fs = FileSystem::mount(bd)?;
+            let count = populate(&mut fs, "/", root)?;
+            fs.unmount()?;
+            println!("mkpkfs: [INFO] {} files copied", count);
+        }
+        println!("mkpkfs: [INFO] Done.");
+        Ok::<(), String>(())
     };
 
     if let Err(e) = result() {
@@ -135,8 +137,3 @@ fn main() {
         std::process::exit(1);
     }
 }
-
-fn sync(bd: &mut FileBlockDevice, superblock: &mut SuperBlock) -> Result<(), String> {
-    bd.write_block(0, 0, superblock.as_bytes())?;
-    Ok(())
-}
@@ -0,0 +1,67 @@
+//! The tool to scrub the proka file system.
+use clap::Parser;
+use colored::Colorize;
+use pkfs_utils::open_partition;
+use proka_fs::{ChecksumPolicy, FileSystem, MountOptions};
+
+// Define CLI args
+#[derive(Parser)]
+#[command(about = "The ProkaFS scrubber")]
+struct Args {
+    /// The path to the file to scrub.
+    #[arg(required = true)]
+    path: String,
+
+    /// The number of the partition to scrub, from the MBR or GPT partition table of the file.
+    #[arg(long)]
+    partition: Option<u32>,
+}
+
+fn main() {
+    let result = || {
+        println!("scrubpkfs {}", "v0.1.0".cyan().bold());
+
+        /* Prework: Open the file system */
+        let args = Args::parse();
+        let bd = open_partition(&args.path, args.partition, 0, None)?;
+        // Nothing is written, and the corrupted blocks are reported by the scrub
+        // instead of failing the mount.
+        let mut fs = FileSystem::mount_read_only_with(
+            bd,
+            &MountOptions::default().checksum_policy(ChecksumPolicy::ReadOnly),
+        )?;
+        if let Some(block) = fs.backup_used {
+            println!(
+                "scrubpkfs: [WARN] The super block is invalid, using its backup at block {}",
+                block
+            );
+        }
+
+        /* Scrub the file system */
+        println!("scrubpkfs: [INFO] Reading every allocated block...");
+        let report = fs.scrub()?;
+        for corruption in &report.corruptions {
+            println!(
+                "scrubpkfs: [ERROR] {}, it doesn't match its checksum",
+                corruption
+            );
+        }
+
+        println!(
+            "scrubpkfs: [INFO] {} blocks read, {} corrupted.",
+            report.blocks_read,
+            report.corruptions.len()
+        );
+        if !report.is_clean() {
+            return Err("The file system is corrupted".to_string());
+        }
+        println!("scrubpkfs: [INFO] Done.");
+        Ok::<(), String>(())
+    };
+
+    if let Err(e) = result() {
+        eprintln!("scrubpkfs: [ERROR] {}", e);
+        eprintln!("scrubpkfs: [ERROR] Terminated.");
+        std::process::exit(1);
+    }
+}
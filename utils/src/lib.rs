@@ -0,0 +1,147 @@
+//! The helpers shared by the ProkaFS utilities.
+use proka_fs::partition::read_partitions;
+use proka_fs::range::RangeBlockDevice;
+use proka_fs::{BlockDevice, FileBlockDevice, FileSystem, init_block_device};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+/// The number of bytes copied at once from a host file.
+const COPY_CHUNK: usize = 64 * 1024;
+
+/// Open a disk image, and select the bytes of the file system inside it.
+///
+/// # Parameters
+///
+/// * `path` - The path of the image.
+/// * `partition` - The number of the partition holding the file system, from the
+///   MBR or GPT partition table of the image.
+/// * `offset` - Without `partition`, the byte offset of the file system in the image.
+/// * `size` - Without `partition`, the size of the file system in bytes, or `None`
+///   for up to the end of the image.
+///
+/// # Returns
+///
+/// * `Ok(RangeBlockDevice<FileBlockDevice>)` - The file system, whose size is
+///   [`RangeBlockDevice::len`].
+/// * `Err(String)` - If the image can't be opened, the partition doesn't exist, or
+///   the range goes beyond the end of the image.
+pub fn open_partition(
+    path: &str,
+    partition: Option<u32>,
+    offset: u64,
+    size: Option<u64>,
+) -> Result<RangeBlockDevice<FileBlockDevice>, String> {
+    let mut disk = init_block_device(path)?;
+    let (offset, size) = match partition {
+        Some(number) => {
+            let partition = read_partitions(&mut disk)?
+                .into_iter()
+                .find(|partition| partition.number == number)
+                .ok_or(format!("No partition {} in the partition table", number))?;
+            (partition.start, partition.len)
+        }
+        None => {
+            let disk_size = disk.block_count().unwrap_or(0) as u64 * disk.block_size() as u64;
+            let size = match size {
+                Some(size) => size,
+                None => disk_size
+                    .checked_sub(offset)
+                    .ok_or("The offset is beyond the end of the file")?,
+            };
+            (offset, size)
+        }
+    };
+    Ok(RangeBlockDevice::new(disk, offset, size)?)
+}
+
+/// Copy the content of a host directory into a directory of a file system.
+///
+/// # Parameters
+///
+/// * `fs` - The file system to populate.
+/// * `dir` - The path of the directory to copy into, like `/`.
+/// * `source` - The host directory.
+///
+/// # Returns
+///
+/// * `Ok(usize)` - The number of files created, of every type.
+/// * `Err(String)` - If the host directory can't be read, a name isn't valid
+///   UTF-8, a file has a type the file system can't store, or the file system fails.
+///
+/// # Note
+///
+/// The directories, regular files, FIFOs and sockets are copied, in name order so
+/// the same directory always gives the same image. Anything else, like a symbolic
+/// link or a device, is refused rather than silently left out.
+pub fn populate<B: BlockDevice>(
+    fs: &mut FileSystem<B>,
+    dir: &str,
+    source: &Path,
+) -> Result<usize, String> {
+    let dir_id = fs.lookup(dir).map_err(|e| format!("{}: {}", dir, e))?;
+    let mut entries = std::fs::read_dir(source)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| path_error(source, e))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut count = 0;
+    for entry in entries {
+        let host = entry.path();
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| path_error(&host, "Invalid UTF-8 name"))?;
+        // The type of the entry itself, a symbolic link isn't followed.
+        let file_type = entry.file_type().map_err(|e| path_error(&host, e))?;
+        let created = if file_type.is_dir() {
+            fs.mkdir(dir_id, &name)
+        } else if file_type.is_file() {
+            fs.mkfile(dir_id, &name)
+        } else if file_type.is_fifo() {
+            fs.mkfifo(dir_id, &name)
+        } else if file_type.is_socket() {
+            fs.mksock(dir_id, &name)
+        } else {
+            Err("Unsupported file type")
+        };
+        created.map_err(|e| path_error(&host, e))?;
+        count += 1;
+
+        let path = format!("{}/{}", dir.trim_end_matches('/'), name);
+        if file_type.is_dir() {
+            count += populate(fs, &path, &host)?;
+        } else if file_type.is_file() {
+            let inode_id = fs.lookup(&path).map_err(|e| path_error(&host, e))?;
+            copy_file(fs, inode_id, &host)?;
+        }
+    }
+    Ok(count)
+}
+
+/// Copy the content of a host file into a file.
+fn copy_file<B: BlockDevice>(
+    fs: &mut FileSystem<B>,
+    inode_id: u32,
+    source: &Path,
+) -> Result<(), String> {
+    let mut file = File::open(source).map_err(|e| path_error(source, e))?;
+    let mut buf = vec![0u8; COPY_CHUNK];
+    let mut offset = 0;
+    loop {
+        let len = file.read(&mut buf).map_err(|e| path_error(source, e))?;
+        if len == 0 {
+            return Ok(());
+        }
+        fs.write_at(inode_id, offset, &buf[..len])
+            .map_err(|e| path_error(source, e))?;
+        offset += len as u64;
+    }
+}
+
+/// Prefix an error with the host path it's about.
+fn path_error(path: &Path, e: impl Display) -> String {
+    format!("{}: {}", path.display(), e)
+}
@@ -0,0 +1,105 @@
+//! The tests of the population of new file systems by mkpkfs.
+
+use proka_fs::definition::FileType;
+use proka_fs::{FileSystem, init_block_device};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The size of the test images.
+const IMAGE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Create an empty directory and an image file of [`IMAGE_SIZE`] for a test.
+fn workspace(test: &str) -> (PathBuf, PathBuf) {
+    let base = std::env::temp_dir().join(format!("pkfs-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let root = base.join("root");
+    std::fs::create_dir_all(&root).unwrap();
+    let image = base.join("disk.img");
+    std::fs::File::create(&image)
+        .unwrap()
+        .set_len(IMAGE_SIZE)
+        .unwrap();
+    (root, image)
+}
+
+/// Create a named pipe.
+fn mkfifo(path: &Path) {
+    let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o644) }, 0);
+}
+
+/// Run one of the tools.
+fn run(tool: &str, args: &[&Path]) -> Output {
+    Command::new(tool).args(args).output().unwrap()
+}
+
+/// Get the pattern of bytes of a test file.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+#[test]
+fn directories_are_copied_with_their_fifos_and_sockets() {
+    let (root, image) = workspace("populate");
+    std::fs::create_dir_all(root.join("dir/nested")).unwrap();
+    std::fs::write(root.join("dir/nested/small"), b"hello").unwrap();
+    // Larger than a single copy, and than the direct blocks.
+    std::fs::write(root.join("big"), pattern(200 * 1024)).unwrap();
+    std::fs::write(root.join("empty"), b"").unwrap();
+    mkfifo(&root.join("dir/fifo"));
+    let _listener = UnixListener::bind(root.join("socket")).unwrap();
+
+    let output = run(
+        env!("CARGO_BIN_EXE_mkpkfs"),
+        &[&image, Path::new("--root"), &root],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("7 files copied"));
+    let output = run(env!("CARGO_BIN_EXE_ckpkfs"), &[&image]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("0 errors, 0 warnings."));
+
+    let mut fs = FileSystem::mount(init_block_device(image.to_str().unwrap()).unwrap()).unwrap();
+    // The directories hold an entry of 256 bytes per file, with '.' and '..'.
+    for (path, file_type, size) in [
+        ("/big", FileType::Regular, 200 * 1024),
+        ("/dir", FileType::Directory, 4 * 256),
+        ("/dir/fifo", FileType::Fifo, 0),
+        ("/dir/nested", FileType::Directory, 3 * 256),
+        ("/dir/nested/small", FileType::Regular, 5),
+        ("/empty", FileType::Regular, 0),
+        ("/socket", FileType::Socket, 0),
+    ] {
+        let metadata = fs.stat_path(path).unwrap();
+        assert_eq!(
+            (metadata.file_type, metadata.size),
+            (file_type, size),
+            "{}",
+            path
+        );
+    }
+    let big = fs.lookup("/big").unwrap();
+    let mut buf = vec![0u8; 200 * 1024];
+    assert_eq!(fs.read_at(big, 0, &mut buf), Ok(buf.len()));
+    assert!(buf == pattern(200 * 1024));
+    fs.unmount().unwrap();
+    std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn unsupported_file_types_are_refused() {
+    let (root, image) = workspace("symlink");
+    std::os::unix::fs::symlink("target", root.join("link")).unwrap();
+
+    let output = run(
+        env!("CARGO_BIN_EXE_mkpkfs"),
+        &[&image, Path::new("--root"), &root],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("link: Unsupported file type"), "{}", stderr);
+    std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+}